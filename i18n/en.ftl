@@ -0,0 +1,72 @@
+# Messages shown by hashgood. Each translation is a copy of this file named after its language,
+# such as de.ftl, and only needs to contain the messages which have been translated.
+
+## Results
+
+result = Result:
+result-ok = OK
+result-maybe = MAYBE
+result-similar = SIMILAR ({ $score }%)
+result-fail = FAIL
+
+level-error = (error)
+level-warning = (warning)
+level-note = (note)
+
+## Comparing a digest with a candidate
+
+mismatch-truncated = The candidate looks truncated by { $count } hex chars
+mismatch-first-difference = First difference at byte { $byte }, { $count } of { $total } bytes differ
+mismatch-transposed = The candidate looks like it has two hex chars transposed at position { $position }
+mismatch-reversed = The candidate is the digest with its bytes in reverse order
+mismatch-shifted = The candidate looks shifted by { $count } hex chars from position { $position }, as if some were dropped or repeated
+
+## Checking many files
+
+status-ok = (ok)
+status-failed = (failed)
+status-missing = (missing)
+status-unreadable = (unreadable)
+check-files = { $count } files
+check-listed-stdin = listed in digests on standard input
+check-listed-file = listed in digests file '{ $path }'
+check-summary = { $ok } ok, { $failed } failed, { $missing } missing, { $unreadable } unreadable
+check-skipped = , { $count } skipped
+summary = Summary:
+summary-ok = { $count } OK
+summary-maybe = { $count } MAYBE
+summary-similar = { $count } SIMILAR
+summary-fail = { $count } FAIL
+
+tree-different = (different)
+tree-missing = (missing)
+tree-extra = (extra)
+tree-summary = { $identical } identical, { $different } different, { $missing } missing, { $extra } extra
+
+pieces = pieces
+pieces-summary = { $calculated } pieces calculated, { $expected } expected
+
+## Notes and warnings about a verification
+
+improper-lines = { $count } lines in the check file are improperly formatted and were ignored
+filename-mismatch = The matched hash has filename '{ $filename }', which does not match the input.
+torrent-filename-mismatch = The torrent has filename '{ $filename }', which does not match the input.
+md5-weak = MD5 can easily be forged. Use a stronger algorithm if possible.
+sha1-weak = SHA-1 collisions can be forged. Use a stronger algorithm if possible.
+xxhash-weak = xxHash only detects accidental corruption, not deliberate tampering.
+fuzzy-similarity = Fuzzy hashes measure similarity. Matching fuzzy hashes do not guarantee identical content.
+pieces-failed = { $failed } of { $total } pieces do not match: { $pieces }
+pieces-missing = The input is incomplete: { $missing } of { $total } pieces are missing.
+pieces-extra = The input is longer than the torrent's payload.
+signature-gpg = Check file has a good signature from { $signer }
+signature-minisign = Check file has a good minisign signature: { $comment }
+signature-signify = Check file has a good signify signature from '{ $comment }'
+signature-unchecked = Check file is signed but the signature was not checked. Use --key to verify it.
+
+## Asking for a hash
+
+prompt-hash = Paste expected hash:
+
+## Reading the input
+
+throughput = Read { $bytes } bytes in { $seconds }s ({ $rate } MB/s)
@@ -0,0 +1,61 @@
+use super::calculate::InputData;
+use super::error::HashgoodError;
+use std::path::Path;
+
+/// The type of a function which processes one file from an archive, given its name and contents
+pub type MemberFn<'f> = dyn FnMut(&str, InputData) -> Result<(), HashgoodError> + 'f;
+
+/// Stream each regular file inside a .zip, .tar or .tar.gz archive to the function in turn,
+/// without extracting anything to disk. Members are named by their path inside the archive.
+#[cfg(feature = "archive")]
+pub fn for_each_member(path: &Path, f: &mut MemberFn) -> Result<(), HashgoodError> {
+    use std::fs::File;
+
+    let name = path.to_string_lossy().to_lowercase();
+    let file = File::open(path)
+        .map_err(|e| format!("Unable to open archive '{}': {}", path.to_string_lossy(), e))?;
+    if name.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(file)?;
+        for i in 0..zip.len() {
+            let member = zip.by_index(i)?;
+            if !member.is_file() {
+                continue;
+            }
+            let member_name = member.name().to_owned();
+            f(&member_name, InputData::Stream(Box::new(member)))?;
+        }
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        for_each_tar_member(flate2::read::GzDecoder::new(file), f)?;
+    } else if name.ends_with(".tar") {
+        for_each_tar_member(file, f)?;
+    } else {
+        return Err(format!(
+            "Unrecognised archive type for '{}'. Supported types are .zip, .tar and .tar.gz.",
+            path.to_string_lossy()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "archive")]
+fn for_each_tar_member<R: std::io::Read>(reader: R, f: &mut MemberFn) -> Result<(), HashgoodError> {
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let member_name = entry.path()?.to_string_lossy().to_string();
+        f(&member_name, InputData::Stream(Box::new(entry)))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "archive"))]
+pub fn for_each_member(_path: &Path, _f: &mut MemberFn) -> Result<(), HashgoodError> {
+    Err(
+        "Archives are not supported by this build of hashgood (requires the archive feature)"
+            .into(),
+    )
+}
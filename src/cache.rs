@@ -0,0 +1,270 @@
+use super::checkpoint::{algorithm_name, parse_algorithm};
+use super::Algorithm;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::UNIX_EPOCH;
+
+/// First line of every cache file, identifying the format
+const HEADER: &str = "hashgood cache 1";
+
+/// Identifies one version of a file. If any of these change, the file is hashed again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stamp {
+    size: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    mtime: u128,
+    inode: u64,
+}
+
+impl Stamp {
+    /// The stamp of a regular file as it is now, or None for anything else
+    pub fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(Stamp {
+            size: metadata.len(),
+            mtime: metadata
+                .modified()
+                .ok()?
+                .duration_since(UNIX_EPOCH)
+                .ok()?
+                .as_nanos(),
+            inode,
+        })
+    }
+}
+
+/// Written as `size mtime inode` in cache and checkpoint files
+impl fmt::Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.size, self.mtime, self.inode)
+    }
+}
+
+impl FromStr for Stamp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split(' ').collect::<Vec<_>>()[..] {
+            [size, mtime, inode] => Ok(Stamp {
+                size: size.parse().map_err(|_| ())?,
+                mtime: mtime.parse().map_err(|_| ())?,
+                inode: inode.parse().map_err(|_| ())?,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The stamp of a file when it was hashed and the digests it had then
+type Entry = (Stamp, Vec<(Algorithm, Vec<u8>)>);
+
+/// Digests of files calculated on earlier runs, so that only the files which have changed since
+/// then need to be hashed again
+#[derive(Debug, PartialEq)]
+pub struct DigestCache {
+    /// Where the cache is saved
+    path: PathBuf,
+    /// Each file which has been hashed, by canonical path
+    files: HashMap<PathBuf, Entry>,
+    /// Whether anything has been added since the cache was opened
+    changed: bool,
+}
+
+impl DigestCache {
+    /// Load the cache saved at `path`, or start an empty one if there isn't one yet
+    pub fn open(path: &Path) -> Result<DigestCache, String> {
+        let mut cache = DigestCache {
+            path: path.to_owned(),
+            files: HashMap::new(),
+            changed: false,
+        };
+        if !path.exists() {
+            return Ok(cache);
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read cache '{}': {}", path.display(), e))?;
+        let invalid = || format!("'{}' is not a valid cache", path.display());
+        let mut lines = contents.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid());
+        }
+        // Each line is `size mtime inode alg:digest,alg:digest path`, with the path last since it
+        // may contain spaces
+        for line in lines {
+            let mut fields = line.splitn(5, ' ');
+            let mut next = || fields.next().ok_or_else(invalid);
+            let stamp = Stamp {
+                size: next()?.parse().map_err(|_| invalid())?,
+                mtime: next()?.parse().map_err(|_| invalid())?,
+                inode: next()?.parse().map_err(|_| invalid())?,
+            };
+            let mut digests = vec![];
+            for digest in next()?.split(',') {
+                let (alg, bytes) = digest.split_once(':').ok_or_else(invalid)?;
+                let alg = parse_algorithm(alg).ok_or_else(invalid)?;
+                digests.push((alg, hex::decode(bytes).map_err(|_| invalid())?));
+            }
+            cache.files.insert(PathBuf::from(next()?), (stamp, digests));
+        }
+        Ok(cache)
+    }
+
+    /// The digests of a file, if all of them were calculated when it was in the same state
+    fn get(
+        &self,
+        file: &Path,
+        stamp: Stamp,
+        algorithms: &[Algorithm],
+    ) -> Option<Vec<(Algorithm, Vec<u8>)>> {
+        let (cached_stamp, digests) = self.files.get(file)?;
+        if *cached_stamp != stamp {
+            return None;
+        }
+        algorithms
+            .iter()
+            .map(|alg| digests.iter().find(|(a, _)| a == alg).cloned())
+            .collect()
+    }
+
+    /// Remember the digests of a file in the given state, along with any others already known
+    fn insert(&mut self, file: PathBuf, stamp: Stamp, digests: &[(Algorithm, Vec<u8>)]) {
+        let entry = self.files.entry(file).or_insert((stamp, vec![]));
+        if entry.0 != stamp {
+            *entry = (stamp, vec![]);
+        }
+        for (alg, bytes) in digests {
+            entry.1.retain(|(a, _)| a != alg);
+            entry.1.push((*alg, bytes.clone()));
+        }
+        self.changed = true;
+    }
+
+    /// Write the cache to disk if it has changed. The file is replaced atomically so an
+    /// interruption while saving can't corrupt it.
+    pub fn save(&self) -> Result<(), String> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut contents = format!("{}\n", HEADER);
+        for (file, (stamp, digests)) in &self.files {
+            let digests: Vec<String> = digests
+                .iter()
+                .map(|(alg, bytes)| format!("{}:{}", algorithm_name(*alg), hex::encode(bytes)))
+                .collect();
+            contents.push_str(&format!(
+                "{} {} {}\n",
+                stamp,
+                digests.join(","),
+                file.display()
+            ));
+        }
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, contents)
+            .and_then(|_| fs::rename(&temp, &self.path))
+            .map_err(|e| format!("Unable to save cache '{}': {}", self.path.display(), e))
+    }
+}
+
+/// The cache chosen with --cache, if any
+static CACHE: OnceLock<Mutex<DigestCache>> = OnceLock::new();
+
+/// Use the cache saved at `path` for all further files, creating it if necessary
+pub fn set_cache(path: &Path) -> Result<(), String> {
+    let _ = CACHE.set(Mutex::new(DigestCache::open(path)?));
+    Ok(())
+}
+
+fn cache() -> Option<MutexGuard<'static, DigestCache>> {
+    CACHE
+        .get()
+        .map(|cache| cache.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// A file as it was before hashing started, to look up in the cache and add its digests to it
+pub struct CacheKey {
+    file: PathBuf,
+    stamp: Stamp,
+}
+
+impl CacheKey {
+    /// The key for a file on disk, if a cache is in use and the file is a regular one that can be
+    /// cached. Each line of the cache holds one file so a path with a newline can't be stored.
+    pub fn new(path: &Path) -> Option<CacheKey> {
+        CACHE.get()?;
+        let file = fs::canonicalize(path).ok()?;
+        if file.to_str().is_none_or(|f| f.contains('\n')) {
+            return None;
+        }
+        let stamp = Stamp::of(&file)?;
+        Some(CacheKey { file, stamp })
+    }
+
+    /// The digests of the file from an earlier run, if it hasn't changed since
+    pub fn lookup(&self, algorithms: &[Algorithm]) -> Option<Vec<(Algorithm, Vec<u8>)>> {
+        cache()?.get(&self.file, self.stamp, algorithms)
+    }
+
+    /// Remember the digests of the file for next time, unless it changed while it was being hashed
+    pub fn store(self, digests: &[(Algorithm, Vec<u8>)]) {
+        if let Some(mut cache) = cache() {
+            if Stamp::of(&self.file) == Some(self.stamp) {
+                cache.insert(self.file, self.stamp, digests);
+            }
+        }
+    }
+}
+
+/// Save the cache, if one is in use
+pub fn save() -> Result<(), String> {
+    match cache() {
+        Some(cache) => cache.save(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_open() {
+        let path = std::env::temp_dir().join(format!("hashgood-test-{}.cache", std::process::id()));
+        let mut cache = DigestCache::open(&path).unwrap();
+        let stamp = Stamp {
+            size: 12,
+            mtime: 1_600_000_000_123_456_789,
+            inode: 42,
+        };
+        let file = PathBuf::from("/media/clip one.mov");
+        assert_eq!(cache.get(&file, stamp, &[Algorithm::Md5]), None);
+
+        cache.insert(file.clone(), stamp, &[(Algorithm::Md5, vec![1, 2])]);
+        cache.insert(file.clone(), stamp, &[(Algorithm::Sha256, vec![3])]);
+        cache.save().unwrap();
+        let reopened = DigestCache::open(&path).unwrap();
+        assert_eq!(reopened.files, cache.files);
+        assert_eq!(
+            reopened.get(&file, stamp, &[Algorithm::Sha256, Algorithm::Md5]),
+            Some(vec![
+                (Algorithm::Sha256, vec![3]),
+                (Algorithm::Md5, vec![1, 2])
+            ])
+        );
+        assert_eq!(reopened.get(&file, stamp, &[Algorithm::Sha1]), None);
+        let touched = Stamp { mtime: 0, ..stamp };
+        assert_eq!(reopened.get(&file, touched, &[Algorithm::Md5]), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,120 +1,1400 @@
+use super::checkpoint::Checkpoint;
+use super::display;
+use super::error::HashgoodError;
+use super::fuzzy::FuzzyHasher;
+use super::input::as_url;
+use super::progress::Progress;
 use super::Algorithm;
+use hmac::{Hmac, KeyInit, Mac};
+use md5::digest::common::hazmat::{SerializableState, SerializedState};
 use md5::{Digest, Md5};
+use memmap2::Mmap;
 use sha1::Sha1;
-use sha2::Sha256;
+use sha2::{Sha256, Sha384, Sha512};
+use std::cell::Cell;
+#[cfg(target_family = "wasm")]
+use std::cell::RefCell;
+#[cfg(not(target_family = "wasm"))]
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
-use std::sync::mpsc::{channel, Receiver};
-use std::sync::Arc;
-use std::thread;
-use std::thread::JoinHandle;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+#[cfg(not(target_family = "wasm"))]
+use std::sync::mpsc::{sync_channel, SyncSender};
+#[cfg(not(target_family = "wasm"))]
+use std::sync::Mutex;
+use std::sync::{Arc, Once};
+#[cfg(not(target_family = "wasm"))]
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-pub type CalculateResult = Result<Vec<(Algorithm, Vec<u8>)>, Box<dyn Error>>;
+pub type CalculateResult = Result<Digests, HashgoodError>;
+
+/// The digests of an input, along with how much of it was read and how long that took
+#[derive(Debug)]
+pub struct Digests {
+    pub hashes: Vec<(Algorithm, Vec<u8>)>,
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+/// The data to be hashed
+pub enum InputData<'a> {
+    /// Data which is read from a stream in chunks
+    Stream(Box<dyn Read + 'a>),
+    /// A stream from a regular file on fast storage such as an SSD, or from a pipe, which is read in
+    /// larger chunks
+    FastStream(Box<dyn Read + 'a>),
+    /// A file mapped into memory, which can be shared with the digest threads without copying
+    Mapped(Mmap),
+    /// A regular file containing holes, which are hashed as zeros without reading them from disk
+    Sparse(File),
+}
+
+/// A piece of the input shared between all of the digest threads
+#[derive(Clone)]
+struct Chunk {
+    data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+    range: Range<usize>,
+}
+
+impl Chunk {
+    fn bytes(&self) -> &[u8] {
+        &(*self.data).as_ref()[self.range.clone()]
+    }
+}
+
+/// Alignment of the memory that input is read into. Direct I/O needs this to match the block size
+/// of the device, which is at most 4 KiB in practice.
+const BUFFER_ALIGNMENT: usize = 4096;
+
+/// A buffer of input data which is handed back to be read into again once every digest thread has
+/// finished with it, rather than allocating a new one for every chunk
+struct PooledBuffer {
+    /// Allocated with room to spare so that part of it can be aligned
+    data: Vec<u8>,
+    /// The aligned part of `data` which is read into
+    start: usize,
+    len: usize,
+    pool: Sender<Vec<u8>>,
+}
+
+impl PooledBuffer {
+    fn new(len: usize, pool: &Sender<Vec<u8>>, recycled: &Receiver<Vec<u8>>) -> Self {
+        let data = recycled
+            .try_recv()
+            .unwrap_or_else(|_| vec![0; len + BUFFER_ALIGNMENT]);
+        Self {
+            start: data.as_ptr().align_offset(BUFFER_ALIGNMENT),
+            data,
+            len,
+            pool: pool.clone(),
+        }
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[self.start..self.start + self.len]
+    }
+}
+
+impl AsMut<[u8]> for PooledBuffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.data[self.start..self.start + self.len]
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        // Reading has finished if nobody is left to take it
+        let _ = self.pool.send(std::mem::take(&mut self.data));
+    }
+}
+
+/// Instructions sent to each digest thread
+enum Message {
+    /// Add this chunk to the digest
+    Data(Chunk),
+    /// Reply with the serialised state of the digest after all of the data sent so far
+    SaveState(Sender<Option<Vec<u8>>>),
+}
 
 /// For a given path to the input (may be "-" for STDIN), try to obtain a reader for the data within it.
-pub fn get_input_reader(input: &Path) -> Result<Box<dyn Read>, String> {
+pub fn get_input_reader(input: &Path) -> Result<Box<dyn Read>, HashgoodError> {
     if input.to_str() == Some("-") {
         // Special case: standard input
+        grow_stdin_pipe();
         return Ok(Box::new(std::io::stdin()));
     }
+    if let Some(url) = as_url(input) {
+        return get_url_reader(url);
+    }
+    Ok(file_reader(open_file(input)?))
+}
+
+/// Whether standard input is a pipe, such as from `curl ... | hashgood -`
+#[cfg(target_os = "linux")]
+fn is_stdin_pipe() -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata("/proc/self/fd/0").is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_stdin_pipe() -> bool {
+    false
+}
+
+/// How big to make a pipe on standard input. This is the most an unprivileged process can ask for
+/// unless the limit in /proc/sys/fs/pipe-max-size has been changed.
+#[cfg(target_os = "linux")]
+const PIPE_SIZE: usize = 1024 * 1024;
+
+/// A pipe only holds 64 KiB by default, so the program writing to it is stopped every time the
+/// hashers fall behind and each read gets very little. Making it bigger lets both sides keep going
+/// and the data is read in larger chunks. The data still has to be read into memory for hashing,
+/// so moving it around with splice() wouldn't save anything.
+#[cfg(target_os = "linux")]
+fn grow_stdin_pipe() {
+    if !is_stdin_pipe() {
+        return;
+    }
+    // SAFETY: F_SETPIPE_SZ only changes the capacity of the pipe and fails harmlessly
+    let size = unsafe {
+        libc::fcntl(
+            libc::STDIN_FILENO,
+            libc::F_SETPIPE_SZ,
+            PIPE_SIZE as libc::c_int,
+        )
+    };
+    match size {
+        -1 => display::log(2, || {
+            format!(
+                "Unable to resize the pipe on standard input: {}",
+                std::io::Error::last_os_error()
+            )
+        }),
+        size => display::log(2, || format!("Pipe on standard input holds {} bytes", size)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn grow_stdin_pipe() {}
+
+/// Obtain a reader for the input which begins at the given offset. Files and block devices seek
+/// directly to the offset while streams have to read and discard the data before it.
+pub fn get_input_reader_at(input: &Path, offset: u64) -> Result<Box<dyn Read>, HashgoodError> {
+    let too_short = || format!("The input is shorter than the offset {}", offset).into();
+    if input.to_str() == Some("-") || as_url(input).is_some() {
+        let mut reader = get_input_reader(input)?;
+        let skipped = std::io::copy(&mut reader.by_ref().take(offset), &mut std::io::sink())
+            .map_err(|e| HashgoodError::io(format!("Unable to read up to offset {}", offset), e))?;
+        if skipped < offset {
+            return Err(too_short());
+        }
+        return Ok(reader);
+    }
+    let mut file = open_file(input)?;
+    let len = match is_block_device(input) {
+        true => get_block_device_len(input),
+        false => file.metadata().ok().map(|m| m.len()),
+    };
+    if len.is_some_and(|len| len < offset) {
+        return Err(too_short());
+    }
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| HashgoodError::io(format!("Unable to seek to offset {}", offset), e))?;
+    Ok(file_reader(file))
+}
+
+/// Open a path on disk for reading, checking that it is something which can be hashed
+fn open_file(input: &Path) -> Result<File, HashgoodError> {
     if !input.exists() {
-        return Err(format!(
-            "The path '{}' does not exist.",
+        return Err(HashgoodError::NotFound(input.to_owned()));
+    }
+    if input.is_dir() {
+        return Err(HashgoodError::Usage(format!(
+            "The path '{}' is a directory. Use -r to hash the files inside it.",
             input.to_string_lossy()
-        ));
+        )));
     }
-    if !input.is_file() {
-        return Err(format!(
-            "The path '{}' is not a regular file.",
+    if !input.is_file() && !is_block_device(input) {
+        return Err(HashgoodError::Usage(format!(
+            "The path '{}' is not a regular file or block device.",
             input.to_string_lossy()
-        ));
+        )));
+    }
+    let file = match DIRECT.load(Ordering::Relaxed) {
+        true => open_direct(input).map_err(|e| {
+            let context = format!(
+                "Unable to open '{}' for direct I/O",
+                input.to_string_lossy()
+            );
+            HashgoodError::io(context, e)
+        })?,
+        false => File::open(input).map_err(|e| HashgoodError::io("File open", e))?,
+    };
+    // The whole file is read from start to finish, so the kernel can read further ahead
+    advise_sequential(&file);
+    Ok(file)
+}
+
+/// Set by --direct to read files straight from the device, bypassing the page cache
+static DIRECT: AtomicBool = AtomicBool::new(false);
+
+/// Open all further files for direct I/O
+pub fn set_direct() -> Result<(), String> {
+    if cfg!(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "macos"
+    ))) {
+        return Err("Direct I/O is not supported on this platform".to_owned());
+    }
+    DIRECT.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Open a file so that reads go to the device every time rather than the page cache. Reads must
+/// then be into aligned memory and a multiple of the block size, except at the end of the file.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+fn open_direct(input: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    File::options()
+        .read(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(input)
+}
+
+/// macOS doesn't have O_DIRECT, but turning off caching for the file has the same effect
+#[cfg(target_os = "macos")]
+fn open_direct(input: &Path) -> std::io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+    let file = File::open(input)?;
+    // SAFETY: the descriptor is open for as long as the file is borrowed
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+)))]
+fn open_direct(input: &Path) -> std::io::Result<File> {
+    File::open(input)
+}
+
+/// Set by --no-cache-pollution to keep files out of the page cache once they have been hashed
+static DROP_FROM_CACHE: AtomicBool = AtomicBool::new(false);
+
+/// Drop all further files from the page cache as they are read
+pub fn set_drop_from_cache() {
+    DROP_FROM_CACHE.store(true, Ordering::Relaxed);
+}
+
+/// Read a file, dropping it from the page cache as it goes if that was asked for
+fn file_reader(file: File) -> Box<dyn Read> {
+    match DROP_FROM_CACHE.load(Ordering::Relaxed) {
+        true => Box::new(UncachedReader::new(file)),
+        false => Box::new(file),
+    }
+}
+
+/// How much is read between asking the kernel to drop what has been read from the page cache
+const DROP_INTERVAL: u64 = 16 * 1024 * 1024;
+
+/// Reads a file and then drops what has been read from the page cache, so that hashing a large
+/// image doesn't push everything else out of memory
+struct UncachedReader {
+    file: File,
+    /// Where reading started, since the file may have been opened at an offset
+    start: u64,
+    pos: u64,
+    dropped: u64,
+}
+
+impl UncachedReader {
+    fn new(mut file: File) -> Self {
+        let start = file.stream_position().unwrap_or(0);
+        Self {
+            file,
+            start,
+            pos: start,
+            dropped: start,
+        }
+    }
+}
+
+impl Read for UncachedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.file.read(buf)?;
+        self.pos += size as u64;
+        if self.pos - self.dropped >= DROP_INTERVAL {
+            // Only whole pages are dropped, so start from the beginning each time to catch the
+            // ends of the ranges dropped before
+            drop_cached(&self.file, self.start, self.pos - self.start);
+            self.dropped = self.pos;
+        }
+        Ok(size)
+    }
+}
+
+impl Drop for UncachedReader {
+    fn drop(&mut self) {
+        drop_cached(&self.file, self.start, self.pos - self.start);
+    }
+}
+
+/// Tell the kernel that the file will be read sequentially. It is only a hint so failure is ignored.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn advise_sequential(file: &File) {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: the descriptor is open for as long as the file is borrowed
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL) };
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn advise_sequential(_file: &File) {}
+
+/// Ask the kernel to drop part of a file from the page cache. Pages which another process has
+/// changed and not yet written out are kept.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn drop_cached(file: &File, offset: u64, len: u64) {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: the descriptor is open for as long as the file is borrowed
+    unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            libc::POSIX_FADV_DONTNEED,
+        )
+    };
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn drop_cached(_file: &File, _offset: u64, _len: u64) {}
+
+/// Open a regular file so that it can be hashed as a sparse file, if it contains holes
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+))]
+pub fn open_sparse(input: &Path) -> Option<File> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = input.metadata().ok()?;
+    // Blocks are always counted in units of 512 bytes, whatever the filesystem uses
+    if !metadata.is_file() || metadata.blocks() * 512 >= metadata.len() {
+        return None;
+    }
+    File::open(input).ok()
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+)))]
+pub fn open_sparse(_input: &Path) -> Option<File> {
+    None
+}
+
+/// Find the next region of a sparse file at or after `pos` which contains data, or `None` if
+/// there is only a hole between `pos` and the end of the file.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+))]
+fn find_data(file: &File, pos: u64) -> std::io::Result<Option<Range<u64>>> {
+    use std::io::Error;
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    // SAFETY: lseek only moves the offset of a file descriptor which is valid while `file` lives
+    let start = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+    if start < 0 {
+        let err = Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENXIO) => Ok(None),
+            _ => Err(err),
+        };
+    }
+    // SAFETY: as above
+    let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+    if end < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(Some(start as u64..end as u64))
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+)))]
+fn find_data(_file: &File, pos: u64) -> std::io::Result<Option<Range<u64>>> {
+    Ok(Some(pos..u64::MAX))
+}
+
+/// Map a regular file into memory so that it can be hashed without copying it through a buffer
+pub fn map_input(input: &Path) -> Result<Mmap, HashgoodError> {
+    let file = File::open(input).map_err(|e| HashgoodError::io("File open", e))?;
+    // SAFETY: if another process modifies the file while it is mapped we may read inconsistent
+    // data or be killed by SIGBUS. The user has opted in to this with --mmap.
+    unsafe { Mmap::map(&file) }.map_err(|e| HashgoodError::io("Unable to map file into memory", e))
+}
+
+/// Check whether the path refers to a block device, such as a disk or partition
+#[cfg(unix)]
+pub fn is_block_device(input: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(input)
+        .map(|m| m.file_type().is_block_device())
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_block_device(_input: &Path) -> bool {
+    false
+}
+
+/// Check whether the input is a regular file on storage which isn't a spinning disk, such as an SSD
+/// or a network filesystem, or a pipe on standard input. Reading more at a time is faster for these.
+#[cfg(target_os = "linux")]
+pub fn is_fast_input(input: &Path) -> bool {
+    if input.to_str() == Some("-") {
+        return is_stdin_pipe();
+    }
+    std::fs::metadata(input)
+        .map(|m| m.is_file() && is_fast_device(&m))
+        .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_fast_input(_input: &Path) -> bool {
+    false
+}
+
+/// Ask the kernel whether the device holding a file is rotational. Filesystems which aren't on a
+/// local disk, such as NFS or tmpfs, have a major device number of 0.
+#[cfg(target_os = "linux")]
+fn is_fast_device(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let (major, minor) = (libc::major(metadata.dev()), libc::minor(metadata.dev()));
+    if major == 0 {
+        return true;
+    }
+    // A partition has no queue of its own, so fall back to the disk it is part of
+    let device = PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+    std::fs::read_to_string(device.join("queue/rotational"))
+        .or_else(|_| std::fs::read_to_string(device.join("../queue/rotational")))
+        .map(|rotational| rotational.trim() == "0")
+        .unwrap_or(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_fast_device(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+/// How much of a stream is read at a time, unless it is a file on fast storage
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+/// How much of a file on fast storage is read at a time
+const FAST_BUFFER_SIZE: usize = 1024 * 1024;
+/// Buffers bigger than this don't read any faster and only waste memory
+const MAX_BUFFER_SIZE: usize = 256 * 1024 * 1024;
+
+/// Read size chosen with --buffer-size, or 0 to choose one for each input
+static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Read all further inputs this many bytes at a time, whatever they are stored on
+pub fn set_buffer_size(size: usize) {
+    BUFFER_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Parse a size such as `65536`, `64K` or `8M` given in bytes or binary multiples
+pub fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiple = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Unknown unit '{}' in size '{}'", unit, size)),
+    };
+    let bytes = number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiple))
+        .ok_or_else(|| format!("Invalid size '{}'", size))?;
+    check_size(bytes)
+}
+
+/// Check that a buffer size given as a number of bytes is usable
+pub fn check_size(bytes: usize) -> Result<usize, String> {
+    match bytes {
+        0 => Err("Size must be greater than zero".to_owned()),
+        bytes if bytes > MAX_BUFFER_SIZE => Err(format!(
+            "Size must be no more than {}M",
+            MAX_BUFFER_SIZE / 1024 / 1024
+        )),
+        bytes => Ok(bytes),
+    }
+}
+
+/// Determine the size in bytes of a block device. The file metadata doesn't report this, but
+/// seeking to the end does.
+pub fn get_block_device_len(input: &Path) -> Option<u64> {
+    File::open(input).ok()?.seek(SeekFrom::End(0)).ok()
+}
+
+/// Start downloading from the URL, providing a reader that streams the response body
+#[cfg(feature = "url")]
+fn get_url_reader(url: &str) -> Result<Box<dyn Read>, HashgoodError> {
+    match ureq::get(url).call() {
+        Ok(response) => Ok(response.into_reader()),
+        Err(ureq::Error::Status(code, _)) => {
+            Err(format!("Download of '{}' failed with HTTP status {}", url, code).into())
+        }
+        Err(e) => Err(format!("Download of '{}' failed: {}", url, e).into()),
+    }
+}
+
+#[cfg(not(feature = "url"))]
+fn get_url_reader(_url: &str) -> Result<Box<dyn Read>, HashgoodError> {
+    Err(HashgoodError::Unsupported(
+        "URL inputs are not supported by this build of hashgood (requires the url feature)"
+            .to_owned(),
+    ))
+}
+
+/// Wraps a reader so that all data read through it is also written to another destination
+pub struct TeeReader<R, W> {
+    inner: R,
+    copy: W,
+}
+
+impl<R: Read, W: Write> TeeReader<R, W> {
+    pub fn new(inner: R, copy: W) -> Self {
+        Self { inner, copy }
+    }
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.copy.write_all(&buf[..size])?;
+        Ok(size)
+    }
+}
+
+/// An incremental implementation of a digest algorithm, fed with chunks of input in order
+pub trait Digester: Send {
+    /// Add more data to the digest
+    fn update(&mut self, data: &[u8]);
+
+    /// Consume the digester and produce the final digest bytes
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+
+    /// Serialise the internal state so that the digest can be resumed later, if supported
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+impl<D: Digest + SerializableState + Send> Digester for D {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Digest::finalize(*self).to_vec()
+    }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.serialize().to_vec())
+    }
+}
+
+/// Recreate a digester from the state previously returned by `save_state`
+fn restore_digest<D>(state: &[u8]) -> Result<Box<dyn Digester>, String>
+where
+    D: Digest + SerializableState + Send + 'static,
+{
+    let invalid = "Checkpoint contains an invalid digest state".to_owned();
+    let state = SerializedState::<D>::try_from(state).map_err(|_| invalid.clone())?;
+    Ok(Box::new(D::deserialize(&state).map_err(|_| invalid)?))
+}
+
+/// Adapts a keyed MAC so that it can be driven the same way as a plain digest
+struct MacDigester<M>(M);
+
+impl<M: Mac + Send> Digester for MacDigester<M> {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Constructs a keyed HMAC digester from a secret key
+type HmacConstructor = fn(&[u8]) -> Box<dyn Digester>;
+
+/// Constructs a digester which continues from a saved state
+type RestoreConstructor = fn(&[u8]) -> Result<Box<dyn Digester>, String>;
+
+/// A registered digest implementation and how to construct a fresh instance of it
+struct Backend {
+    alg: Algorithm,
+    create: fn() -> Box<dyn Digester>,
+    /// Construct an HMAC using this digest and the given secret key, if that makes sense for this
+    /// algorithm. HMAC accepts keys of any length so this cannot fail.
+    create_hmac: Option<HmacConstructor>,
+    /// Resume a digest from a checkpoint, if this algorithm can save its state
+    restore: Option<RestoreConstructor>,
+}
+
+/// All digest implementations available in this build. Optional algorithms can be added here
+/// behind a cargo feature without any changes to the input handling in `create_digests`.
+static BACKENDS: &[Backend] = &[
+    Backend {
+        alg: Algorithm::Md5,
+        create: || Box::new(Md5::new()),
+        create_hmac: Some(|key| Box::new(MacDigester(Hmac::<Md5>::new_from_slice(key).unwrap()))),
+        restore: Some(restore_digest::<Md5>),
+    },
+    Backend {
+        alg: Algorithm::Sha1,
+        create: || Box::new(Sha1::new()),
+        create_hmac: Some(|key| Box::new(MacDigester(Hmac::<Sha1>::new_from_slice(key).unwrap()))),
+        restore: Some(restore_digest::<Sha1>),
+    },
+    Backend {
+        alg: Algorithm::Sha256,
+        create: || Box::new(Sha256::new()),
+        create_hmac: Some(|key| {
+            Box::new(MacDigester(Hmac::<Sha256>::new_from_slice(key).unwrap()))
+        }),
+        restore: Some(restore_digest::<Sha256>),
+    },
+    Backend {
+        alg: Algorithm::Sha384,
+        create: || Box::new(Sha384::new()),
+        create_hmac: Some(|key| {
+            Box::new(MacDigester(Hmac::<Sha384>::new_from_slice(key).unwrap()))
+        }),
+        restore: Some(restore_digest::<Sha384>),
+    },
+    Backend {
+        alg: Algorithm::Sha512,
+        create: || Box::new(Sha512::new()),
+        create_hmac: Some(|key| {
+            Box::new(MacDigester(Hmac::<Sha512>::new_from_slice(key).unwrap()))
+        }),
+        restore: Some(restore_digest::<Sha512>),
+    },
+    Backend {
+        alg: Algorithm::Sha256Tree,
+        create: || Box::new(TreeHasher::new()),
+        create_hmac: None,
+        restore: None,
+    },
+    Backend {
+        alg: Algorithm::Ssdeep,
+        create: || Box::new(FuzzyHasher::new()),
+        create_hmac: None,
+        restore: None,
+    },
+    #[cfg(feature = "xxhash")]
+    Backend {
+        alg: Algorithm::Xxh64,
+        create: || Box::new(Xxh64Digester(twox_hash::XxHash64::with_seed(0))),
+        create_hmac: None,
+        restore: None,
+    },
+    #[cfg(feature = "xxhash")]
+    Backend {
+        alg: Algorithm::Xxh128,
+        create: || Box::new(Xxh128Digester(twox_hash::XxHash3_128::new())),
+        create_hmac: None,
+        restore: None,
+    },
+];
+
+/// Size of each leaf of the SHA-256 tree hash used by Amazon Glacier and S3
+const TREE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Calculates the SHA-256 tree hash used by Amazon Glacier and S3.
+///
+/// The input is split into 1 MiB chunks which are hashed individually. Adjacent pairs of hashes
+/// are then concatenated and hashed again, level by level, until a single root hash remains. An
+/// odd hash at the end of a level is carried up unchanged.
+///
+/// Rather than storing every leaf, completed subtrees are merged as soon as possible so that only
+/// one hash per level needs to be kept while streaming.
+struct TreeHasher {
+    /// Digest of the chunk currently being read
+    chunk: Sha256,
+    /// Number of bytes added to the current chunk so far
+    chunk_len: usize,
+    /// Roots of completed subtrees as (level, hash), strictly decreasing in level
+    subtrees: Vec<(u32, Vec<u8>)>,
+}
+
+impl TreeHasher {
+    fn new() -> Self {
+        Self {
+            chunk: Sha256::new(),
+            chunk_len: 0,
+            subtrees: vec![],
+        }
     }
-    match File::open(input) {
-        Ok(f) => Ok(Box::new(f)),
-        Err(e) => Err(format!("File open: {}", e)),
+
+    /// Add the current chunk as a leaf, merging any subtrees which are now complete
+    fn finish_chunk(&mut self) {
+        let mut level = 0;
+        let mut hash = self.chunk.finalize_reset().to_vec();
+        self.chunk_len = 0;
+        while let Some((top_level, _)) = self.subtrees.last() {
+            if *top_level != level {
+                break;
+            }
+            let (_, left) = self.subtrees.pop().unwrap();
+            hash = Sha256::new()
+                .chain_update(left)
+                .chain_update(hash)
+                .finalize()
+                .to_vec();
+            level += 1;
+        }
+        self.subtrees.push((level, hash));
     }
 }
 
+impl Digester for TreeHasher {
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = data.len().min(TREE_CHUNK_SIZE - self.chunk_len);
+            Digest::update(&mut self.chunk, &data[..take]);
+            self.chunk_len += take;
+            data = &data[take..];
+            if self.chunk_len == TREE_CHUNK_SIZE {
+                self.finish_chunk();
+            }
+        }
+    }
+
+    fn finalize(mut self: Box<Self>) -> Vec<u8> {
+        // A partial final chunk is a leaf too. Empty input is treated as a single empty chunk.
+        if self.chunk_len > 0 || self.subtrees.is_empty() {
+            self.finish_chunk();
+        }
+        // Whatever remains are the carried-up right edges of the tree, which combine right to left
+        let mut subtrees = self.subtrees.into_iter().rev().map(|(_, hash)| hash);
+        let mut root = subtrees.next().unwrap();
+        for left in subtrees {
+            root = Sha256::new()
+                .chain_update(left)
+                .chain_update(root)
+                .finalize()
+                .to_vec();
+        }
+        root
+    }
+}
+
+/// Calculates the SHA-1 hash of each fixed-size piece of the input, as used by BitTorrent. The
+/// result is the concatenation of all the piece hashes.
+struct PieceHasher {
+    piece_length: u64,
+    /// Digest of the piece currently being read
+    piece: Sha1,
+    /// Number of bytes added to the current piece so far
+    piece_len: u64,
+    pieces: Vec<u8>,
+}
+
+impl Digester for PieceHasher {
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = data
+                .len()
+                .min((self.piece_length - self.piece_len) as usize);
+            Digest::update(&mut self.piece, &data[..take]);
+            self.piece_len += take as u64;
+            data = &data[take..];
+            if self.piece_len == self.piece_length {
+                self.pieces.extend(self.piece.finalize_reset());
+                self.piece_len = 0;
+            }
+        }
+    }
+
+    fn finalize(mut self: Box<Self>) -> Vec<u8> {
+        if self.piece_len > 0 {
+            self.pieces.extend(self.piece.finalize());
+        }
+        self.pieces
+    }
+}
+
+/// Adapts 64-bit xxHash, which is written big-endian in checksum files
+#[cfg(feature = "xxhash")]
+struct Xxh64Digester(twox_hash::XxHash64);
+
+#[cfg(feature = "xxhash")]
+impl Digester for Xxh64Digester {
+    fn update(&mut self, data: &[u8]) {
+        std::hash::Hasher::write(&mut self.0, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        std::hash::Hasher::finish(&self.0).to_be_bytes().to_vec()
+    }
+}
+
+/// Adapts 128-bit XXH3, which is written big-endian in checksum files
+#[cfg(feature = "xxhash")]
+struct Xxh128Digester(twox_hash::XxHash3_128);
+
+#[cfg(feature = "xxhash")]
+impl Digester for Xxh128Digester {
+    fn update(&mut self, data: &[u8]) {
+        self.0.write(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finish_128().to_be_bytes().to_vec()
+    }
+}
+
+/// Every algorithm which can be calculated by this build
+pub fn available_algorithms() -> impl Iterator<Item = Algorithm> {
+    BACKENDS.iter().map(|b| b.alg)
+}
+
+/// Find the registered backend for the given algorithm, if it is supported by this build
+fn find_backend(alg: Algorithm) -> Result<&'static Backend, HashgoodError> {
+    BACKENDS.iter().find(|b| b.alg == alg).ok_or_else(|| {
+        HashgoodError::Unsupported(match alg {
+            Algorithm::Xxh64 | Algorithm::Xxh128 => {
+                "xxHash is not supported by this build of hashgood (requires the xxhash feature)"
+                    .to_owned()
+            }
+            _ => format!("No digest implementation available for {:?}", alg),
+        })
+    })
+}
+
 /// For the given input stream, calculate all requested digest types
-pub fn create_digests(algorithms: &[Algorithm], mut input: Box<dyn Read>) -> CalculateResult {
-    let mut senders = vec![];
-    let mut handles = vec![];
-
-    if algorithms.contains(&Algorithm::Md5) {
-        let (s, r) = channel();
-        senders.push(s);
-        handles.push(md5_digest(r));
-    }
-    if algorithms.contains(&Algorithm::Sha1) {
-        let (s, r) = channel();
-        senders.push(s);
-        handles.push(sha1_digest(r));
-    }
-    if algorithms.contains(&Algorithm::Sha256) {
-        let (s, r) = channel();
-        senders.push(s);
-        handles.push(sha256_digest(r));
-    }
-
-    // 64 KB chunks will be read from the input at 64 KB and supplied to all hashing threads at once
-    // Right now that could be up to three threads. If CPU-bound, the other threads will mostly block while the slowest one finishes
-    const BUF_SIZE: usize = 1024 * 64;
-    let mut buf = [0; BUF_SIZE];
-    while let Ok(size) = input.read(&mut buf) {
+pub fn create_digests(algorithms: &[Algorithm], input: InputData) -> CalculateResult {
+    let mut digesters = vec![];
+    for alg in algorithms {
+        digesters.push((*alg, (find_backend(*alg)?.create)()));
+    }
+    run_digesters(digesters, input, None, None)
+}
+
+/// Called with the number of bytes read so far and the total size of the input, if known
+pub type ProgressFn<'f> = dyn FnMut(u64, Option<u64>) + 'f;
+
+/// The same as `create_digests`, calling `progress` each time another chunk of the input has been
+/// read. `total` is passed on to it as the size of the input.
+pub fn create_digests_with_progress(
+    algorithms: &[Algorithm],
+    input: InputData,
+    total: Option<u64>,
+    progress: &mut ProgressFn,
+) -> CalculateResult {
+    let mut digesters = vec![];
+    for alg in algorithms {
+        digesters.push((*alg, (find_backend(*alg)?.create)()));
+    }
+    let mut report = |bytes| progress(bytes, total);
+    run_digesters(digesters, input, None, Some(&mut report))
+}
+
+/// For the given asynchronous stream, calculate all requested digest types. Each chunk is hashed
+/// on the calling task as soon as it arrives, so no threads are spawned. A chunk takes well under a
+/// millisecond to hash, which is short enough not to hold up other tasks.
+#[cfg(feature = "async")]
+pub async fn create_digests_async<R>(algorithms: &[Algorithm], mut input: R) -> CalculateResult
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut digesters = vec![];
+    for alg in algorithms {
+        digesters.push((*alg, (find_backend(*alg)?.create)()));
+    }
+    let started = Instant::now();
+    let mut buf = vec![0; DEFAULT_BUFFER_SIZE];
+    let mut bytes = 0;
+    loop {
+        let size = match input.read(&mut buf).await {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            result => result.map_err(|e| HashgoodError::io("Unable to read input", e))?,
+        };
         if size == 0 {
             break;
-        } else {
-            // Create a shared read-only copy for the hashers to take as input
-            // buf is freed up for more reading
-            let chunk = Arc::new(buf[0..size].to_vec());
-            for s in &senders {
-                s.send(chunk.clone())?;
-            }
         }
+        for (_, digester) in &mut digesters {
+            digester.update(&buf[..size]);
+        }
+        bytes += size as u64;
     }
-    drop(senders);
-    // Once all data has been sent we just have to wait for the digests to fall out
-    Ok(handles.into_iter().map(|h| h.join().unwrap()).collect())
+    Ok(Digests {
+        hashes: digesters
+            .into_iter()
+            .map(|(alg, digester)| (alg, digester.finalize()))
+            .collect(),
+        bytes,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// For the given input stream, calculate all requested digest types while periodically saving
+/// their progress to the checkpoint. If the checkpoint was resumed, the input must begin at the
+/// checkpoint's offset.
+pub fn create_resumable_digests(
+    algorithms: &[Algorithm],
+    input: InputData,
+    checkpoint: &mut Checkpoint,
+) -> CalculateResult {
+    let mut digesters = vec![];
+    for alg in algorithms {
+        let restore = find_backend(*alg)?.restore.ok_or_else(|| {
+            HashgoodError::Unsupported(format!("Checkpoints are not supported for {:?}", alg))
+        })?;
+        let digester = match checkpoint.state(*alg) {
+            Some(state) => restore(state)?,
+            None if checkpoint.offset == 0 => (find_backend(*alg)?.create)(),
+            None => {
+                return Err("Checkpoint was made with different algorithms. \
+                    Delete it to start again."
+                    .into())
+            }
+        };
+        digesters.push((*alg, digester));
+    }
+    run_digesters(digesters, input, Some(checkpoint), None)
 }
 
-/// Calculate the md5 digest of some data on the given channel
-fn md5_digest(rx: Receiver<Arc<Vec<u8>>>) -> JoinHandle<(Algorithm, Vec<u8>)> {
-    thread::spawn(move || {
-        let mut md5 = Md5::new();
-        while let Ok(chunk) = rx.recv() {
-            md5.update(&*chunk);
+/// For the given input stream, calculate an HMAC with the secret key for all requested digest types
+pub fn create_hmacs(algorithms: &[Algorithm], key: &[u8], input: InputData) -> CalculateResult {
+    let mut digesters = vec![];
+    for alg in algorithms {
+        let create_hmac = find_backend(*alg)?.create_hmac.ok_or_else(|| {
+            HashgoodError::Unsupported(format!("HMAC is not supported for {:?}", alg))
+        })?;
+        digesters.push((*alg, create_hmac(key)));
+    }
+    run_digesters(digesters, input, None, None)
+}
+
+/// For the given input stream, calculate the concatenated SHA-1 hashes of each piece of the given size
+pub fn create_piece_digests(piece_length: u64, input: InputData) -> CalculateResult {
+    let hasher = PieceHasher {
+        piece_length,
+        piece: Sha1::new(),
+        piece_len: 0,
+        pieces: vec![],
+    };
+    run_digesters(vec![(Algorithm::Sha1, Box::new(hasher))], input, None, None)
+}
+
+/// Run `work` on each of the items using up to `jobs` threads, and pass the results to `done` on
+/// the calling thread in the same order as the items. A result which is ready early waits for the
+/// ones before it, so that whatever `done` prints for each item is never interleaved. Stops at the
+/// first error from `done`, once the items already being worked on are finished.
+#[cfg(not(target_family = "wasm"))]
+pub fn for_each_parallel<T, R, E>(
+    jobs: usize,
+    items: Vec<T>,
+    work: impl Fn(T) -> R + Sync,
+    mut done: impl FnMut(R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Send,
+    R: Send,
+{
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let (sender, receiver) = channel();
+    thread::scope(|s| {
+        for _ in 0..jobs.max(1) {
+            let sender = sender.clone();
+            let (queue, work) = (&queue, &work);
+            s.spawn(move || loop {
+                let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let Some((i, item)) = next else { break };
+                // The receiver is only gone if there was an error and nothing else is wanted
+                if sender.send((i, work(item))).is_err() {
+                    break;
+                }
+            });
         }
-        let result = md5.finalize();
-        (Algorithm::Md5, result.to_vec())
+        drop(sender);
+        let mut finished = BTreeMap::new();
+        let mut next = 0;
+        for (i, result) in receiver {
+            finished.insert(i, result);
+            while let Some(result) = finished.remove(&next) {
+                done(result)?;
+                next += 1;
+            }
+        }
+        Ok(())
     })
 }
 
-/// Calculate the sha1 digest of some data on the given channel
-fn sha1_digest(rx: Receiver<Arc<Vec<u8>>>) -> JoinHandle<(Algorithm, Vec<u8>)> {
-    thread::spawn(move || {
-        let mut sha1 = Sha1::new();
-        while let Ok(chunk) = rx.recv() {
-            sha1.update(&*chunk);
+/// Work on each of the items in turn. WebAssembly has no threads, so `jobs` is ignored.
+#[cfg(target_family = "wasm")]
+pub fn for_each_parallel<T, R, E>(
+    _jobs: usize,
+    items: Vec<T>,
+    work: impl Fn(T) -> R,
+    mut done: impl FnMut(R) -> Result<(), E>,
+) -> Result<(), E> {
+    for item in items {
+        done(work(item))?;
+    }
+    Ok(())
+}
+
+/// Which implementation the sha1 and sha2 crates pick for SHA-1 and SHA-256. They check the CPU
+/// at runtime and use its SHA instructions if it has them, otherwise portable code.
+pub fn sha_backend() -> &'static str {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("sse2")
+            && is_x86_feature_detected!("ssse3")
+            && is_x86_feature_detected!("sse4.1")
+        {
+            return "x86 SHA extensions";
         }
-        let result = sha1.finalize();
-        (Algorithm::Sha1, result.to_vec())
-    })
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("sha2") {
+            return "ARMv8 cryptography extensions";
+        }
+    }
+    "software"
 }
 
-/// Calculate the sha256 digest of some data on the given channel
-fn sha256_digest(rx: Receiver<Arc<Vec<u8>>>) -> JoinHandle<(Algorithm, Vec<u8>)> {
-    thread::spawn(move || {
-        let mut sha256 = Sha256::new();
-        while let Ok(chunk) = rx.recv() {
-            sha256.update(&*chunk);
+/// The backend is the same for every input so it is only reported once
+static SHA_BACKEND_LOGGED: Once = Once::new();
+
+/// How often the state of the digests is saved when checkpointing
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Stream the input through all of the given digesters at once, each running on its own thread
+fn run_digesters(
+    digesters: Vec<(Algorithm, Box<dyn Digester>)>,
+    input: InputData,
+    mut checkpoint: Option<&mut Checkpoint>,
+    mut progress: Option<&mut dyn FnMut(u64)>,
+) -> CalculateResult {
+    let uses_sha = |alg| {
+        matches!(
+            alg,
+            Algorithm::Sha1 | Algorithm::Sha256 | Algorithm::Sha256Tree
+        )
+    };
+    if digesters.iter().any(|(alg, _)| uses_sha(*alg)) {
+        SHA_BACKEND_LOGGED.call_once(|| {
+            display::log(1, || {
+                format!("SHA-1 and SHA-256 are using the {} backend", sha_backend())
+            })
+        });
+    }
+    let fast = match &input {
+        InputData::FastStream(_) => true,
+        InputData::Sparse(file) => file.metadata().is_ok_and(|m| is_fast_device(&m)),
+        InputData::Stream(_) | InputData::Mapped(_) => false,
+    };
+    let buf_size = match BUFFER_SIZE.load(Ordering::Relaxed) {
+        0 if fast => FAST_BUFFER_SIZE,
+        0 => DEFAULT_BUFFER_SIZE,
+        size => size,
+    };
+    let depth = match &input {
+        InputData::Mapped(_) => queue_depth(MAPPED_CHUNK_SIZE),
+        _ => queue_depth(buf_size),
+    };
+    display::log(2, || {
+        format!(
+            "Reading {} bytes at a time, up to {} chunks ahead",
+            buf_size, depth
+        )
+    });
+
+    // Each digest has its own queue so a fast one is never held up by a slow one, and reading
+    // only pauses once the slowest has fallen a whole queue behind
+    let workers: Vec<_> = digesters
+        .into_iter()
+        .map(|(alg, digester)| DigestWorker::spawn(alg, digester, depth))
+        .collect();
+    let started = Instant::now();
+    let sent = Cell::new(0);
+    let mut send_all = |chunk: Chunk| -> Result<(), Box<dyn Error>> {
+        sent.set(sent.get() + chunk.range.len() as u64);
+        for worker in &workers {
+            worker.send(Message::Data(chunk.clone()))?;
+        }
+        if let Some(progress) = progress.as_deref_mut() {
+            progress(sent.get());
+        }
+        Ok(())
+    };
+    // Each thread replies once it has processed everything sent before the request
+    let save_states = || -> Result<Vec<_>, Box<dyn Error>> {
+        let mut states = vec![];
+        for worker in &workers {
+            let (reply, response) = channel();
+            worker.send(Message::SaveState(reply))?;
+            let state = response
+                .recv()?
+                .ok_or_else(|| format!("Checkpoints are not supported for {:?}", worker.alg))?;
+            states.push((worker.alg, state));
+        }
+        Ok(states)
+    };
+
+    // Chunks are read from the input and supplied to all hashing threads at once
+    // Buffers come back once every hasher is finished with them, so only a few are ever allocated
+    let (recycle, recycled) = channel();
+    let next_buffer = || PooledBuffer::new(buf_size, &recycle, &recycled);
+    match input {
+        InputData::Stream(mut input) | InputData::FastStream(mut input) => {
+            let mut last_saved = Instant::now();
+            let mut unsaved = 0;
+            loop {
+                let mut buf = next_buffer();
+                let size = match input.read(buf.as_mut()) {
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    // Stop rather than produce a digest of partial data
+                    result => result.map_err(|e| format!("Unable to read input: {}", e))?,
+                };
+                if size == 0 {
+                    break;
+                } else {
+                    // Share the buffer with the hashers as it is, and read into another one
+                    send_all(Chunk {
+                        data: Arc::new(buf),
+                        range: 0..size,
+                    })?;
+                    unsaved += size as u64;
+                }
+                if let Some(checkpoint) = checkpoint.as_deref_mut() {
+                    if last_saved.elapsed() >= CHECKPOINT_INTERVAL {
+                        checkpoint.save(checkpoint.offset + unsaved, save_states()?)?;
+                        last_saved = Instant::now();
+                        unsaved = 0;
+                    }
+                }
+            }
+        }
+        InputData::Sparse(mut file) => {
+            // Holes read as zeros, so every hasher can share the same block of zeros
+            const ZERO_CHUNK_SIZE: usize = 1024 * 1024;
+            let zeros: Arc<dyn AsRef<[u8]> + Send + Sync> = Arc::new(vec![0u8; ZERO_CHUNK_SIZE]);
+            let len = file.metadata()?.len();
+            let mut progress = Progress::new(len);
+            let mut pos = 0;
+            'file: while pos < len {
+                let data = find_data(&file, pos)?.unwrap_or(len..len);
+                while pos < data.start.min(len) {
+                    let size = (data.start.min(len) - pos).min(ZERO_CHUNK_SIZE as u64);
+                    send_all(Chunk {
+                        data: zeros.clone(),
+                        range: 0..size as usize,
+                    })?;
+                    pos += size;
+                    progress.iter_mut().for_each(|p| p.advance(size));
+                }
+                file.seek(SeekFrom::Start(pos))?;
+                while pos < data.end.min(len) {
+                    let want = (data.end.min(len) - pos).min(buf_size as u64) as usize;
+                    let mut buf = next_buffer();
+                    let size = match file.read(&mut buf.as_mut()[..want]) {
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        result => result.map_err(|e| format!("Unable to read input: {}", e))?,
+                    };
+                    if size == 0 {
+                        // The file has been truncated while we were reading it
+                        break 'file;
+                    }
+                    send_all(Chunk {
+                        data: Arc::new(buf),
+                        range: 0..size,
+                    })?;
+                    pos += size as u64;
+                    progress.iter_mut().for_each(|p| p.advance(size as u64));
+                }
+            }
+            if DROP_FROM_CACHE.load(Ordering::Relaxed) {
+                drop_cached(&file, 0, len);
+            }
         }
-        let result = sha256.finalize();
-        (Algorithm::Sha256, result.to_vec())
+        InputData::Mapped(map) => {
+            // Every hasher can read directly from the mapping, so just tell them which part
+            let len = map.len();
+            let map: Arc<dyn AsRef<[u8]> + Send + Sync> = Arc::new(map);
+            let mut progress = Progress::new(len as u64);
+            for start in (0..len).step_by(MAPPED_CHUNK_SIZE) {
+                let range = start..len.min(start + MAPPED_CHUNK_SIZE);
+                let size = range.len() as u64;
+                send_all(Chunk {
+                    data: map.clone(),
+                    range,
+                })?;
+                progress.iter_mut().for_each(|p| p.advance(size));
+            }
+        }
+    }
+    // Once all data has been sent we just have to wait for the digests to fall out
+    Ok(Digests {
+        hashes: workers.into_iter().map(DigestWorker::finish).collect(),
+        bytes: sent.get(),
+        elapsed: started.elapsed(),
     })
 }
 
+/// How much data can be waiting for each digest thread before reading pauses. This keeps the disk
+/// busy through any hiccups in hashing while stopping a slow digest from causing the whole input to
+/// be buffered in memory.
+const READ_AHEAD_BYTES: usize = 32 * 1024 * 1024;
+
+/// How many chunks of a memory-mapped file are handed to the hashers at once. This is a multiple of
+/// the page size so each one touches as few pages as possible.
+const MAPPED_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How many chunks of the given size can be waiting for each digest thread. A few are always
+/// allowed so that reading and hashing overlap even with very large buffers.
+fn queue_depth(chunk_size: usize) -> usize {
+    (READ_AHEAD_BYTES / chunk_size.max(1)).clamp(2, 1024)
+}
+
+/// A digest which is fed the chunks of the input in turn
+#[cfg(not(target_family = "wasm"))]
+struct DigestWorker {
+    alg: Algorithm,
+    queue: SyncSender<Message>,
+    handle: JoinHandle<Vec<u8>>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl DigestWorker {
+    /// Feed the messages sent to the worker into a digester on a new thread. Up to `depth` of them
+    /// can be waiting before sending blocks.
+    fn spawn(alg: Algorithm, mut digester: Box<dyn Digester>, depth: usize) -> Self {
+        let (queue, rx) = sync_channel(depth);
+        let handle = thread::spawn(move || {
+            // Only the time spent hashing, not waiting for data
+            let mut busy = Duration::ZERO;
+            while let Ok(message) = rx.recv() {
+                process_message(&mut digester, message, &mut busy);
+            }
+            finish_digest(alg, digester, busy)
+        });
+        DigestWorker { alg, queue, handle }
+    }
+
+    fn send(&self, message: Message) -> Result<(), SendError<Message>> {
+        self.queue.send(message)
+    }
+
+    /// Wait for everything sent so far to be hashed and produce the digest
+    fn finish(self) -> (Algorithm, Vec<u8>) {
+        drop(self.queue);
+        (self.alg, self.handle.join().unwrap())
+    }
+}
+
+/// A digest which is fed the chunks of the input in turn. WebAssembly has no threads, so each chunk
+/// is hashed as soon as it is sent.
+#[cfg(target_family = "wasm")]
+struct DigestWorker {
+    alg: Algorithm,
+    digester: RefCell<Box<dyn Digester>>,
+    busy: Cell<Duration>,
+}
+
+#[cfg(target_family = "wasm")]
+impl DigestWorker {
+    fn spawn(alg: Algorithm, digester: Box<dyn Digester>, _depth: usize) -> Self {
+        DigestWorker {
+            alg,
+            digester: RefCell::new(digester),
+            busy: Cell::new(Duration::ZERO),
+        }
+    }
+
+    fn send(&self, message: Message) -> Result<(), SendError<Message>> {
+        let mut busy = self.busy.get();
+        process_message(&mut self.digester.borrow_mut(), message, &mut busy);
+        self.busy.set(busy);
+        Ok(())
+    }
+
+    fn finish(self) -> (Algorithm, Vec<u8>) {
+        let digest = finish_digest(self.alg, self.digester.into_inner(), self.busy.get());
+        (self.alg, digest)
+    }
+}
+
+/// Act on one message to a digest, adding the time spent hashing to `busy`
+fn process_message(digester: &mut Box<dyn Digester>, message: Message, busy: &mut Duration) {
+    match message {
+        Message::Data(chunk) => {
+            let start = Instant::now();
+            digester.update(chunk.bytes());
+            *busy += start.elapsed();
+        }
+        Message::SaveState(reply) => {
+            let _ = reply.send(digester.save_state());
+        }
+    }
+}
+
+/// Produce the final digest once all of the input has been processed
+fn finish_digest(alg: Algorithm, digester: Box<dyn Digester>, busy: Duration) -> Vec<u8> {
+    let digest = digester.finalize();
+    display::log(1, || {
+        format!(
+            "{} took {:.2}s",
+            display::algorithm_name(alg),
+            busy.as_secs_f64()
+        )
+    });
+    digest
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,10 +1419,214 @@ mod tests {
         "b9193853f7798e92e2f6b82eda336fa7d6fc0fa90fdefe665f372b0bad8cdf8c";
 
     fn verify_digest(alg: Algorithm, data: &'static [u8], hash: &str) {
-        let reader = Cursor::new(&*data);
-        let digests = create_digests(&[alg], Box::new(reader)).unwrap();
-        assert_eq!(digests.len(), 1);
-        assert_eq!(digests[0], (alg, hex::decode(hash).unwrap()));
+        let reader = Cursor::new(data);
+        let digests = create_digests(&[alg], InputData::Stream(Box::new(reader))).unwrap();
+        assert_eq!(digests.hashes.len(), 1);
+        assert_eq!(digests.hashes[0], (alg, hex::decode(hash).unwrap()));
+        assert_eq!(digests.bytes, data.len() as u64);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_digests() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+
+        // Reading from a slice never has to wait, so the future completes on the first poll
+        let future = create_digests_async(&[Algorithm::Md5, Algorithm::Sha256], &LARGE_DATA[..]);
+        let mut future = std::pin::pin!(future);
+        let Poll::Ready(digests) = future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        else {
+            panic!("Hashing a slice should not have to wait");
+        };
+        let digests = digests.unwrap();
+        assert_eq!(digests.hashes[0].1, hex::decode(LARGE_DATA_MD5).unwrap());
+        assert_eq!(digests.hashes[1].1, hex::decode(LARGE_DATA_SHA256).unwrap());
+        assert_eq!(digests.bytes, LARGE_DATA.len() as u64);
+    }
+
+    #[test]
+    fn progress_callback() {
+        let mut reports = vec![];
+        let data = InputData::Mapped(
+            memmap2::MmapOptions::new()
+                .len(3 << 20)
+                .map_anon()
+                .unwrap()
+                .make_read_only()
+                .unwrap(),
+        );
+        let digests = create_digests_with_progress(
+            &[Algorithm::Md5],
+            data,
+            Some(3 << 20),
+            &mut |bytes, total| reports.push((bytes, total)),
+        )
+        .unwrap();
+        assert_eq!(digests.bytes, 3 << 20);
+        assert_eq!(
+            reports,
+            vec![
+                (1 << 20, Some(3 << 20)),
+                (2 << 20, Some(3 << 20)),
+                (3 << 20, Some(3 << 20))
+            ]
+        );
+    }
+
+    #[test]
+    fn queue_depths() {
+        assert_eq!(queue_depth(DEFAULT_BUFFER_SIZE), 512);
+        assert_eq!(queue_depth(FAST_BUFFER_SIZE), 32);
+        assert_eq!(queue_depth(MAX_BUFFER_SIZE), 2);
+        assert_eq!(queue_depth(4096), 1024);
+    }
+
+    /// Assert that results from several threads are handed back in the order of the items.
+    #[test]
+    fn parallel_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let mut results = vec![];
+        for_each_parallel(
+            4,
+            items,
+            |i| {
+                // Later items finish first
+                std::thread::sleep(Duration::from_millis(20 - i));
+                i * 2
+            },
+            |r| -> Result<(), ()> {
+                results.push(r);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(results, (0..20).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    /// Assert that data read through a TeeReader is passed through and copied unchanged.
+    #[test]
+    fn tee_reader() {
+        let mut copy = vec![];
+        let mut read = vec![];
+        TeeReader::new(Cursor::new(&LARGE_DATA), &mut copy)
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, LARGE_DATA);
+        assert_eq!(copy, LARGE_DATA);
+    }
+
+    /// Assert that an HMAC is calculated rather than a plain digest when a key is supplied.
+    #[test]
+    fn hmac_sha256() {
+        // echo -n "The quick brown fox jumps over the lazy dog" | openssl dgst -sha256 -hmac key
+        let data = b"The quick brown fox jumps over the lazy dog";
+        let expected = "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8";
+        let digests = create_hmacs(
+            &[Algorithm::Sha256],
+            b"key",
+            InputData::Stream(Box::new(Cursor::new(data))),
+        )
+        .unwrap();
+        assert_eq!(
+            digests.hashes,
+            vec![(Algorithm::Sha256, hex::decode(expected).unwrap())]
+        );
+    }
+
+    /// Assert that the tree hash matches the reference algorithm for inputs spanning several
+    /// chunks, where the number of leaves is and is not a power of two. For a single chunk the
+    /// tree hash is the same as the plain SHA-256.
+    #[test]
+    fn tree_hash() {
+        // See the Amazon Glacier developer guide for the reference algorithm
+        let four_leaves = vec![b'C'; 3 * TREE_CHUNK_SIZE + 5];
+        let four_leaves_hash = "0b0aac646547d4c4a16147008730fa817ab910cb033a0b01f02149dfbbf94d59";
+        let five_leaves = vec![b'C'; 5 * TREE_CHUNK_SIZE];
+        let five_leaves_hash = "32bce17610a89b8652038472a3dacb2498b93ce0f3f413d511b5f07c5268e1d0";
+
+        for (data, hash) in [
+            (four_leaves, four_leaves_hash),
+            (five_leaves, five_leaves_hash),
+            (LARGE_DATA.to_vec(), LARGE_DATA_SHA256),
+        ] {
+            let digests = create_digests(
+                &[Algorithm::Sha256Tree],
+                InputData::Stream(Box::new(Cursor::new(data))),
+            )
+            .unwrap();
+            assert_eq!(
+                digests.hashes,
+                vec![(Algorithm::Sha256Tree, hex::decode(hash).unwrap())]
+            );
+        }
+    }
+
+    /// Assert that a digest saved part way through gives the same result once it is resumed.
+    #[test]
+    fn resume_from_state() {
+        for (alg, expected) in [
+            (Algorithm::Md5, LARGE_DATA_MD5),
+            (Algorithm::Sha1, LARGE_DATA_SHA1),
+            (Algorithm::Sha256, LARGE_DATA_SHA256),
+        ] {
+            let backend = find_backend(alg).unwrap();
+            let mut digester = (backend.create)();
+            digester.update(&LARGE_DATA[..300_001]);
+            let state = digester.save_state().unwrap();
+            let mut resumed = (backend.restore.unwrap())(&state).unwrap();
+            resumed.update(&LARGE_DATA[300_001..]);
+            assert_eq!(hex::encode(resumed.finalize()), expected);
+        }
+    }
+
+    #[test]
+    fn sparse_digests() {
+        let path = std::env::temp_dir().join(format!("hashgood-sparse-{}", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.set_len(3_000_000).unwrap();
+        file.seek(SeekFrom::Start(1_500_000)).unwrap();
+        file.write_all(&LARGE_DATA[..100_000]).unwrap();
+        drop(file);
+
+        let algorithms = [Algorithm::Sha256];
+        let sparse = InputData::Sparse(File::open(&path).unwrap());
+        let stream = InputData::Stream(Box::new(File::open(&path).unwrap()));
+        let sparse = create_digests(&algorithms, sparse).unwrap();
+        let stream = create_digests(&algorithms, stream).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sparse.hashes, stream.hashes);
+        assert_eq!(sparse.bytes, stream.bytes);
+    }
+
+    /// Assert that each piece is hashed separately, including a shorter final piece.
+    #[test]
+    fn piece_digests() {
+        let digests =
+            create_piece_digests(4, InputData::Stream(Box::new(Cursor::new(&SMALL_DATA)))).unwrap();
+        // python3 -c 'print ("A"*4, end="", flush=True)' | sha1sum
+        let full_piece = "e2512172abf8cc9f67fdd49eb6cacf2df71bbad3";
+        // python3 -c 'print ("A"*2, end="", flush=True)' | sha1sum
+        let final_piece = "801c34269f74ed383fc97de33604b8a905adb635";
+        let expected = format!("{}{}{}", full_piece, full_piece, final_piece);
+        assert_eq!(
+            digests.hashes,
+            vec![(Algorithm::Sha1, hex::decode(expected).unwrap())]
+        );
+    }
+
+    /// Assert that sizes are understood in bytes and binary multiples, within the limits.
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("65536"), Ok(65536));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("8m"), Ok(8 * 1024 * 1024));
+        assert_eq!(parse_size("1MiB"), Ok(1024 * 1024));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("4X").is_err());
+        assert!(parse_size("2G").is_err());
     }
 
     /// Assert that digests for all algorithms are calculated correctly for a small piece
@@ -154,6 +1638,20 @@ mod tests {
         verify_digest(Algorithm::Sha256, &SMALL_DATA, SMALL_DATA_SHA256);
     }
 
+    /// Assert that a memory-mapped file produces the same digests as reading it as a stream.
+    #[test]
+    fn mapped_digests() {
+        let path = std::env::temp_dir().join(format!("hashgood-mapped-{}", std::process::id()));
+        std::fs::write(&path, &LARGE_DATA[..]).unwrap();
+        let map = map_input(&path).unwrap();
+        let digests = create_digests(&[Algorithm::Sha1], InputData::Mapped(map)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            digests.hashes,
+            vec![(Algorithm::Sha1, hex::decode(LARGE_DATA_SHA1).unwrap())]
+        );
+    }
+
     /// Assert that digests for all algorithms are calculated correctly for a large piece
     /// of test data. For our purposes, "large" means that it spans several of the 64 KB
     /// blocks used to break up the input processing. Using one million bytes instead of
@@ -0,0 +1,181 @@
+use super::cache::Stamp;
+use super::Algorithm;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// First line of every checkpoint file, identifying the format
+const HEADER: &str = "hashgood checkpoint 1";
+
+/// The progress of a long hashing job, saved to a file so that it can be resumed after an
+/// interruption. It is only valid for the same input, unchanged since the checkpoint was made,
+/// and the same set of algorithms.
+#[derive(Debug, PartialEq)]
+pub struct Checkpoint {
+    /// Where the checkpoint is saved
+    path: PathBuf,
+    /// The name of the input being hashed
+    name: String,
+    /// The size and modification time of the input, or None if it isn't a regular file
+    stamp: Option<Stamp>,
+    /// Number of bytes of the input already included in the saved states
+    pub offset: u64,
+    /// Serialised internal state of each digest
+    states: Vec<(Algorithm, Vec<u8>)>,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint saved at `path` for the named input, which is read from `input`. If
+    /// there isn't one yet then a new checkpoint starting from the beginning of the input is
+    /// returned.
+    pub fn open(path: &Path, input: &Path, name: &str) -> Result<Checkpoint, String> {
+        let mut checkpoint = Checkpoint {
+            path: path.to_owned(),
+            name: name.to_owned(),
+            stamp: Stamp::of(input),
+            offset: 0,
+            states: vec![],
+        };
+        if !path.exists() {
+            return Ok(checkpoint);
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read checkpoint '{}': {}", path.display(), e))?;
+        let invalid = || format!("'{}' is not a valid checkpoint", path.display());
+        let mut lines = contents.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid());
+        }
+        let mut stamp = None;
+        for line in lines {
+            let (key, value) = line.split_once(": ").ok_or_else(invalid)?;
+            match key {
+                "name" if value != name => {
+                    return Err(format!(
+                        "Checkpoint '{}' is for a different input: {}",
+                        path.display(),
+                        value
+                    ));
+                }
+                "name" => (),
+                "stamp" => stamp = Some(value.parse().map_err(|_| invalid())?),
+                "offset" => checkpoint.offset = value.parse().map_err(|_| invalid())?,
+                _ => {
+                    let alg = parse_algorithm(key).ok_or_else(invalid)?;
+                    let state = hex::decode(value).map_err(|_| invalid())?;
+                    checkpoint.states.push((alg, state));
+                }
+            }
+        }
+        if stamp != checkpoint.stamp {
+            return Err(format!(
+                "Checkpoint '{}' cannot be resumed because {} has changed since it was made",
+                path.display(),
+                name
+            ));
+        }
+        Ok(checkpoint)
+    }
+
+    /// The saved state of a particular digest, if the checkpoint has one
+    pub fn state(&self, alg: Algorithm) -> Option<&[u8]> {
+        self.states
+            .iter()
+            .find(|(a, _)| *a == alg)
+            .map(|(_, state)| state.as_slice())
+    }
+
+    /// Record that the digests have reached `offset` with the given states and write it to disk.
+    /// The file is replaced atomically so an interruption while saving can't corrupt it.
+    pub fn save(&mut self, offset: u64, states: Vec<(Algorithm, Vec<u8>)>) -> Result<(), String> {
+        self.offset = offset;
+        self.states = states;
+        let mut contents = format!("{}\nname: {}\n", HEADER, self.name);
+        if let Some(stamp) = self.stamp {
+            contents.push_str(&format!("stamp: {}\n", stamp));
+        }
+        contents.push_str(&format!("offset: {}\n", offset));
+        for (alg, state) in &self.states {
+            contents.push_str(&format!(
+                "{}: {}\n",
+                algorithm_name(*alg),
+                hex::encode(state)
+            ));
+        }
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, contents)
+            .and_then(|_| fs::rename(&temp, &self.path))
+            .map_err(|e| format!("Unable to save checkpoint '{}': {}", self.path.display(), e))
+    }
+
+    /// Delete the checkpoint file once the job it belongs to has finished
+    pub fn remove(self) -> Result<(), String> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!(
+                "Unable to remove checkpoint '{}': {}",
+                self.path.display(),
+                e
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The name used for each algorithm which can be checkpointed or cached
+pub fn algorithm_name(alg: Algorithm) -> &'static str {
+    match alg {
+        Algorithm::Md5 => "md5",
+        Algorithm::Sha1 => "sha1",
+        Algorithm::Sha256 => "sha256",
+        Algorithm::Sha384 => "sha384",
+        Algorithm::Sha512 => "sha512",
+        Algorithm::Sha256Tree => "sha256-tree",
+        Algorithm::Ssdeep => "ssdeep",
+        Algorithm::Xxh64 => "xxh64",
+        Algorithm::Xxh128 => "xxh128",
+    }
+}
+
+pub fn parse_algorithm(name: &str) -> Option<Algorithm> {
+    [
+        Algorithm::Md5,
+        Algorithm::Sha1,
+        Algorithm::Sha256,
+        Algorithm::Sha384,
+        Algorithm::Sha512,
+        Algorithm::Sha256Tree,
+        Algorithm::Ssdeep,
+        Algorithm::Xxh64,
+        Algorithm::Xxh128,
+    ]
+    .into_iter()
+    .find(|a| algorithm_name(*a) == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_open() {
+        let path = std::env::temp_dir().join(format!("hashgood-test-{}.ckpt", std::process::id()));
+        let input = std::env::temp_dir().join(format!("hashgood-test-{}.img", std::process::id()));
+        fs::write(&input, b"disk image").unwrap();
+        let mut checkpoint = Checkpoint::open(&path, &input, "disk.img").unwrap();
+        assert_eq!(checkpoint.offset, 0);
+        assert_eq!(checkpoint.state(Algorithm::Sha256), None);
+
+        let states = vec![(Algorithm::Md5, vec![1, 2]), (Algorithm::Sha256, vec![3])];
+        checkpoint.save(1234, states).unwrap();
+        let reopened = Checkpoint::open(&path, &input, "disk.img").unwrap();
+        assert_eq!(reopened, checkpoint);
+        assert_eq!(reopened.state(Algorithm::Sha256), Some(&[3][..]));
+
+        assert!(Checkpoint::open(&path, &input, "other.img").is_err());
+        fs::write(&input, b"disk image, grown").unwrap();
+        assert!(Checkpoint::open(&path, &input, "disk.img").is_err());
+        reopened.remove().unwrap();
+        fs::remove_file(&input).unwrap();
+        assert!(!path.exists());
+    }
+}
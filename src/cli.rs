@@ -0,0 +1,1636 @@
+use calculate::InputData;
+use checkpoint::Checkpoint;
+use config::Config;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+use structopt::clap::{ArgMatches, Shell};
+use structopt::StructOpt;
+use termcolor::ColorChoice;
+
+use super::error::HashgoodError;
+use super::verify::NamePolicy;
+use super::{
+    archive, cache, calculate, checkpoint, config, display, image, input, man, policy, progress,
+    verify, watch, Algorithm, CandidateHash, CandidateHashes, CheckStatus, Hash, MatchLevel,
+    MessageLevel, TreeDifference, Verification, VerificationSource,
+};
+
+/// Problem running the program
+const EXIT_ERR: i32 = 1;
+/// Verification was performed and was not a match
+const EXIT_MISMATCH: i32 = 2;
+
+// The subcommands. Running hashgood without one is the same as `hashgood verify`.
+#[derive(StructOpt)]
+#[structopt(name = "hashgood")]
+enum Command {
+    /// Verify a file against a hash, a check file or another source of hashes
+    Verify(Opt),
+    /// Calculate the digests of a file without verifying it
+    Hash(Opt),
+    /// Verify every file listed in a check file, given in place of the input
+    Check(Opt),
+    /// Measure how fast each algorithm hashes on this machine
+    Bench(BenchOpt),
+    /// Print a completion script for a shell to standard output
+    Completions {
+        /// The shell to complete in
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
+}
+
+/// The name of each subcommand
+const SUBCOMMANDS: &[&str] = &["verify", "hash", "check", "bench", "completions"];
+
+/// Every other word which is handled by the top level instead of `verify`
+const COMMAND_WORDS: &[&str] = &["help", "-h", "--help", "-V", "--version"];
+
+/// The most MiB of data `bench` will hash, so that a mistyped size can't exhaust memory
+const MAX_BENCH_SIZE: usize = 16 * 1024;
+
+#[derive(StructOpt)]
+struct BenchOpt {
+    /// How many MiB of data to hash with each algorithm
+    #[structopt(long = "size", default_value = "256")]
+    size: usize,
+}
+
+#[derive(StructOpt)]
+#[structopt(name = "hashgood")]
+pub struct Opt {
+    /// Read the hash from the clipboard
+    #[cfg(all(feature = "paste", not(target_family = "wasm")))]
+    #[structopt(short = "p", long = "paste")]
+    pub(crate) paste: bool,
+
+    /// Disable ANSI colours in output. This is the same as `--color never`.
+    #[structopt(short = "C", long = "no-colour")]
+    pub(crate) no_colour: bool,
+
+    /// When to use colours in output: `auto` uses them when printing to a terminal, unless the NO_COLOR environment variable is set
+    #[structopt(
+        long = "color",
+        alias = "colour",
+        default_value = "auto",
+        possible_values = &["auto", "always", "never"]
+    )]
+    pub(crate) colour: ColorChoice,
+
+    /// Print digests in groups of this many characters separated by spaces, to make them easier to check by eye
+    #[structopt(long = "group", possible_values = &["4", "8"])]
+    pub(crate) group: Option<usize>,
+
+    /// Write the JUnit XML report to this file instead of standard output
+    #[structopt(long = "output", parse(from_os_str))]
+    pub(crate) output: Option<PathBuf>,
+
+    /// Append a timestamped record of each result to this file, as one line of JSON per result, to keep an audit trail of verifications
+    #[structopt(long = "report", parse(from_os_str))]
+    pub(crate) report: Option<PathBuf>,
+
+    /// Ring the terminal bell when a result is FAIL
+    #[structopt(long = "bell")]
+    pub(crate) bell: bool,
+
+    /// Print a full-width banner when a result is FAIL, so that it stands out when scrolling back
+    #[structopt(long = "banner")]
+    pub(crate) banner: bool,
+
+    /// Colours to use: `default`, `high-contrast` or `colourblind`, optionally followed by changes to individual colours such as `,bad=magenta`. The colours are good, bad, warning, label and note, and each can be a name, an ANSI 256-colour number or `#rrggbb`.
+    #[structopt(long = "theme", default_value = "default")]
+    pub(crate) theme: display::Theme,
+
+    /// How to print results: `text` for people to read, `json` for one JSON object per result, `tsv` or `csv` for one line per result with its filename, algorithm, digest and result, `coreutils` or `tag` for `sha256sum`-style lines which can be saved as a digests file, `tap` for the Test Anything Protocol, or `junit` for a JUnit XML report
+    #[structopt(
+        long = "format",
+        default_value = "text",
+        possible_values = &["text", "json", "tsv", "csv", "coreutils", "tag", "tap", "junit"]
+    )]
+    pub(crate) format: display::OutputFormat,
+
+    /// Print each result as a JSON object on its own line. This is the same as `--format json`.
+    #[structopt(long = "json", conflicts_with = "format")]
+    pub(crate) json: bool,
+
+    /// Print digests as `SHA256 (filename) = digest` lines, like `shasum --tag`. This is the same as `--format tag`.
+    #[structopt(long = "tag", conflicts_with_all = &["format", "json"])]
+    pub(crate) tag: bool,
+
+    /// Print digests as Subresource Integrity strings such as `sha384-<base64>`, for use in `integrity` attributes. SHA-256, SHA-384 and SHA-512 are calculated when there is no hash to verify.
+    #[structopt(long = "sri", conflicts_with_all = &["tree-hash", "fuzzy", "hmac-key"])]
+    pub(crate) sri: bool,
+
+    /// Print digests in base64 instead of hex, as expected by `Content-MD5` headers and some cloud tools
+    #[structopt(long = "base64", conflicts_with = "sri")]
+    pub(crate) base64: bool,
+
+    /// Report what hashgood is doing on standard error, such as how the check file was interpreted and how long hashing took. Use twice for more detail.
+    #[structopt(
+        short = "v",
+        long = "verbose",
+        parse(from_occurrences),
+        conflicts_with = "quiet"
+    )]
+    pub(crate) verbose: u8,
+
+    /// Print only OK or FAIL for each result. Use twice to print nothing at all, so that only the exit status indicates the result.
+    #[structopt(
+        short = "q",
+        long = "quiet",
+        parse(from_occurrences),
+        conflicts_with_all = &["format", "json", "tag"]
+    )]
+    pub(crate) quiet: u8,
+
+    /// A file containing the hash to verify. It can either be a raw hash or a SHASUMS-style listing. Use `-` for standard input. A magnet link can also be given in place of a file.
+    #[structopt(short = "c", long = "check", parse(from_os_str))]
+    pub(crate) hash_file: Option<PathBuf>,
+
+    /// Download the checksums file from a GitHub release, given as `owner/repo` or `owner/repo@tag`. The latest release is used if no tag is given.
+    #[structopt(long = "github")]
+    pub(crate) github: Option<String>,
+
+    /// When the input is a URL, download its checksum from a file published next to it, such as `<url>.sha256` or `SHA256SUMS` in the same directory
+    #[structopt(long = "auto-sums")]
+    pub(crate) auto_sums: bool,
+
+    /// Ask for the hash to verify against, so that it can be pasted in. If nothing is entered, the digests are printed instead.
+    #[structopt(short = "i", long = "interactive")]
+    pub(crate) interactive: bool,
+
+    /// A detached signature over the check file, which must be valid before its hashes are trusted. By default it is looked for next to the check file.
+    #[structopt(long = "sig", parse(from_os_str))]
+    pub(crate) sig: Option<PathBuf>,
+
+    /// A PGP public key or keyring file containing the keys trusted to sign the check file, which may be clearsigned
+    #[structopt(
+        long = "key",
+        parse(from_os_str),
+        conflicts_with_all = &["minisign-pub", "signify-pub"]
+    )]
+    pub(crate) key: Option<PathBuf>,
+
+    /// A minisign public key trusted to sign the check file, either a .pub file or the key itself
+    #[structopt(long = "minisign-pub", conflicts_with = "signify-pub")]
+    pub(crate) minisign_pub: Option<String>,
+
+    /// An OpenBSD signify public key file trusted to sign the check file
+    #[structopt(long = "signify-pub", parse(from_os_str))]
+    pub(crate) signify_pub: Option<PathBuf>,
+
+    /// Verify each input against a hash which is part of its own filename, such as `package-<hash>.tar.gz`
+    #[structopt(long = "from-name")]
+    pub(crate) from_name: bool,
+
+    /// Verify each input against a checksum stored in one of its extended attributes, such as `user.checksum.sha256`
+    #[structopt(long = "from-xattr")]
+    pub(crate) from_xattr: bool,
+
+    /// The extended attribute to read with --from-xattr. By default several common names are tried.
+    #[structopt(long = "xattr-name", requires = "from-xattr")]
+    pub(crate) xattr_name: Option<String>,
+
+    /// Verify every file listed in this digests file, like `sha256sum -c`. Use `-` for standard input.
+    #[structopt(long = "check-all", parse(from_os_str))]
+    pub(crate) check_all: Option<PathBuf>,
+
+    /// Fail if any line of a digests file can't be understood, instead of ignoring it with a warning
+    #[structopt(long = "strict")]
+    pub(crate) strict: bool,
+
+    /// With --check-all, skip listed files which don't exist instead of treating them as failures
+    #[structopt(long = "ignore-missing", requires = "check-all")]
+    pub(crate) ignore_missing: bool,
+
+    /// Compare the input directly against this other file, hashing both at the same time. If both are directories, every file inside them is compared.
+    #[structopt(long = "compare", parse(from_os_str))]
+    pub(crate) compare: Option<PathBuf>,
+
+    /// Calculate an HMAC using this secret key instead of a plain digest. Use `@path` to read the key from a file.
+    #[structopt(long = "hmac-key")]
+    pub(crate) hmac_key: Option<String>,
+
+    /// Calculate the SHA-256 tree hash used by Amazon Glacier and S3 instead of plain digests
+    #[structopt(long = "tree-hash")]
+    pub(crate) tree_hash: bool,
+
+    /// Also calculate an ssdeep fuzzy hash when no hash is provided
+    #[structopt(long = "fuzzy")]
+    pub(crate) fuzzy: bool,
+
+    /// Calculate the MD5 digest. With any of the digest flags, only the chosen digests are calculated instead of MD5, SHA-1 and SHA-256.
+    #[structopt(long = "md5", conflicts_with = "tree-hash")]
+    pub(crate) md5: bool,
+
+    /// Calculate the SHA-1 digest
+    #[structopt(long = "sha1", conflicts_with = "tree-hash")]
+    pub(crate) sha1: bool,
+
+    /// Calculate the SHA-256 digest
+    #[structopt(long = "sha256", conflicts_with = "tree-hash")]
+    pub(crate) sha256: bool,
+
+    /// Calculate the SHA-384 digest
+    #[structopt(long = "sha384", conflicts_with = "tree-hash")]
+    pub(crate) sha384: bool,
+
+    /// Calculate the SHA-512 digest
+    #[structopt(long = "sha512", conflicts_with = "tree-hash")]
+    pub(crate) sha512: bool,
+
+    /// Treat a matching hash as OK even when the check file lists it under a different filename, such as after the download was renamed. The difference is still noted.
+    #[structopt(long = "no-name-check")]
+    pub(crate) no_name_check: bool,
+
+    /// Fail when the hash matches but the check file lists it under a different filename, instead of giving MAYBE
+    #[structopt(long = "strict-names", conflicts_with = "no-name-check")]
+    pub(crate) strict_names: bool,
+
+    /// Give up after this many seconds if nothing arrives on standard input for `-c -`, or the clipboard doesn't respond
+    #[structopt(long = "timeout")]
+    pub(crate) timeout: Option<u64>,
+
+    /// How to report a match made with a weak algorithm: `off`, `note`, `warning` or `error`, which fails the result. Algorithms can be set individually, such as `md5=error,sha1=warning,xxhash=off`.
+    #[structopt(long = "warn-level")]
+    pub(crate) warn_level: Option<String>,
+
+    /// Don't warn about matches made with weak algorithms such as MD5. This is the same as `--warn-level off`.
+    #[structopt(long = "no-warn", conflicts_with = "warn-level")]
+    pub(crate) no_warn: bool,
+
+    /// Calculate every digest supported by this build in a single pass over the input
+    #[structopt(
+        long = "all",
+        conflicts_with_all = &["md5", "sha1", "sha256", "sha384", "sha512", "tree-hash", "fuzzy", "sri"]
+    )]
+    pub(crate) all: bool,
+
+    /// Save a copy of the input data to this path while it is being hashed, such as when downloading from a URL. Use `-` to pass the data through to standard output, in which case results are printed to standard error.
+    #[structopt(long = "save", alias = "tee", parse(from_os_str))]
+    pub(crate) save: Option<PathBuf>,
+
+    /// Treat the input as a .zip, .tar or .tar.gz archive and hash each file inside it without extracting
+    #[structopt(long = "archive")]
+    pub(crate) archive: bool,
+
+    /// Treat the input as a container image and verify every blob against its digest. It can be an OCI image layout directory, a `docker save` or OCI archive tarball, or a manifest file with its blobs in the same directory.
+    #[structopt(long = "image")]
+    pub(crate) image: bool,
+
+    /// The filename to use for data read from standard input, so it can be matched in a digests file
+    #[structopt(long = "stdin-name")]
+    pub(crate) stdin_name: Option<String>,
+
+    /// Skip this many bytes at the start of the input before hashing, such as to verify a partition inside a larger image
+    #[structopt(long = "offset")]
+    pub(crate) offset: Option<u64>,
+
+    /// Read the input this much at a time, such as `64K` or `8M`. By default files on SSDs and network filesystems, and pipes on Linux, are read 1M at a time and everything else 64K at a time.
+    #[structopt(long = "buffer-size", parse(try_from_str = calculate::parse_size))]
+    pub(crate) buffer_size: Option<usize>,
+
+    /// Stop after hashing this many bytes of the input, such as the size of an image written to a larger device
+    #[structopt(long = "length")]
+    pub(crate) length: Option<u64>,
+
+    /// Read regular files by mapping them into memory, which can be faster on fast storage. The file must not be modified while it is being hashed.
+    #[structopt(long = "mmap")]
+    pub(crate) mmap: bool,
+
+    /// Read files directly from the device with O_DIRECT, bypassing the page cache, to be sure that the data really is on the disk
+    #[structopt(long = "direct", conflicts_with = "mmap")]
+    pub(crate) direct: bool,
+
+    /// Drop files from the page cache once they have been hashed, so that verifying a large image doesn't push other files out of memory
+    #[structopt(long = "no-cache-pollution", conflicts_with = "mmap")]
+    pub(crate) no_cache_pollution: bool,
+
+    /// Periodically save progress to this file while hashing, and resume from it if it already exists. It is removed once hashing is complete.
+    #[structopt(long = "checkpoint", parse(from_os_str))]
+    pub(crate) checkpoint: Option<PathBuf>,
+
+    /// Remember the digests of files in this cache file, so that files which haven't changed size, modification time or inode since an earlier run aren't read again
+    #[structopt(long = "cache", parse(from_os_str))]
+    pub(crate) cache: Option<PathBuf>,
+
+    /// Hash all of the inputs in order as though they were joined into one file, such as the parts of a split download
+    #[structopt(long = "concat")]
+    pub(crate) concat: bool,
+
+    /// Keep watching the input and hash it again whenever it changes, until interrupted
+    #[structopt(long = "watch")]
+    pub(crate) watch: bool,
+
+    /// Hash every file inside the input if it is a directory
+    #[structopt(short = "r", long = "recursive")]
+    pub(crate) recursive: bool,
+
+    /// How many inputs to hash at the same time. Defaults to the number of CPUs. Results are still printed in order, one input at a time.
+    #[structopt(short = "j", long = "jobs")]
+    pub(crate) jobs: Option<usize>,
+
+    /// When hashing a directory, follow symlinks to directories as well as files
+    #[structopt(long = "follow-symlinks", conflicts_with = "skip-symlinks")]
+    pub(crate) follow_symlinks: bool,
+
+    /// When hashing a directory, ignore all symlinks
+    #[structopt(long = "skip-symlinks")]
+    pub(crate) skip_symlinks: bool,
+
+    /// Read the list of files to hash from this file, one per line. Use `-` for standard input.
+    #[structopt(long = "files-from", parse(from_os_str))]
+    pub(crate) files_from: Option<PathBuf>,
+
+    /// Entries in the --files-from list are separated by NUL characters, as produced by `find -print0`
+    #[structopt(short = "0", long = "null", requires = "files-from")]
+    pub(crate) null: bool,
+
+    /// The file to be verified, a glob pattern matching several files, an HTTP(S) URL, or `-` for standard input
+    #[structopt(
+        name = "input",
+        parse(from_os_str),
+        required_unless_one = &["files-from", "check-all"],
+        conflicts_with_all = &["files-from", "check-all"]
+    )]
+    pub(crate) input: Option<PathBuf>,
+
+    /// A hash to verify, supplied directly on the command line
+    #[structopt(name = "hash")]
+    pub(crate) hash: Option<String>,
+
+    /// Digests to calculate when there is no hash to verify against, from the config file
+    #[structopt(skip)]
+    pub(crate) default_algorithms: Option<Vec<Algorithm>>,
+}
+
+impl Opt {
+    /// The digests chosen with flags such as `--sha256`, if any
+    fn selected_algorithms(&self) -> Vec<Algorithm> {
+        if self.all {
+            return calculate::available_algorithms().collect();
+        }
+        [
+            (self.md5, Algorithm::Md5),
+            (self.sha1, Algorithm::Sha1),
+            (self.sha256, Algorithm::Sha256),
+            (self.sha384, Algorithm::Sha384),
+            (self.sha512, Algorithm::Sha512),
+        ]
+        .into_iter()
+        .filter_map(|(selected, alg)| selected.then_some(alg))
+        .collect()
+    }
+
+    /// What to make of a matching hash listed under a different filename
+    pub(crate) fn name_policy(&self) -> NamePolicy {
+        match (self.no_name_check, self.strict_names) {
+            (true, _) => NamePolicy::Ignore,
+            (_, true) => NamePolicy::Fail,
+            _ => NamePolicy::Maybe,
+        }
+    }
+
+    pub(crate) fn get_paste(&self) -> bool {
+        #[cfg(all(feature = "paste", not(target_family = "wasm")))]
+        {
+            self.paste
+        }
+        #[cfg(not(all(feature = "paste", not(target_family = "wasm"))))]
+        {
+            false
+        }
+    }
+
+    /// The output format, taking into account the options which are shorthand for one
+    fn output_format(&self) -> display::OutputFormat {
+        match (self.quiet, self.json, self.tag) {
+            (0, true, _) => display::OutputFormat::Json,
+            (0, _, true) => display::OutputFormat::Tag,
+            (0, false, false) => self.format,
+            (1, _, _) => display::OutputFormat::ResultOnly,
+            _ => display::OutputFormat::Silent,
+        }
+    }
+}
+
+/// Entry point - run the program and handle errors ourselves cleanly.
+///
+/// A failed verification exits with its own code once the results have been finished off. Any
+/// other error is printed and the application terminates with a non-zero return code.
+pub fn main() {
+    match hashgood() {
+        Ok(()) => exit(0),
+        Err(HashgoodError::Mismatch) => exit(EXIT_MISMATCH),
+        Err(e) => {
+            display::bail_out(&e.to_string());
+            // Keep whatever was hashed before the error
+            if let Err(e) = cache::save() {
+                eprintln!("Error: {}", e);
+            }
+            process::exit(EXIT_ERR);
+        }
+    }
+}
+
+/// Finish off the output, such as the plan at the end of TAP, and exit with the given code
+fn exit(code: i32) -> ! {
+    if let Err(e) = display::finish().and_then(|_| Ok(cache::save()?)) {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_ERR);
+    }
+    process::exit(code);
+}
+
+/// Main application logic
+fn hashgood() -> Result<(), HashgoodError> {
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|a| a == "--generate-man")
+    {
+        // Hidden, for packagers to build the man page
+        print!("{}", man::man_page(Command::clap(), SUBCOMMANDS));
+        return Ok(());
+    }
+    let opt = match parse_command()? {
+        Command::Verify(opt) => opt,
+        Command::Hash(opt) => {
+            if count_hash_methods(&opt) > 0 {
+                return Err(HashgoodError::Usage(
+                    "Error: hashgood hash only calculates digests. Use hashgood verify to check them."
+                        .to_owned(),
+                ));
+            }
+            opt
+        }
+        Command::Check(mut opt) => {
+            if opt.check_all.is_none() {
+                opt.check_all = opt.input.take();
+            }
+            opt
+        }
+        Command::Bench(bench_opt) => return bench(&bench_opt),
+        Command::Completions { shell } => {
+            Command::clap().gen_completions_to("hashgood", shell, &mut std::io::stdout());
+            return Ok(());
+        }
+    };
+    let mut opt = get_verified_options(opt).map_err(HashgoodError::Usage)?;
+    if opt.save.as_ref().and_then(|s| s.to_str()) == Some("-") {
+        // Standard output is carrying the data, so keep our results out of the way
+        display::redirect_to_stderr();
+    }
+    // From here on, no_colour takes --color into account as well
+    opt.no_colour |= !display::use_colour(opt.colour);
+    display::set_verbosity(opt.verbose);
+    display::set_theme(opt.theme.clone());
+    display::set_group_size(opt.group.unwrap_or(0));
+    display::set_alerts(opt.bell, opt.banner);
+    if let Some(report) = &opt.report {
+        display::set_report(report)?;
+    }
+    if let Some(output) = &opt.output {
+        display::set_output(output);
+    }
+    display::set_format(opt.output_format());
+    if let Some(size) = opt.buffer_size {
+        calculate::set_buffer_size(size);
+    }
+    if opt.no_cache_pollution {
+        calculate::set_drop_from_cache();
+    }
+    if let Some(cache) = &opt.cache {
+        cache::set_cache(cache)?;
+    }
+    if opt.direct {
+        calculate::set_direct()?;
+    }
+    if let Some(seconds) = opt.timeout {
+        verify::set_timeout(seconds);
+    }
+    if opt.no_warn {
+        policy::set_policy("off").map_err(HashgoodError::Usage)?;
+    } else if let Some(warn_level) = &opt.warn_level {
+        policy::set_policy(warn_level).map_err(HashgoodError::Usage)?;
+    }
+    if opt.sri {
+        display::set_encoding(display::DigestEncoding::Sri);
+    } else if opt.base64 {
+        display::set_encoding(display::DigestEncoding::Base64);
+    }
+    let mut candidate_messages = vec![];
+    let mut candidates = verify::get_candidate_hashes(&opt, &mut candidate_messages)?;
+    display::log(1, || match &candidates {
+        Some(c) => format!(
+            "Verifying against {} {} hash(es) from {}",
+            c.hashes.len(),
+            display::algorithm_name(c.alg),
+            display::describe_source(&c.source, &None)
+        ),
+        None => "No hash was provided, so digests are only calculated".to_owned(),
+    });
+    display::print_messages(candidate_messages, opt.no_colour)?;
+    if opt.tree_hash {
+        // A tree hash is indistinguishable from a regular SHA-256 by length alone
+        if let Some(c) = &mut candidates {
+            if c.alg != Algorithm::Sha256 {
+                return Err(HashgoodError::InvalidHash(
+                    "Provided hash is not the length of a SHA-256 tree hash".to_owned(),
+                ));
+            }
+            c.alg = Algorithm::Sha256Tree;
+        }
+    }
+    let hmac_key = get_hmac_key(&opt)?;
+    if let (Some(_), Some(candidates)) = (&opt.check_all, &candidates) {
+        if !check_all(&opt, candidates, hmac_key.as_deref())? {
+            return Err(HashgoodError::Mismatch);
+        }
+        return Ok(());
+    }
+    if let (true, Some(input)) = (opt.image, &opt.input) {
+        if !verify_image(&opt, input)? {
+            return Err(HashgoodError::Mismatch);
+        }
+        return Ok(());
+    }
+    if let (Some(reference), Some(input)) = (&opt.compare, &opt.input) {
+        if reference.is_dir() && input.is_dir() {
+            if !compare_trees(&opt, input, reference, hmac_key.as_deref())? {
+                return Err(HashgoodError::Mismatch);
+            }
+            return Ok(());
+        }
+    }
+    let mut input_messages = vec![];
+    let inputs = input::get_inputs(&opt, &mut input_messages)?;
+    display::print_messages(input_messages, opt.no_colour)?;
+    if ((inputs.len() > 1 && !opt.concat) || opt.archive) && opt.save.is_some() {
+        return Err(HashgoodError::Usage(
+            "Error: --save can only be used with a single input".to_owned(),
+        ));
+    }
+    if (inputs.len() > 1 || opt.archive) && opt.checkpoint.is_some() {
+        return Err(HashgoodError::Usage(
+            "Error: --checkpoint can only be used with a single input".to_owned(),
+        ));
+    }
+    let mut checkpoint = match &opt.checkpoint {
+        Some(path) => Some(Checkpoint::open(path, &inputs[0].path, &inputs[0].name)?),
+        None => None,
+    };
+    if let (Some(_), Some(c)) = (&checkpoint, &candidates) {
+        if let VerificationSource::TorrentFile { .. } = c.source {
+            return Err(HashgoodError::Usage(
+                "Error: --checkpoint cannot be used to verify a torrent".to_owned(),
+            ));
+        }
+    }
+    let resume_offset = checkpoint.as_ref().map(|c| c.offset);
+    if let Some(reference) = &opt.compare {
+        if inputs.len() > 1 || opt.archive {
+            return Err(HashgoodError::Usage(
+                "Error: --compare can only be used with a single input".to_owned(),
+            ));
+        }
+        let reference = input::Input {
+            path: reference.clone(),
+            name: reference.to_string_lossy().into_owned(),
+        };
+        if !compare_files(&opt, &inputs[0], &reference, hmac_key.as_deref())? {
+            return Err(HashgoodError::Mismatch);
+        }
+        return Ok(());
+    }
+    let mut all_matched = true;
+    let mut first = true;
+    // Checksums stored with each input are all read before any hashing starts
+    let mut stored_candidates = HashMap::new();
+    if opt.from_xattr {
+        for input in &inputs {
+            let stored = verify::get_from_xattr(&input.path, opt.xattr_name.as_deref())?;
+            stored_candidates.insert(input.name.clone(), stored);
+        }
+    }
+    let parallel = jobs(&opt) > 1 && inputs.len() > 1 && !opt.concat && !opt.archive && !opt.watch;
+    if parallel {
+        // Inputs may finish in any order, so names are checked for hashes in advance like xattrs
+        if opt.from_name {
+            for input in &inputs {
+                let named = verify::get_from_name(&input.name)?;
+                stored_candidates.insert(input.name.clone(), named);
+            }
+        }
+        // Several progress lines would overwrite each other
+        progress::hide_progress();
+    }
+    let mut inputs = inputs;
+    let whole_files = !opt.concat && !opt.archive && opt.offset.is_none() && opt.length.is_none();
+    if let (true, Some(c)) = (whole_files, &candidates) {
+        // A file with the wrong size can't match, so there is no need to spend time hashing it
+        let mut wrong_sizes = vec![];
+        inputs.retain(|input| match verify::check_listed_size(c, input) {
+            Some(problem) => {
+                wrong_sizes.push(problem);
+                false
+            }
+            None => true,
+        });
+        for problem in wrong_sizes {
+            if !first {
+                display::print_separator(opt.no_colour)?;
+            }
+            first = false;
+            all_matched = false;
+            print_result(
+                Verification {
+                    match_level: MatchLevel::Fail,
+                    comparison_hash: None,
+                    messages: vec![(MessageLevel::Error, problem)],
+                },
+                opt.no_colour,
+            )?;
+        }
+    }
+    let hash_one =
+        &mut |name: &str, path: Option<&Path>, data: InputData| -> Result<(), HashgoodError> {
+            if !first && (candidates.is_some() || opt.from_name || opt.from_xattr) {
+                // Separate each result from the previous one
+                display::print_separator(opt.no_colour)?;
+            }
+            first = false;
+            let named_candidates = match opt.from_name {
+                true => Some(verify::get_from_name(name)?),
+                false => None,
+            };
+            let candidates = named_candidates
+                .as_ref()
+                .or(stored_candidates.get(name))
+                .or(candidates.as_ref());
+            all_matched &= hash_data(
+                &opt,
+                name,
+                path,
+                data,
+                candidates,
+                hmac_key.as_deref(),
+                checkpoint.as_mut(),
+            )?;
+            Ok(())
+        };
+    if opt.concat {
+        // The parts are hashed as though they had already been joined into one file
+        let data = get_concatenated_data(&opt, &inputs)?;
+        hash_one(&input::joined_name(&inputs[0].name), None, data)?;
+    } else if parallel {
+        // Several inputs are read at once but each result is printed in full, in order
+        let candidates_for = |name: &str| stored_candidates.get(name).or(candidates.as_ref());
+        let digest_one = |input: &input::Input| {
+            let digests = get_input_data(&opt, input, None).and_then(|data| {
+                let candidates = candidates_for(&input.name);
+                let path = Some(input.path.as_path());
+                digest_data(&opt, path, data, candidates, hmac_key.as_deref(), None)
+            });
+            (input.name.clone(), digests)
+        };
+        let report =
+            |(name, digests): (String, Result<_, HashgoodError>)| -> Result<(), HashgoodError> {
+                if !first && (candidates.is_some() || opt.from_name || opt.from_xattr) {
+                    display::print_separator(opt.no_colour)?;
+                }
+                first = false;
+                let candidates = candidates_for(&name);
+                all_matched &=
+                    report_digests(&opt, &name, digests?, candidates, hmac_key.as_deref())?;
+                Ok(())
+            };
+        calculate::for_each_parallel(jobs(&opt), inputs.iter().collect(), digest_one, report)?;
+    } else {
+        let mut hash_input = |input: &input::Input| {
+            if opt.archive {
+                archive::for_each_member(&input.path, &mut |name, data| hash_one(name, None, data))
+            } else {
+                let data = get_input_data(&opt, input, resume_offset)?;
+                hash_one(&input.name, Some(&input.path), data)
+            }
+        };
+        for input in &inputs {
+            hash_input(input)?;
+        }
+        if opt.watch {
+            // Results are only informational while watching, so a mismatch doesn't end the program
+            return watch::watch_inputs(&inputs, &mut hash_input);
+        }
+    }
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.remove()?;
+    }
+    display::print_summary(opt.no_colour)?;
+    if !all_matched {
+        return Err(HashgoodError::Mismatch);
+    }
+    Ok(())
+}
+
+/// How many inputs to hash at the same time
+fn jobs(opt: &Opt) -> usize {
+    opt.jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Open an input for hashing, applying any options which affect how it is read. When resuming from
+/// a checkpoint, reading starts from `resume_offset` within the region being hashed.
+fn get_input_data<'a>(
+    opt: &Opt,
+    input: &input::Input,
+    resume_offset: Option<u64>,
+) -> Result<InputData<'a>, HashgoodError> {
+    let region_only = opt.offset.is_some() || opt.length.is_some();
+    if opt.mmap && input.path.is_file() && opt.save.is_none() && !region_only {
+        return Ok(InputData::Mapped(calculate::map_input(&input.path)?));
+    }
+    if !region_only && opt.save.is_none() && resume_offset.is_none() && !opt.direct {
+        if let Some(file) = calculate::open_sparse(&input.path) {
+            return Ok(InputData::Sparse(file));
+        }
+    }
+    let start = opt.offset.unwrap_or(0);
+    let resume_offset = resume_offset.unwrap_or(0);
+    let mut reader = match start + resume_offset {
+        0 => calculate::get_input_reader(&input.path)?,
+        offset => calculate::get_input_reader_at(&input.path, offset)?,
+    };
+    if let Some(length) = opt.length {
+        reader = Box::new(reader.take(length.saturating_sub(resume_offset)));
+    }
+    // Reading a large file or a whole device can take a while, so show how far through it we are
+    let input_len = match calculate::is_block_device(&input.path) {
+        true => calculate::get_block_device_len(&input.path),
+        false => input
+            .path
+            .metadata()
+            .ok()
+            .filter(|m| m.is_file())
+            .map(|m| m.len()),
+    };
+    if let (Some(input_len), true) = (input_len, progress::can_show_progress()) {
+        let remaining = input_len.saturating_sub(start);
+        let total = opt.length.map_or(remaining, |l| l.min(remaining));
+        let total = total.saturating_sub(resume_offset);
+        reader = Box::new(progress::ProgressReader::new(reader, total));
+    }
+    let reader = save_copy(opt, reader)?;
+    match calculate::is_fast_input(&input.path) {
+        true => Ok(InputData::FastStream(reader)),
+        false => Ok(InputData::Stream(reader)),
+    }
+}
+
+/// Open all of the inputs as a single stream of data, one after another
+fn get_concatenated_data<'a>(
+    opt: &Opt,
+    inputs: &[input::Input],
+) -> Result<InputData<'a>, HashgoodError> {
+    let mut reader: Box<dyn Read> = Box::new(std::io::empty());
+    for input in inputs {
+        reader = Box::new(reader.chain(calculate::get_input_reader(&input.path)?));
+    }
+    if let Some(length) = opt.length {
+        reader = Box::new(reader.take(length));
+    }
+    Ok(InputData::Stream(save_copy(opt, reader)?))
+}
+
+/// If requested, copy the data to a file or standard output as it is read
+fn save_copy<'a>(
+    opt: &Opt,
+    reader: Box<dyn Read + 'a>,
+) -> Result<Box<dyn Read + 'a>, HashgoodError> {
+    Ok(match opt.save.as_deref() {
+        Some(save) if save.to_str() == Some("-") => {
+            Box::new(calculate::TeeReader::new(reader, std::io::stdout()))
+        }
+        Some(save) => {
+            let file = File::create(save)
+                .map_err(|e| format!("Unable to create '{}': {}", save.to_string_lossy(), e))?;
+            Box::new(calculate::TeeReader::new(reader, file))
+        }
+        None => reader,
+    })
+}
+
+/// Calculate the digests of one input and verify them against the candidates, if there are any.
+/// Returns whether the input was successfully verified, which is always true with no candidates.
+fn hash_data(
+    opt: &Opt,
+    name: &str,
+    path: Option<&Path>,
+    data: InputData,
+    candidates: Option<&CandidateHashes>,
+    hmac_key: Option<&[u8]>,
+    checkpoint: Option<&mut Checkpoint>,
+) -> Result<bool, HashgoodError> {
+    let digests = digest_data(opt, path, data, candidates, hmac_key, checkpoint)?;
+    report_digests(opt, name, digests, candidates, hmac_key)
+}
+
+/// Calculate the digests of one input which are needed to verify it against the candidates. With
+/// no candidates, the usual set of digests is calculated for output. If the input is a file on
+/// disk which is in the cache, its digests are taken from there instead.
+fn digest_data(
+    opt: &Opt,
+    path: Option<&Path>,
+    data: InputData,
+    candidates: Option<&CandidateHashes>,
+    hmac_key: Option<&[u8]>,
+    checkpoint: Option<&mut Checkpoint>,
+) -> calculate::CalculateResult {
+    if let Some(VerificationSource::TorrentFile { piece_length, .. }) =
+        candidates.map(|c| &c.source)
+    {
+        // A torrent lists the hash of each piece rather than the whole input
+        if hmac_key.is_some() {
+            return Err(HashgoodError::Usage(
+                "HMAC cannot be used to verify a torrent".to_owned(),
+            ));
+        }
+        return calculate::create_piece_digests(*piece_length, data);
+    }
+    let algorithms = match candidates {
+        // If we have a candidate hash of a particular type, use that specific algorithm
+        Some(c) => vec![c.alg],
+        // If no candidate, calculate all three common digest types for output
+        None if opt.tree_hash => vec![Algorithm::Sha256Tree],
+        None if !opt.selected_algorithms().is_empty() => opt.selected_algorithms(),
+        // A digests file has to use the same algorithm throughout
+        None if opt.output_format().is_digests_file() => vec![Algorithm::Sha256],
+        None if opt.sri => vec![Algorithm::Sha256, Algorithm::Sha384, Algorithm::Sha512],
+        None => opt
+            .default_algorithms
+            .clone()
+            .unwrap_or_else(|| vec![Algorithm::Md5, Algorithm::Sha1, Algorithm::Sha256]),
+    };
+    let algorithms = match (opt.fuzzy, candidates) {
+        (true, None) => [algorithms, vec![Algorithm::Ssdeep]].concat(),
+        _ => algorithms,
+    };
+    let cache_key = path.and_then(cache::CacheKey::new);
+    if let Some(hashes) = cache_key.as_ref().and_then(|k| k.lookup(&algorithms)) {
+        display::log(1, || {
+            let path = path.map(Path::to_string_lossy).unwrap_or_default();
+            format!("Using cached digests of '{}'", path)
+        });
+        return Ok(calculate::Digests {
+            hashes,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+        });
+    }
+    let digests = match (hmac_key, checkpoint) {
+        (Some(key), _) => calculate::create_hmacs(&algorithms, key, data),
+        (None, Some(checkpoint)) => {
+            calculate::create_resumable_digests(&algorithms, data, checkpoint)
+        }
+        (None, None) => calculate::create_digests(&algorithms, data),
+    }?;
+    if let Some(key) = cache_key {
+        key.store(&digests.hashes);
+    }
+    Ok(digests)
+}
+
+/// Print the digests of one input and verify them against the candidates, if there are any.
+/// Returns whether the input was successfully verified, which is always true with no candidates.
+fn report_digests(
+    opt: &Opt,
+    name: &str,
+    mut digests: calculate::Digests,
+    candidates: Option<&CandidateHashes>,
+    hmac_key: Option<&[u8]>,
+) -> Result<bool, HashgoodError> {
+    let mut matched = true;
+    if let Some(c) = candidates {
+        if let VerificationSource::TorrentFile { .. } = c.source {
+            let (alg, bytes) = digests.hashes.remove(0);
+            let hash = Hash::new(alg, bytes, name);
+            let verification = verify::verify_pieces(&hash, c, opt.name_policy());
+            display::print_pieces(&hash, c, opt.no_colour)?;
+            display::print_throughput(digests.bytes, digests.elapsed, opt.no_colour)?;
+            return print_result(verification, opt.no_colour);
+        }
+        for (alg, bytes) in digests.hashes {
+            // Should always be true
+            if c.alg == alg {
+                let hash = Hash {
+                    keyed: hmac_key.is_some(),
+                    ..Hash::new(alg, bytes, name)
+                };
+                let verification = verify::verify_hash(&hash, c, opt.name_policy());
+                display::print_hash(
+                    &hash,
+                    verification.comparison_hash,
+                    Some(&c.source),
+                    opt.no_colour,
+                )?;
+                display::print_throughput(digests.bytes, digests.elapsed, opt.no_colour)?;
+                matched &= print_result(verification, opt.no_colour)?;
+            }
+        }
+    } else {
+        for (alg, bytes) in digests.hashes {
+            let hash = Hash {
+                keyed: hmac_key.is_some(),
+                ..Hash::new(alg, bytes, name)
+            };
+            display::print_hash(&hash, None, None, opt.no_colour)?;
+        }
+        display::print_throughput(digests.bytes, digests.elapsed, opt.no_colour)?;
+    }
+    Ok(matched)
+}
+
+/// Hash the input and a reference file concurrently, then verify the input against the reference.
+/// Returns whether they matched.
+fn compare_files(
+    opt: &Opt,
+    input: &input::Input,
+    reference: &input::Input,
+    hmac_key: Option<&[u8]>,
+) -> Result<bool, HashgoodError> {
+    let alg = compare_algorithm(opt);
+    let (bytes, reference_bytes) = digest_pair(opt, alg, input, reference, hmac_key);
+    let candidates = CandidateHashes {
+        alg,
+        hashes: vec![CandidateHash {
+            bytes: reference_bytes?,
+            filename: None,
+            size: None,
+        }],
+        source: VerificationSource::ComparedFile(reference.name.clone()),
+    };
+    let hash = Hash {
+        keyed: hmac_key.is_some(),
+        ..Hash::new(alg, bytes?, &input.name)
+    };
+    let verification = verify::verify_hash(&hash, &candidates, opt.name_policy());
+    display::print_hash(
+        &hash,
+        verification.comparison_hash,
+        Some(&candidates.source),
+        opt.no_colour,
+    )?;
+    print_result(verification, opt.no_colour)
+}
+
+/// Hash every file in two directory trees and report the files which are missing, extra or
+/// different in the input compared to the reference. Returns whether the trees are identical.
+fn compare_trees(
+    opt: &Opt,
+    input_dir: &Path,
+    reference_dir: &Path,
+    hmac_key: Option<&[u8]>,
+) -> Result<bool, HashgoodError> {
+    let mut messages = vec![];
+    let inputs = input::get_directory_inputs(input_dir, opt, &mut messages)?;
+    let references = input::get_directory_inputs(reference_dir, opt, &mut messages)?;
+    display::print_messages(messages, opt.no_colour)?;
+
+    let alg = compare_algorithm(opt);
+    let by_name: HashMap<&str, &input::Input> =
+        inputs.iter().map(|i| (i.name.as_str(), i)).collect();
+    let mut differences = vec![];
+    let mut identical = 0;
+    for reference in &references {
+        match by_name.get(reference.name.as_str()) {
+            Some(input) => {
+                let (bytes, reference_bytes) = digest_pair(opt, alg, input, reference, hmac_key);
+                if bytes? == reference_bytes? {
+                    identical += 1;
+                } else {
+                    differences.push((reference.name.clone(), TreeDifference::Different));
+                }
+            }
+            None => differences.push((reference.name.clone(), TreeDifference::Missing)),
+        }
+    }
+    let reference_names: HashSet<&str> = references.iter().map(|r| r.name.as_str()).collect();
+    for input in &inputs {
+        if !reference_names.contains(input.name.as_str()) {
+            differences.push((input.name.clone(), TreeDifference::Extra));
+        }
+    }
+    differences.sort_by(|a, b| a.0.cmp(&b.0));
+
+    display::print_tree_differences(
+        &input_dir.to_string_lossy(),
+        &reference_dir.to_string_lossy(),
+        alg,
+        hmac_key.is_some(),
+        &differences,
+        identical,
+        opt.no_colour,
+    )?;
+    let match_level = match differences.is_empty() {
+        true => MatchLevel::Ok,
+        false => MatchLevel::Fail,
+    };
+    display::print_match_level(match_level, opt.no_colour)?;
+    Ok(differences.is_empty())
+}
+
+/// Verify every blob in a container image against the digest it is stored under. Returns whether
+/// all of the blobs referenced by the image were present and correct.
+fn verify_image(opt: &Opt, path: &Path) -> Result<bool, HashgoodError> {
+    let image_name = path.to_string_lossy();
+    let mut all_matched = true;
+    let mut first = true;
+    let unverified = image::for_each_blob(path, &mut |name, alg, digest, data| {
+        if !first {
+            display::print_separator(opt.no_colour)?;
+        }
+        first = false;
+        let candidates = CandidateHashes {
+            alg,
+            hashes: vec![CandidateHash {
+                bytes: digest,
+                filename: None,
+                size: None,
+            }],
+            source: VerificationSource::ContainerImage(image_name.to_string()),
+        };
+        all_matched &= hash_data(opt, name, None, data, Some(&candidates), None, None)?;
+        Ok(())
+    })?;
+    if !unverified.is_empty() {
+        if !first {
+            display::print_separator(opt.no_colour)?;
+        }
+        let messages = unverified
+            .into_iter()
+            .map(|m| (MessageLevel::Error, m))
+            .collect();
+        display::print_messages(messages, opt.no_colour)?;
+        display::print_match_level(MatchLevel::Fail, opt.no_colour)?;
+        return Ok(false);
+    }
+    Ok(all_matched)
+}
+
+/// Verify every file listed in a digests file against its digest. Returns whether all of them
+/// matched.
+fn check_all(
+    opt: &Opt,
+    candidates: &CandidateHashes,
+    hmac_key: Option<&[u8]>,
+) -> Result<bool, HashgoodError> {
+    let listed = matches!(candidates.source, VerificationSource::DigestsFile(_));
+    if !listed || candidates.hashes.iter().any(|c| c.filename.is_none()) {
+        return Err(HashgoodError::Usage(
+            "--check-all requires a digests file listing each filename".to_owned(),
+        ));
+    }
+    let mut counts = [0; 4];
+    let mut skipped = 0;
+    let check = |candidate: &CandidateHash| {
+        let name = candidate.filename.as_deref().unwrap_or_default();
+        let input = input::Input {
+            path: PathBuf::from(name),
+            name: name.to_owned(),
+        };
+        let status = match input.path.exists() {
+            true => Some(check_file(opt, candidate, candidates.alg, &input, hmac_key)),
+            false if opt.ignore_missing => None,
+            false => Some(CheckStatus::Missing),
+        };
+        (name.to_owned(), status)
+    };
+    let report = |(name, status): (String, Option<CheckStatus>)| -> Result<(), HashgoodError> {
+        let status = match status {
+            Some(status) => status,
+            None => {
+                skipped += 1;
+                return Ok(());
+            }
+        };
+        counts[match status {
+            CheckStatus::Ok => 0,
+            CheckStatus::Failed => 1,
+            CheckStatus::Missing => 2,
+            CheckStatus::Unreadable(_) => 3,
+        }] += 1;
+        display::print_check_status(&name, &status, opt.no_colour)?;
+        Ok(())
+    };
+    if jobs(opt) > 1 {
+        progress::hide_progress();
+    }
+    let listed = candidates.hashes.iter().collect();
+    calculate::for_each_parallel(jobs(opt), listed, check, report)?;
+    let keyed = hmac_key.is_some();
+    display::print_check_summary(candidates, keyed, counts, skipped, opt.no_colour)?;
+    // Skipping everything isn't a successful verification
+    let all_ok = counts[0] == candidates.hashes.len() - skipped && counts[0] > 0;
+    if counts[0] == 0 && skipped > 0 {
+        let message = "None of the listed files were found".to_owned();
+        display::print_messages(vec![(MessageLevel::Error, message)], opt.no_colour)?;
+    }
+    let match_level = match all_ok {
+        true => MatchLevel::Ok,
+        false => MatchLevel::Fail,
+    };
+    display::print_match_level(match_level, opt.no_colour)?;
+    Ok(all_ok)
+}
+
+/// Verify one of the files listed in a digests file
+fn check_file(
+    opt: &Opt,
+    candidate: &CandidateHash,
+    alg: Algorithm,
+    input: &input::Input,
+    hmac_key: Option<&[u8]>,
+) -> CheckStatus {
+    if verify::wrong_size(candidate, &input.path).is_some() {
+        return CheckStatus::Failed;
+    }
+    match digest_input(opt, alg, input, hmac_key) {
+        Ok(bytes) => {
+            let hash = Hash {
+                keyed: hmac_key.is_some(),
+                ..Hash::new(alg, bytes, &input.name)
+            };
+            match verify::digests_equal(&hash, candidate) {
+                true => CheckStatus::Ok,
+                false => CheckStatus::Failed,
+            }
+        }
+        Err(e) => CheckStatus::Unreadable(e),
+    }
+}
+
+/// The algorithm used to compare files directly with each other
+fn compare_algorithm(opt: &Opt) -> Algorithm {
+    if opt.tree_hash {
+        Algorithm::Sha256Tree
+    } else if opt.fuzzy {
+        Algorithm::Ssdeep
+    } else {
+        Algorithm::Sha256
+    }
+}
+
+/// Calculate the same digest of two inputs concurrently
+fn digest_pair(
+    opt: &Opt,
+    alg: Algorithm,
+    input: &input::Input,
+    reference: &input::Input,
+    hmac_key: Option<&[u8]>,
+) -> (Result<Vec<u8>, String>, Result<Vec<u8>, String>) {
+    let digest = |input| digest_input(opt, alg, input, hmac_key);
+    if cfg!(target_family = "wasm") {
+        // There are no threads to hash them at the same time
+        return (digest(input), digest(reference));
+    }
+    std::thread::scope(|s| {
+        let reference = s.spawn(|| digest(reference));
+        (digest(input), reference.join().unwrap())
+    })
+}
+
+/// Calculate a single digest of an input
+fn digest_input(
+    opt: &Opt,
+    alg: Algorithm,
+    input: &input::Input,
+    hmac_key: Option<&[u8]>,
+) -> Result<Vec<u8>, String> {
+    let cache_key = cache::CacheKey::new(&input.path);
+    if let Some(mut hashes) = cache_key.as_ref().and_then(|k| k.lookup(&[alg])) {
+        display::log(1, || format!("Using cached digest of '{}'", input.name));
+        return Ok(hashes.remove(0).1);
+    }
+    let data = get_input_data(opt, input, None).map_err(|e| e.to_string())?;
+    let hashes = match hmac_key {
+        Some(key) => calculate::create_hmacs(&[alg], key, data),
+        None => calculate::create_digests(&[alg], data),
+    };
+    let mut digests = hashes.map_err(|e| e.to_string())?;
+    if let Some(key) = cache_key {
+        key.store(&digests.hashes);
+    }
+    display::count_read(digests.bytes, digests.elapsed);
+    Ok(digests.hashes.remove(0).1)
+}
+
+/// Print the outcome of a verification and return whether it was a match
+fn print_result(verification: Verification, no_colour: bool) -> Result<bool, HashgoodError> {
+    let successful_match = verification.match_level == MatchLevel::Ok;
+    display::print_messages(verification.messages, no_colour)?;
+    display::print_match_level(verification.match_level, no_colour)?;
+    Ok(successful_match)
+}
+
+/// Obtain the secret key for HMAC mode, if one was specified. A value beginning with `@` is treated
+/// as the path to a file containing the key, in which case a trailing newline is ignored.
+fn get_hmac_key(opt: &Opt) -> Result<Option<Vec<u8>>, String> {
+    let key = match &opt.hmac_key {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+    match key.strip_prefix('@') {
+        Some(path) => {
+            let mut bytes = std::fs::read(path)
+                .map_err(|e| format!("Unable to read HMAC key file '{}': {}", path, e))?;
+            if bytes.ends_with(b"\n") {
+                bytes.pop();
+                if bytes.ends_with(b"\r") {
+                    bytes.pop();
+                }
+            }
+            Ok(Some(bytes))
+        }
+        None => Ok(Some(key.as_bytes().to_vec())),
+    }
+}
+
+/// Parse the command line, treating it as `hashgood verify` if there is no subcommand, and fill
+/// in any options which weren't given from the config file
+fn parse_command() -> Result<Command, HashgoodError> {
+    let mut args: Vec<_> = std::env::args_os().collect();
+    let word = args.get(1).and_then(|a| a.to_str());
+    if !word.is_some_and(|w| SUBCOMMANDS.contains(&w) || COMMAND_WORDS.contains(&w)) {
+        args.insert(1.min(args.len()), "verify".into());
+    }
+    let version = long_version();
+    let matches = Command::clap()
+        .long_version(version.as_str())
+        .get_matches_from(args);
+    let mut command = Command::from_clap(&matches);
+    if let (Command::Verify(opt) | Command::Hash(opt) | Command::Check(opt), Some(sub)) =
+        (&mut command, matches.subcommand().1)
+    {
+        apply_config(opt, config::load()?, sub);
+    }
+    Ok(command)
+}
+
+/// The build information printed by `--version`, for bug reports
+fn long_version() -> String {
+    let features = [
+        (
+            "paste",
+            cfg!(all(feature = "paste", not(target_family = "wasm"))),
+        ),
+        ("url", cfg!(feature = "url")),
+        ("archive", cfg!(feature = "archive")),
+        ("watch", cfg!(feature = "watch")),
+        ("xxhash", cfg!(feature = "xxhash")),
+        ("async", cfg!(feature = "async")),
+        ("serde", cfg!(feature = "serde")),
+    ];
+    let features: Vec<_> = features
+        .iter()
+        .filter_map(|(name, enabled)| enabled.then_some(*name))
+        .collect();
+    let algorithms: Vec<_> = calculate::available_algorithms()
+        .map(display::algorithm_name)
+        .collect();
+    format!(
+        "{}\nfeatures: {}\nalgorithms: {}\nSHA-1/SHA-256: {}\ntarget: {}-{}",
+        env!("CARGO_PKG_VERSION"),
+        match features.is_empty() {
+            true => "none".to_owned(),
+            false => features.join(", "),
+        },
+        algorithms.join(", "),
+        calculate::sha_backend(),
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+/// Use the settings from the config file for anything which wasn't given on the command line
+fn apply_config(opt: &mut Opt, config: Config, matches: &ArgMatches) {
+    let given = |name| matches.occurrences_of(name) > 0;
+    if let Some(no_colour) = config.no_colour {
+        if !given("no-colour") && !given("colour") {
+            opt.no_colour = no_colour;
+        }
+    }
+    if let Some(theme) = config.theme {
+        if !given("theme") {
+            opt.theme = theme;
+        }
+    }
+    opt.buffer_size = opt.buffer_size.or(config.buffer_size);
+    opt.default_algorithms = config.algorithms;
+}
+
+/// How many different sources of hashes to verify against were given
+fn count_hash_methods(opt: &Opt) -> i32 {
+    opt.hash.is_some() as i32
+        + opt.get_paste() as i32
+        + opt.hash_file.is_some() as i32
+        + opt.github.is_some() as i32
+        + opt.auto_sums as i32
+        + opt.interactive as i32
+        + opt.check_all.is_some() as i32
+        + opt.from_name as i32
+        + opt.from_xattr as i32
+        + opt.compare.is_some() as i32
+}
+
+/// Hash a buffer in memory with each algorithm in turn and print how quickly it went
+fn bench(opt: &BenchOpt) -> Result<(), HashgoodError> {
+    let len = match opt.size.checked_mul(1024 * 1024) {
+        Some(len) if (1..=MAX_BENCH_SIZE).contains(&opt.size) => len,
+        _ => {
+            return Err(HashgoodError::Usage(format!(
+                "Size must be between 1 and {} MiB",
+                MAX_BENCH_SIZE
+            )))
+        }
+    };
+    let mut data = vec![];
+    data.try_reserve_exact(len).map_err(|_| {
+        HashgoodError::Other(format!("Unable to allocate {} MiB to hash", opt.size))
+    })?;
+    data.resize(len, 0x5a);
+    for alg in calculate::available_algorithms() {
+        let start = Instant::now();
+        let input = InputData::FastStream(Box::new(data.as_slice()));
+        calculate::create_digests(&[alg], input)?;
+        let seconds = start.elapsed().as_secs_f64();
+        println!(
+            "{:<18} {:>8.1} MiB/s",
+            display::algorithm_name(alg),
+            opt.size as f64 / seconds
+        );
+    }
+    Ok(())
+}
+
+/// Check the parsed command line options for ambiguous or inconsistent settings
+fn get_verified_options(opt: Opt) -> Result<Opt, String> {
+    let hash_methods = count_hash_methods(&opt);
+    if hash_methods > 1 {
+        if opt.hash.is_some() {
+            eprintln!("* specified as command line argument");
+        }
+        if opt.get_paste() {
+            eprintln!("* paste from clipboard (-p)")
+        }
+        if opt.hash_file.is_some() {
+            eprintln!("* check hash from file (-c)")
+        }
+        if opt.github.is_some() {
+            eprintln!("* checksums from a GitHub release (--github)")
+        }
+        if opt.auto_sums {
+            eprintln!("* checksums published next to the URL (--auto-sums)")
+        }
+        if opt.interactive {
+            eprintln!("* typed in at a prompt (--interactive)")
+        }
+        if opt.check_all.is_some() {
+            eprintln!("* check every file in a digests file (--check-all)")
+        }
+        if opt.from_name {
+            eprintln!("* hash in the input filename (--from-name)")
+        }
+        if opt.from_xattr {
+            eprintln!("* checksum in an extended attribute (--from-xattr)")
+        }
+        if opt.compare.is_some() {
+            eprintln!("* compare with another file (--compare)")
+        }
+        return Err("Error: Hashes were provided by multiple methods. Use only one.".to_owned());
+    }
+    if opt.auto_sums && opt.input.as_deref().and_then(input::as_url).is_none() {
+        return Err("Error: --auto-sums requires the input to be an HTTP(S) URL".to_owned());
+    }
+    let selected = opt.selected_algorithms();
+    if !selected.is_empty() {
+        if hash_methods > 0 {
+            return Err(
+                "Error: Digest flags such as --sha256 only apply when there is no hash to verify"
+                    .to_owned(),
+            );
+        }
+        if selected.len() > 1 && opt.output_format().is_digests_file() {
+            return Err("Error: A digests file can only use one algorithm".to_owned());
+        }
+        if opt.sri
+            && selected
+                .iter()
+                .any(|a| matches!(a, Algorithm::Md5 | Algorithm::Sha1))
+        {
+            return Err("Error: --sri only supports SHA-256, SHA-384 and SHA-512".to_owned());
+        }
+    }
+    if opt.interactive && !std::io::stdin().is_terminal() {
+        return Err("Error: --interactive requires standard input to be a terminal".to_owned());
+    }
+    if opt.interactive && opt.input.as_ref().and_then(|i| i.to_str()) == Some("-") {
+        return Err("Error: Cannot use stdin for both the prompt and input data".to_owned());
+    }
+    if opt.cache.is_some() {
+        // The digests in the cache are of whole files, read as they are
+        let conflicts = [
+            ("--hmac-key", opt.hmac_key.is_some()),
+            ("--checkpoint", opt.checkpoint.is_some()),
+            ("--save", opt.save.is_some()),
+            ("--offset", opt.offset.is_some()),
+            ("--length", opt.length.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!("Error: --cache cannot be combined with {}", flag));
+        }
+    }
+    if opt.direct {
+        // Direct reads must start at a multiple of the block size and read whole blocks
+        let conflicts = [
+            ("--offset", opt.offset.is_some()),
+            ("--length", opt.length.is_some()),
+            ("--checkpoint", opt.checkpoint.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!("Error: --direct cannot be combined with {}", flag));
+        }
+        if opt.buffer_size.is_some_and(|size| size % 4096 != 0) {
+            return Err("Error: --buffer-size must be a multiple of 4K with --direct".to_owned());
+        }
+    }
+    if opt.checkpoint.is_some() {
+        let conflicts = [
+            ("--hmac-key", opt.hmac_key.is_some()),
+            ("--tree-hash", opt.tree_hash),
+            ("--fuzzy", opt.fuzzy),
+            ("--save", opt.save.is_some()),
+            ("--mmap", opt.mmap),
+            ("--watch", opt.watch),
+            ("--compare", opt.compare.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!(
+                "Error: --checkpoint cannot be combined with {}",
+                flag
+            ));
+        }
+    }
+    if opt.output.is_some() && opt.output_format() != display::OutputFormat::Junit {
+        return Err("Error: --output can only be used with --format junit".to_owned());
+    }
+    if opt.output_format().is_digests_file() && opt.fuzzy {
+        return Err("Error: Digests file output cannot be combined with --fuzzy".to_owned());
+    }
+    if opt.concat {
+        let conflicts = [
+            ("--archive", opt.archive),
+            ("--offset", opt.offset.is_some()),
+            ("--checkpoint", opt.checkpoint.is_some()),
+            ("--compare", opt.compare.is_some()),
+            ("--watch", opt.watch),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!("Error: --concat cannot be combined with {}", flag));
+        }
+    }
+    if opt.image {
+        let conflicts = [
+            ("a hash to verify", hash_methods > 0),
+            ("--archive", opt.archive),
+            ("--concat", opt.concat),
+            ("--checkpoint", opt.checkpoint.is_some()),
+            ("--files-from", opt.files_from.is_some()),
+            ("--hmac-key", opt.hmac_key.is_some()),
+            ("--tree-hash", opt.tree_hash),
+            ("--fuzzy", opt.fuzzy),
+            ("--save", opt.save.is_some()),
+            ("--offset", opt.offset.is_some()),
+            ("--length", opt.length.is_some()),
+            ("--watch", opt.watch),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!("Error: --image cannot be combined with {}", flag));
+        }
+    }
+    if opt.from_xattr {
+        let conflicts = [
+            ("--archive", opt.archive),
+            ("--concat", opt.concat),
+            ("--tree-hash", opt.tree_hash),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!(
+                "Error: --from-xattr cannot be combined with {}",
+                flag
+            ));
+        }
+    }
+    if opt.from_name {
+        let conflicts = [("--concat", opt.concat), ("--tree-hash", opt.tree_hash)];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!(
+                "Error: --from-name cannot be combined with {}",
+                flag
+            ));
+        }
+    }
+    let signature_keys =
+        opt.key.is_some() || opt.minisign_pub.is_some() || opt.signify_pub.is_some();
+    if opt.sig.is_some() && !signature_keys {
+        return Err(
+            "Error: --sig requires a trusted key from --key, --minisign-pub or --signify-pub"
+                .to_owned(),
+        );
+    }
+    if signature_keys && opt.hash_file.is_none() && opt.check_all.is_none() {
+        return Err(
+            "Error: Signatures can only be checked on a digests file given with -c or --check-all"
+                .to_owned(),
+        );
+    }
+    if opt.check_all.is_some() {
+        let conflicts = [
+            ("--archive", opt.archive),
+            ("--concat", opt.concat),
+            ("--checkpoint", opt.checkpoint.is_some()),
+            ("--image", opt.image),
+            ("--save", opt.save.is_some()),
+            ("--watch", opt.watch),
+            ("--fuzzy", opt.fuzzy),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, used)| *used) {
+            return Err(format!(
+                "Error: --check-all cannot be combined with {}",
+                flag
+            ));
+        }
+    }
+    if opt.jobs == Some(0) {
+        return Err("Error: --jobs must be at least 1".to_owned());
+    }
+    if opt.tree_hash && opt.hmac_key.is_some() {
+        return Err("Error: HMAC cannot be combined with a tree hash".to_owned());
+    }
+    let stdin_input = opt.input.as_ref().and_then(|i| i.to_str()) == Some("-");
+    if opt.stdin_name.is_some() && !stdin_input {
+        return Err(
+            "Error: --stdin-name can only be used when the input is standard input".to_owned(),
+        );
+    }
+    let stdin_list = opt.files_from.as_ref().and_then(|f| f.to_str()) == Some("-");
+    if (stdin_input || stdin_list) && opt.hash_file.as_ref().and_then(|h| h.to_str()) == Some("-") {
+        return Err("Error: Cannot use use stdin for both hash file and input data".to_owned());
+    }
+    Ok(opt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `args` after `hashgood verify` and apply `config` to them
+    fn configured(args: &[&str], config: Config) -> Opt {
+        let matches = Command::clap().get_matches_from(["hashgood", "verify"].iter().chain(args));
+        let Command::Verify(mut opt) = Command::from_clap(&matches) else {
+            panic!("not parsed as verify");
+        };
+        apply_config(&mut opt, config, matches.subcommand().1.unwrap());
+        opt
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let config = || Config {
+            no_colour: Some(false),
+            buffer_size: Some(4096),
+            ..Config::default()
+        };
+        let opt = configured(&["-C", "file"], config());
+        assert!(opt.no_colour);
+        assert_eq!(opt.buffer_size, Some(4096));
+
+        let opt = configured(&["--buffer-size", "8K", "file"], config());
+        assert!(!opt.no_colour);
+        assert_eq!(opt.buffer_size, Some(8192));
+    }
+
+    #[test]
+    fn bench_sizes() {
+        assert!(matches!(
+            bench(&BenchOpt { size: 0 }),
+            Err(HashgoodError::Usage(_))
+        ));
+        assert!(matches!(
+            bench(&BenchOpt { size: usize::MAX }),
+            Err(HashgoodError::Usage(_))
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::Instant;
+
+/// A way of reading the clipboard, which may not work in every session. It is given the time by
+/// which it must have finished, if there is one.
+struct Provider {
+    name: &'static str,
+    read: fn(Option<Instant>) -> Result<String, String>,
+}
+
+/// The providers in the order they are tried. Wayland comes first because X11 may also appear to
+/// work under XWayland but only sees what X11 programs copied.
+const PROVIDERS: &[Provider] = &[
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    ))]
+    Provider {
+        name: "Wayland",
+        read: read_wayland,
+    },
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    ))]
+    Provider {
+        name: "X11",
+        read: read_system,
+    },
+    #[cfg(not(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    )))]
+    Provider {
+        name: "the system clipboard",
+        read: read_system,
+    },
+    #[cfg(unix)]
+    Provider {
+        name: "an OSC 52 query to the terminal",
+        read: read_osc52,
+    },
+];
+
+/// How long to wait for the terminal to answer an OSC 52 query. Many terminals ignore it.
+#[cfg(unix)]
+const OSC52_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How long before the deadline to stop waiting for the terminal, leaving time to restore it
+#[cfg(unix)]
+const OSC52_MARGIN: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Read text from the clipboard with the first provider which works, finishing before `deadline`
+/// if one is given. Returns the text and the name of the provider, or the reason each one failed.
+pub fn get_contents(deadline: Option<Instant>) -> Result<(String, &'static str), String> {
+    let mut errors = vec![];
+    for provider in PROVIDERS {
+        match (provider.read)(deadline) {
+            Ok(text) => return Ok((text, provider.name)),
+            Err(e) => errors.push(format!("{}: {}", provider.name, e)),
+        }
+    }
+    Err(format!(
+        "Error reading from clipboard ({})",
+        errors.join("; ")
+    ))
+}
+
+/// Read the clipboard through the Wayland data control protocols
+#[cfg(all(
+    unix,
+    not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+))]
+fn read_wayland(_deadline: Option<Instant>) -> Result<String, String> {
+    use std::io::Read;
+    use wl_clipboard_rs::paste::{get_contents, ClipboardType, MimeType, Seat};
+
+    let (mut pipe, _) = get_contents(ClipboardType::Regular, Seat::Unspecified, MimeType::Text)
+        .map_err(|e| e.to_string())?;
+    let mut text = String::new();
+    pipe.read_to_string(&mut text).map_err(|e| e.to_string())?;
+    Ok(text)
+}
+
+/// Read the clipboard with copypasta, which uses X11 on Linux and the native clipboard elsewhere
+fn read_system(_deadline: Option<Instant>) -> Result<String, String> {
+    use copypasta::{ClipboardContext, ClipboardProvider};
+    let mut ctx = ClipboardContext::new().map_err(|e| e.to_string())?;
+    ctx.get_contents().map_err(|e| e.to_string())
+}
+
+/// Ask the terminal for the clipboard with an OSC 52 escape sequence. This works over SSH, but
+/// only in terminals which allow it.
+#[cfg(unix)]
+fn read_osc52(deadline: Option<Instant>) -> Result<String, String> {
+    use std::fs::OpenOptions;
+    use std::os::unix::io::AsRawFd;
+
+    // Stop waiting in time to put the terminal back before the caller gives up on us, since the
+    // process may exit as soon as it does
+    let mut until = Instant::now() + OSC52_TIMEOUT;
+    if let Some(deadline) = deadline {
+        until = until.min(deadline.checked_sub(OSC52_MARGIN).unwrap_or(deadline));
+    }
+    if until <= Instant::now() {
+        return Err("no time left to wait for the terminal".to_owned());
+    }
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| format!("no terminal ({})", e))?;
+    // The answer arrives as input, so stop the terminal echoing it or waiting for a newline
+    let _raw = RawMode::enable(tty.as_raw_fd())?;
+    decode_osc52(&query_osc52(&mut tty, until)?)
+}
+
+/// The terminal switched to raw mode, which is switched back when this is dropped
+#[cfg(unix)]
+struct RawMode {
+    fd: std::os::unix::io::RawFd,
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable(fd: std::os::unix::io::RawFd) -> Result<RawMode, String> {
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Err("unable to configure the terminal".to_owned());
+        }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err("unable to configure the terminal".to_owned());
+        }
+        Ok(RawMode { fd, original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // Discard any of the answer which arrived too late, so it doesn't appear as typed input
+        unsafe { libc::tcsetattr(self.fd, libc::TCSAFLUSH, &self.original) };
+    }
+}
+
+/// Send the query and collect the answer up to its terminator, giving up at `until`
+#[cfg(unix)]
+fn query_osc52(tty: &mut std::fs::File, until: Instant) -> Result<Vec<u8>, String> {
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+
+    tty.write_all(b"\x1b]52;c;?\x07")
+        .map_err(|e| e.to_string())?;
+    let mut response = vec![];
+    let mut buf = [0u8; 4096];
+    while !response.ends_with(b"\x07") && !response.ends_with(b"\x1b\\") {
+        let remaining = until.saturating_duration_since(Instant::now());
+        let mut poll = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if remaining.is_zero()
+            || unsafe { libc::poll(&mut poll, 1, remaining.as_millis().max(1) as i32) } <= 0
+        {
+            return Err("the terminal did not answer".to_owned());
+        }
+        let len = tty.read(&mut buf).map_err(|e| e.to_string())?;
+        if len == 0 {
+            return Err("the terminal did not answer".to_owned());
+        }
+        response.extend_from_slice(&buf[..len]);
+    }
+    Ok(response)
+}
+
+/// Extract the text from an answer such as `ESC ] 52 ; c ; <base64> BEL`
+fn decode_osc52(response: &[u8]) -> Result<String, String> {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    let invalid = || "the terminal's answer was not understood".to_owned();
+    let response = std::str::from_utf8(response).map_err(|_| invalid())?;
+    let response = response.trim_end_matches('\x07').trim_end_matches("\x1b\\");
+    let start = response.find("]52;").ok_or_else(invalid)?;
+    let (_, data) = response[start + 4..].split_once(';').ok_or_else(invalid)?;
+    if data.is_empty() {
+        return Err("the terminal did not share the clipboard".to_owned());
+    }
+    let bytes = STANDARD.decode(data).map_err(|_| invalid())?;
+    String::from_utf8(bytes).map_err(|_| "the clipboard does not contain text".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn osc52_answers() {
+        assert_eq!(decode_osc52(b"\x1b]52;c;aGVsbG8=\x07").unwrap(), "hello");
+        assert_eq!(decode_osc52(b"\x1b]52;c;aGVsbG8=\x1b\\").unwrap(), "hello");
+        assert!(decode_osc52(b"\x1b]52;c;\x07").is_err());
+        assert!(decode_osc52(b"garbage").is_err());
+    }
+}
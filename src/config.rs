@@ -0,0 +1,114 @@
+use super::calculate::{check_size, parse_size};
+use super::checkpoint::parse_algorithm;
+use super::display::Theme;
+use super::error::HashgoodError;
+use super::Algorithm;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// Defaults read from the configuration file. Anything which isn't set there is None, and
+/// anything given on the command line takes precedence.
+#[derive(Default)]
+pub struct Config {
+    pub no_colour: Option<bool>,
+    /// Digests to calculate when there is no hash to verify against
+    pub algorithms: Option<Vec<Algorithm>>,
+    pub buffer_size: Option<usize>,
+    pub theme: Option<Theme>,
+}
+
+/// Where the configuration file is read from: `HASHGOOD_CONFIG` if it is set, otherwise
+/// `hashgood/config.toml` in the platform's configuration directory
+pub fn config_path() -> Option<PathBuf> {
+    match std::env::var_os("HASHGOOD_CONFIG") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::config_dir().map(|dir| dir.join("hashgood").join("config.toml")),
+    }
+}
+
+/// Read the configuration file, or the default configuration if there isn't one
+pub fn load() -> Result<Config, HashgoodError> {
+    let Some(path) = config_path() else {
+        return Ok(Config::default());
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => {
+            let context = format!("Unable to read config file '{}'", path.to_string_lossy());
+            return Err(HashgoodError::io(context, e));
+        }
+    };
+    parse(&text).map_err(|e| {
+        HashgoodError::Usage(format!(
+            "Error in config file '{}': {}",
+            path.to_string_lossy(),
+            e
+        ))
+    })
+}
+
+/// Parse the contents of a configuration file, rejecting unknown settings so that typos are
+/// noticed
+fn parse(text: &str) -> Result<Config, String> {
+    let table: Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| e.message().to_owned())?;
+    let mut config = Config::default();
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("no_colour" | "no_color", Value::Boolean(b)) => config.no_colour = Some(b),
+            ("algorithms", Value::Array(names)) => {
+                let algorithms = names
+                    .iter()
+                    .map(|name| {
+                        name.as_str()
+                            .and_then(parse_algorithm)
+                            .ok_or_else(|| format!("Unknown algorithm {}", name))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                config.algorithms = Some(algorithms);
+            }
+            ("buffer_size", Value::Integer(size)) => {
+                let size = usize::try_from(size).map_err(|_| format!("Invalid size '{}'", size))?;
+                config.buffer_size = Some(check_size(size)?);
+            }
+            ("buffer_size", Value::String(size)) => config.buffer_size = Some(parse_size(&size)?),
+            ("theme", Value::String(theme)) => config.theme = Some(theme.parse()?),
+            ("no_colour" | "no_color" | "algorithms" | "buffer_size" | "theme", value) => {
+                return Err(format!("Unexpected {} for {}", value.type_str(), key))
+            }
+            _ => return Err(format!("Unknown setting {}", key)),
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = parse(
+            "no_colour = true\nalgorithms = [\"sha256\", \"sha512\"]\nbuffer_size = \"8M\"\ntheme = \"colourblind\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.no_colour, Some(true));
+        assert_eq!(
+            config.algorithms,
+            Some(vec![Algorithm::Sha256, Algorithm::Sha512])
+        );
+        assert_eq!(config.buffer_size, Some(8 * 1024 * 1024));
+        assert!(config.theme.is_some());
+
+        assert_eq!(parse("buffer_size = 4096").unwrap().buffer_size, Some(4096));
+        assert!(parse("").unwrap().algorithms.is_none());
+        assert!(parse("algorithms = [\"crc32\"]").is_err());
+        assert!(parse("no_colour = \"yes\"").is_err());
+        assert!(parse("colour = false").is_err());
+        assert!(parse("buffer_size = 0").is_err());
+        assert!(parse("buffer_size = -1").is_err());
+        assert!(parse("buffer_size = 100000000000").is_err());
+    }
+}
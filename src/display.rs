@@ -1,6 +1,18 @@
-use super::{Algorithm, CandidateHash, Hash, MatchLevel, MessageLevel, VerificationSource};
+use super::{
+    Algorithm, CandidateHash, CandidateHashes, CheckStatus, Hash, MatchLevel, MessageLevel,
+    TreeDifference, VerificationSource,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
 use std::error::Error;
-use std::io::Write;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 pub type PrintResult = Result<(), Box<dyn Error>>;
@@ -12,36 +24,798 @@ fn filename_display(filename: &str) -> &str {
     filename
 }
 
+/// How calculated digests are written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DigestEncoding {
+    Hex,
+    /// Standard base64, as used by the `Content-MD5` header and many cloud APIs
+    Base64,
+    /// Subresource Integrity strings such as `sha384-<base64>`, for SHA-2 digests
+    Sri,
+}
+
+static ENCODING: OnceLock<DigestEncoding> = OnceLock::new();
+
+/// Write all further digests in the given encoding
+pub fn set_encoding(encoding: DigestEncoding) {
+    let _ = ENCODING.set(encoding);
+}
+
+/// Fuzzy hashes are already text, everything else is shown as hex unless another encoding was
+/// chosen. Only SHA-2 digests can be written as SRI, so other algorithms stay in hex.
+fn digest_display(alg: Algorithm, bytes: &[u8]) -> String {
+    let encoding = ENCODING.get().copied().unwrap_or(DigestEncoding::Hex);
+    match (alg, encoding) {
+        (Algorithm::Ssdeep, _) => String::from_utf8_lossy(bytes).to_string(),
+        (Algorithm::Sha256, DigestEncoding::Sri) => format!("sha256-{}", STANDARD.encode(bytes)),
+        (Algorithm::Sha384, DigestEncoding::Sri) => format!("sha384-{}", STANDARD.encode(bytes)),
+        (Algorithm::Sha512, DigestEncoding::Sri) => format!("sha512-{}", STANDARD.encode(bytes)),
+        (_, DigestEncoding::Base64) => STANDARD.encode(bytes),
+        _ => hex::encode(bytes),
+    }
+}
+
+/// How much detail to report about what hashgood is doing, from `-v`
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Report a detail on standard error if the verbosity is at least `level`. Level 1 is for the
+/// decisions that were made and level 2 for everything that was tried along the way. The message
+/// is only formatted if it will be printed.
+pub fn log(level: u8, message: impl FnOnce() -> String) {
+    if VERBOSITY.load(Ordering::Relaxed) >= level {
+        let label = match level {
+            1 => "info",
+            _ => "debug",
+        };
+        eprintln!("({}) {}", label, message());
+    }
+}
+
+/// How many characters of a digest to print in each space-separated group, or 0 to not group them
+static GROUP_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Split all further digests shown for reading into groups of this many characters
+pub fn set_group_size(size: usize) {
+    GROUP_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Separate a digest into groups with spaces, if enabled, so that it is easier to read out
+fn grouped(digest: String) -> String {
+    let size = GROUP_SIZE.load(Ordering::Relaxed);
+    if size == 0 {
+        return digest;
+    }
+    let chars: Vec<char> = digest.chars().collect();
+    chars
+        .chunks(size)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Ring the terminal bell when a result is FAIL
+static BELL: AtomicBool = AtomicBool::new(false);
+/// Print a full-width banner when a result is FAIL
+static BANNER: AtomicBool = AtomicBool::new(false);
+
+/// Make failures harder to miss with the terminal bell and a banner
+pub fn set_alerts(bell: bool, banner: bool) {
+    BELL.store(bell, Ordering::Relaxed);
+    BANNER.store(banner, Ordering::Relaxed);
+}
+
+/// The width of the terminal being printed to, or 80 columns if it can't be determined
+fn terminal_width() -> usize {
+    #[cfg(unix)]
+    {
+        let fd = match OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+            true => libc::STDERR_FILENO,
+            false => libc::STDOUT_FILENO,
+        };
+        // SAFETY: TIOCGWINSZ only writes a winsize to the pointer given
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+            return size.ws_col as usize;
+        }
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Whether output should go to standard error because standard output is carrying the input data
+static OUTPUT_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Print all further output to standard error instead of standard output
+pub fn redirect_to_stderr() {
+    OUTPUT_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+/// How results are printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Coloured output for a person to read
+    Text,
+    /// One JSON object per result
+    Json,
+    /// One line per result with tab-separated fields, for spreadsheets
+    Tsv,
+    /// One line per result with comma-separated fields, for spreadsheets
+    Csv,
+    /// `digest  filename` lines like `sha256sum`, which can be saved as a digests file
+    Coreutils,
+    /// `SHA256 (filename) = digest` lines like `shasum --tag`, which can be saved as a digests file
+    Tag,
+    /// Test Anything Protocol, with one test per result
+    Tap,
+    /// A JUnit XML report written once everything has been verified, with one test case per file
+    Junit,
+    /// Only the final word of each result, such as OK or FAIL
+    ResultOnly,
+    /// Nothing at all, so that only the exit status matters
+    Silent,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "csv" => Ok(OutputFormat::Csv),
+            "coreutils" => Ok(OutputFormat::Coreutils),
+            "tag" => Ok(OutputFormat::Tag),
+            "tap" => Ok(OutputFormat::Tap),
+            "junit" => Ok(OutputFormat::Junit),
+            _ => Err(format!("Unknown output format '{}'", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Whether the output is a digests file, which must only contain one algorithm
+    pub fn is_digests_file(self) -> bool {
+        matches!(self, OutputFormat::Coreutils | OutputFormat::Tag)
+    }
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Print all further results in the given format
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or(OutputFormat::Text)
+}
+
+/// Whether results are collected into records and printed whole, rather than as they arrive
+fn structured() -> bool {
+    format() != OutputFormat::Text
+}
+
+/// Whether only the overall result is wanted, not the result of each file in a list
+fn quiet() -> bool {
+    matches!(format(), OutputFormat::ResultOnly | OutputFormat::Silent)
+}
+
+/// Whether the header line of a table has been printed yet
+static HEADER_PRINTED: AtomicBool = AtomicBool::new(false);
+
+/// A result which is collected piece by piece so it can be printed as a single record. The
+/// pieces arrive in the same order as the human-readable output, ending with the match level.
+#[derive(Default)]
+struct Record {
+    filename: Option<String>,
+    alg: Option<Algorithm>,
+    algorithm: Option<String>,
+    digest: Option<String>,
+    expected: Option<String>,
+    source: Option<String>,
+    messages: Vec<(MessageLevel, String)>,
+}
+
+static PENDING: Mutex<Record> = Mutex::new(Record {
+    filename: None,
+    alg: None,
+    algorithm: None,
+    digest: None,
+    expected: None,
+    source: None,
+    messages: Vec::new(),
+});
+
+fn pending() -> MutexGuard<'static, Record> {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Print a result as a single line in the chosen format
+fn write_record(record: Record, result: Option<&str>, similarity: Option<u32>) -> PrintResult {
+    if let Some(path) = REPORT.get() {
+        append_report(path, json_record(&record, result, similarity))?;
+    }
+    match format() {
+        OutputFormat::Text => Ok(()),
+        OutputFormat::Json => {
+            writeln!(
+                get_stdout(true),
+                "{}",
+                json_record(&record, result, similarity)
+            )?;
+            Ok(())
+        }
+        OutputFormat::Tsv => write_table_record(record, result, '\t'),
+        OutputFormat::Csv => write_table_record(record, result, ','),
+        OutputFormat::Coreutils | OutputFormat::Tag => write_coreutils_record(record, result),
+        OutputFormat::Tap => write_tap_record(record, result, similarity),
+        OutputFormat::Junit => {
+            // Only verifications are test cases, and the overall result of a list is redundant
+            if let (Some(result), Some(_)) = (result, &record.filename) {
+                JUNIT_CASES
+                    .lock()
+                    .unwrap()
+                    .push((record, result.to_owned()));
+            }
+            Ok(())
+        }
+        OutputFormat::ResultOnly => match result {
+            Some(result) => {
+                writeln!(get_stdout(true), "{}", result.to_uppercase())?;
+                Ok(())
+            }
+            None => Ok(()),
+        },
+        OutputFormat::Silent => Ok(()),
+    }
+}
+
+/// A result as a JSON object. Every key is always present so that it is easy to consume.
+fn json_record(record: &Record, result: Option<&str>, similarity: Option<u32>) -> Value {
+    let messages: Vec<Value> = record
+        .messages
+        .iter()
+        .map(|(level, message)| {
+            let level = match level {
+                MessageLevel::Error => "error",
+                MessageLevel::Warning => "warning",
+                MessageLevel::Note => "note",
+            };
+            json!({ "level": level, "message": message })
+        })
+        .collect();
+    json!({
+        "filename": record.filename,
+        "algorithm": record.algorithm,
+        "digest": record.digest,
+        "expected": record.expected,
+        "source": record.source,
+        "result": result,
+        "similarity": similarity,
+        "messages": messages,
+    })
+}
+
+/// The file which records of results are appended to, from `--report`
+static REPORT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Append a record of each result to the report file as well as printing it. The file is opened
+/// now so that a mistake in the path is reported before any hashing.
+pub fn set_report(path: &Path) -> Result<(), String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Unable to open report '{}': {}", path.to_string_lossy(), e))?;
+    let _ = REPORT.set(path.to_owned());
+    Ok(())
+}
+
+/// Whether results need to be collected into records, for printing or for the report
+fn recording() -> bool {
+    structured() || REPORT.get().is_some()
+}
+
+/// Add a line to the report for a result, along with the time it was produced
+fn append_report(path: &Path, record: Value) -> PrintResult {
+    let mut line = serde_json::Map::new();
+    line.insert("time".to_owned(), utc_timestamp(SystemTime::now()).into());
+    if let Value::Object(fields) = record {
+        line.extend(fields);
+    }
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    // A single write keeps lines intact if several runs share a report
+    file.write_all(format!("{}\n", Value::Object(line)).as_bytes())?;
+    Ok(())
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC, such as `2024-03-01T12:34:56Z`
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Convert days since 1970 to a civil date, from Howard Hinnant's days_from_civil inverse
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Print a result as a row of a table, after a header row for the first one
+fn write_table_record(record: Record, result: Option<&str>, separator: char) -> PrintResult {
+    let mut stdout = get_stdout(true);
+    if !HEADER_PRINTED.swap(true, Ordering::Relaxed) {
+        let header = ["filename", "algorithm", "digest", "result"];
+        writeln!(&mut stdout, "{}", header.join(&separator.to_string()))?;
+    }
+    let fields = [
+        record.filename.as_deref(),
+        record.algorithm.as_deref(),
+        record.digest.as_deref(),
+        result,
+    ];
+    let row: Vec<String> = fields
+        .iter()
+        .map(|f| table_field(f.unwrap_or_default(), separator))
+        .collect();
+    writeln!(&mut stdout, "{}", row.join(&separator.to_string()))?;
+    write_messages_to_stderr(record.messages);
+    Ok(())
+}
+
+/// Print a digest in the same form as `sha256sum` or `shasum --tag`, or a verification result in
+/// the same form as `sha256sum -c`. The overall result of a list of files has no line of its own.
+fn write_coreutils_record(record: Record, result: Option<&str>) -> PrintResult {
+    let mut stdout = get_stdout(true);
+    let filename = record.filename.as_deref();
+    match (filename, record.digest.as_deref(), result) {
+        (Some(filename), Some(digest), None) => {
+            // Like coreutils, a leading backslash shows that the filename has been escaped
+            let escaped = filename
+                .replace('\\', "\\\\")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r");
+            if escaped != filename {
+                write!(&mut stdout, "\\")?;
+            }
+            match (format(), record.alg) {
+                (OutputFormat::Tag, Some(alg)) => {
+                    writeln!(&mut stdout, "{} ({}) = {}", tag_name(alg), escaped, digest)?
+                }
+                _ => writeln!(&mut stdout, "{}  {}", digest, escaped)?,
+            }
+        }
+        (Some(filename), _, Some(result)) => {
+            let status = match result {
+                "ok" => "OK",
+                "missing" | "unreadable" => "FAILED open or read",
+                "fail" => "FAILED",
+                other => &other.to_uppercase(),
+            };
+            writeln!(&mut stdout, "{}: {}", filename, status)?
+        }
+        _ => (),
+    }
+    write_messages_to_stderr(record.messages);
+    Ok(())
+}
+
+/// The number of tests printed so far in TAP output
+static TAP_TESTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Print a verification result as a TAP test line followed by any messages as diagnostics. A digest
+/// which wasn't verified is only a diagnostic, and so is the overall result of a list of files
+/// since each of them is already a test.
+fn write_tap_record(record: Record, result: Option<&str>, similarity: Option<u32>) -> PrintResult {
+    let mut stdout = get_stdout(true);
+    let name = match &record.filename {
+        // A # would start a directive
+        Some(filename) => filename.replace('#', "\\#"),
+        None => String::new(),
+    };
+    let alg = record
+        .alg
+        .map(|alg| format!(" {}", tag_name(alg).to_lowercase()))
+        .unwrap_or_default();
+    match (result, record.digest) {
+        (Some(result), _) if record.filename.is_some() => {
+            let number = TAP_TESTS.fetch_add(1, Ordering::Relaxed) + 1;
+            let status = match result {
+                "ok" => "ok",
+                _ => "not ok",
+            };
+            write!(&mut stdout, "{} {} - {}{}", status, number, name, alg)?;
+            match (result, similarity) {
+                ("ok", _) => writeln!(&mut stdout)?,
+                (_, Some(similarity)) => writeln!(&mut stdout, " ({}% similar)", similarity)?,
+                (result, None) => writeln!(&mut stdout, " ({})", result)?,
+            }
+        }
+        (None, Some(digest)) => writeln!(&mut stdout, "# {}  {}{}", digest, name, alg)?,
+        _ => (),
+    }
+    for (level, message) in record.messages {
+        let level = match level {
+            MessageLevel::Error => "error",
+            MessageLevel::Warning => "warning",
+            MessageLevel::Note => "note",
+        };
+        writeln!(&mut stdout, "# ({}) {}", level, message)?;
+    }
+    Ok(())
+}
+
+/// The results collected for a JUnit report, which can only be written at the end
+static JUNIT_CASES: Mutex<Vec<(Record, String)>> = Mutex::new(Vec::new());
+
+/// The file to write a report to instead of standard output, from `--output`
+static OUTPUT: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_output(path: &Path) {
+    let _ = OUTPUT.set(path.to_owned());
+}
+
+/// Build a JUnit XML report in the form understood by Jenkins and GitLab. Mismatches are failures
+/// while files which couldn't be read are errors.
+fn junit_report(cases: &[(Record, String)]) -> String {
+    let count = |results: &[&str]| {
+        cases
+            .iter()
+            .filter(|(_, r)| results.contains(&r.as_str()))
+            .count()
+    };
+    let errors = count(&["unreadable"]);
+    let failures = cases.len() - errors - count(&["ok"]);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!(
+        "<testsuites tests=\"{0}\" failures=\"{1}\" errors=\"{2}\">\n  \
+         <testsuite name=\"hashgood\" tests=\"{0}\" failures=\"{1}\" errors=\"{2}\">\n",
+        cases.len(),
+        failures,
+        errors
+    );
+    for (record, result) in cases {
+        let classname = match record.alg {
+            Some(alg) => format!("hashgood.{}", tag_name(alg).to_lowercase()),
+            None => "hashgood".to_owned(),
+        };
+        xml += &format!(
+            "    <testcase classname=\"{}\" name=\"{}\"",
+            xml_escape(&classname),
+            xml_escape(record.filename.as_deref().unwrap_or_default())
+        );
+        let mut details: Vec<String> = vec![];
+        if let Some(digest) = &record.digest {
+            details.push(format!("Calculated: {}", digest));
+        }
+        if let Some(expected) = &record.expected {
+            details.push(format!("Expected: {}", expected));
+        }
+        if let Some(source) = &record.source {
+            details.push(format!("Source: {}", source));
+        }
+        details.extend(record.messages.iter().map(|(_, m)| m.clone()));
+        let element = match result.as_str() {
+            "ok" => None,
+            "unreadable" => Some("error"),
+            _ => Some("failure"),
+        };
+        match element {
+            None => xml += "/>\n",
+            Some(element) => {
+                xml += &format!(
+                    ">\n      <{0} message=\"{1}\">{2}</{0}>\n    </testcase>\n",
+                    element,
+                    xml_escape(result),
+                    xml_escape(&details.join("\n"))
+                );
+            }
+        }
+    }
+    xml += "  </testsuite>\n</testsuites>\n";
+    xml
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Close off the output once everything has been printed. TAP ends with the number of tests and a
+/// JUnit report is only written now that all of its results are known.
+pub fn finish() -> PrintResult {
+    match format() {
+        OutputFormat::Tap => {
+            writeln!(get_stdout(true), "1..{}", TAP_TESTS.load(Ordering::Relaxed))?
+        }
+        OutputFormat::Junit => {
+            let report = junit_report(&JUNIT_CASES.lock().unwrap());
+            match OUTPUT.get() {
+                Some(path) => std::fs::write(path, report)
+                    .map_err(|e| format!("Unable to write '{}': {}", path.to_string_lossy(), e))?,
+                None => write!(get_stdout(true), "{}", report)?,
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Report an error which stopped the program. A TAP harness is told to abandon the run instead of
+/// seeing a plan which would make the results look complete.
+pub fn bail_out(error: &str) {
+    match format() {
+        OutputFormat::Tap => println!("Bail out! {}", error),
+        _ => eprintln!("Error: {}", error),
+    }
+}
+
+/// Formats with no room for messages print them to standard error instead
+fn write_messages_to_stderr(messages: Vec<(MessageLevel, String)>) {
+    for (level, message) in messages {
+        let level = match level {
+            MessageLevel::Error => "error",
+            MessageLevel::Warning => "warning",
+            MessageLevel::Note => "note",
+        };
+        eprintln!("({}) {}", level, message);
+    }
+}
+
+/// Make a value safe to use as a field in a table. TSV fields can't contain tabs or line breaks
+/// so these are escaped, while CSV fields are quoted if necessary.
+fn table_field(value: &str, separator: char) -> String {
+    match separator {
+        '\t' => value
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r"),
+        _ if value.contains([separator, '"', '\n', '\r']) => {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        }
+        _ => value.to_owned(),
+    }
+}
+
+fn keyed_algorithm_name(alg: Algorithm, keyed: bool) -> String {
+    match keyed {
+        true => format!("HMAC-{}", algorithm_name(alg)),
+        false => algorithm_name(alg).to_owned(),
+    }
+}
+
+/// The colours used for each kind of output
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// Matching characters and successful results
+    pub good: ColorSpec,
+    /// Mismatched characters, failures and errors
+    pub bad: ColorSpec,
+    /// Uncertain results and warnings
+    pub warning: ColorSpec,
+    /// Filenames and where hashes came from
+    pub label: ColorSpec,
+    /// Notes which are only informative
+    pub note: ColorSpec,
+}
+
+fn spec(colour: Color, intense: bool) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_fg(Some(colour))
+        .set_intense(intense)
+        .set_bold(intense);
+    spec
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            good: spec(Color::Green, false),
+            bad: spec(Color::Red, false),
+            warning: spec(Color::Yellow, false),
+            label: spec(Color::Yellow, false),
+            note: spec(Color::Cyan, false),
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    /// Parse a preset optionally followed by overrides for individual colours, such as
+    /// `colourblind,label=white`. Colours are names, ANSI 256-colour numbers or `#rrggbb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = s.split(',').map(str::trim);
+        let mut theme = match entries.next().unwrap_or_default() {
+            "default" => Theme::default(),
+            "high-contrast" => Theme {
+                good: spec(Color::Green, true),
+                bad: spec(Color::Red, true),
+                warning: spec(Color::Yellow, true),
+                label: spec(Color::White, true),
+                note: spec(Color::Cyan, true),
+            },
+            // Blue and orange can be told apart with all common forms of colour blindness
+            "colourblind" | "colorblind" => Theme {
+                good: spec(Color::Blue, true),
+                bad: spec(Color::Ansi256(208), false),
+                warning: spec(Color::Ansi256(220), false),
+                label: spec(Color::White, false),
+                note: spec(Color::Cyan, false),
+            },
+            preset => {
+                return Err(format!(
+                    "Unknown theme '{}'. Use default, high-contrast or colourblind",
+                    preset
+                ))
+            }
+        };
+        for entry in entries {
+            let (role, colour) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Theme colours are set like 'bad=red', not '{}'", entry))?;
+            let colour = parse_colour(colour)
+                .ok_or_else(|| format!("Invalid colour '{}' in theme", colour))?;
+            let spec = match role {
+                "good" => &mut theme.good,
+                "bad" => &mut theme.bad,
+                "warning" => &mut theme.warning,
+                "label" => &mut theme.label,
+                "note" => &mut theme.note,
+                _ => {
+                    return Err(format!(
+                        "Unknown theme colour '{}'. Use good, bad, warning, label or note",
+                        role
+                    ))
+                }
+            };
+            spec.set_fg(Some(colour));
+        }
+        Ok(theme)
+    }
+}
+
+/// Parse a colour name, ANSI 256-colour number or `#rrggbb`
+fn parse_colour(s: &str) -> Option<Color> {
+    match s.strip_prefix('#') {
+        Some(rgb) if rgb.len() == 6 => {
+            let rgb = hex::decode(rgb).ok()?;
+            Some(Color::Rgb(rgb[0], rgb[1], rgb[2]))
+        }
+        Some(_) => None,
+        None => s.parse().ok(),
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Use the given colours for all further output
+pub fn set_theme(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+/// Decide whether to use colour. With `auto`, colour is only used when printing to a terminal and
+/// the `NO_COLOR` environment variable isn't set.
+pub fn use_colour(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Never => false,
+        ColorChoice::Always | ColorChoice::AlwaysAnsi => true,
+        ColorChoice::Auto => {
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            let terminal = match OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+                true => std::io::stderr().is_terminal(),
+                false => std::io::stdout().is_terminal(),
+            };
+            terminal && !no_color
+        }
+    }
+}
+
 fn get_stdout(no_colour: bool) -> StandardStream {
-    if no_colour {
-        StandardStream::stdout(ColorChoice::Never)
+    let choice = if no_colour {
+        ColorChoice::Never
     } else {
-        StandardStream::stdout(ColorChoice::Always)
+        ColorChoice::Always
+    };
+    if OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+        StandardStream::stderr(choice)
+    } else {
+        StandardStream::stdout(choice)
+    }
+}
+
+/// Print an empty line to separate one result from the next
+pub fn print_separator(no_colour: bool) -> PrintResult {
+    if structured() {
+        return Ok(());
     }
+    writeln!(get_stdout(no_colour))?;
+    Ok(())
 }
 
 fn write_filename(mut stdout: &mut StandardStream, filename: &str) -> PrintResult {
-    stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+    stdout.set_color(&theme().label)?;
     write!(&mut stdout, "{}", filename_display(filename))?;
     stdout.reset()?;
     Ok(())
 }
 
-fn write_algorithm(mut stdout: &mut StandardStream, alg: Algorithm) -> PrintResult {
+/// The usual name of each algorithm, which is also used in machine-readable output
+pub fn algorithm_name(alg: Algorithm) -> &'static str {
     match alg {
-        Algorithm::Md5 => {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
-            write!(&mut stdout, "MD5")?;
-        }
-        Algorithm::Sha1 => {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
-            write!(&mut stdout, "SHA-1")?;
-        }
-        Algorithm::Sha256 => {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
-            write!(&mut stdout, "SHA-256")?;
+        Algorithm::Md5 => "MD5",
+        Algorithm::Sha1 => "SHA-1",
+        Algorithm::Sha256 => "SHA-256",
+        Algorithm::Sha384 => "SHA-384",
+        Algorithm::Sha512 => "SHA-512",
+        Algorithm::Sha256Tree => "SHA-256 tree hash",
+        Algorithm::Ssdeep => "ssdeep",
+        Algorithm::Xxh64 => "XXH64",
+        Algorithm::Xxh128 => "XXH128",
+    }
+}
+
+/// The name of each algorithm in BSD-style tagged digests
+fn tag_name(alg: Algorithm) -> &'static str {
+    match alg {
+        Algorithm::Md5 => "MD5",
+        Algorithm::Sha1 => "SHA1",
+        Algorithm::Sha256 => "SHA256",
+        Algorithm::Sha384 => "SHA384",
+        Algorithm::Sha512 => "SHA512",
+        Algorithm::Sha256Tree => "SHA256TREE",
+        Algorithm::Ssdeep => "SSDEEP",
+        Algorithm::Xxh64 => "XXH64",
+        Algorithm::Xxh128 => "XXH128",
+    }
+}
+
+fn algorithm_colour(alg: Algorithm) -> Color {
+    match alg {
+        Algorithm::Md5 => Color::Magenta,
+        Algorithm::Sha1 => Color::Cyan,
+        Algorithm::Sha256 | Algorithm::Sha384 | Algorithm::Sha512 | Algorithm::Sha256Tree => {
+            Color::Green
         }
+        Algorithm::Ssdeep => Color::Blue,
+        Algorithm::Xxh64 | Algorithm::Xxh128 => Color::Yellow,
     }
+}
+
+fn write_algorithm(mut stdout: &mut StandardStream, alg: Algorithm, keyed: bool) -> PrintResult {
+    if keyed {
+        write!(&mut stdout, "HMAC-")?;
+    }
+    stdout.set_color(ColorSpec::new().set_fg(Some(algorithm_colour(alg))))?;
+    write!(&mut stdout, "{}", algorithm_name(alg))?;
     stdout.reset()?;
     Ok(())
 }
@@ -49,9 +823,9 @@ fn write_algorithm(mut stdout: &mut StandardStream, alg: Algorithm) -> PrintResu
 fn print_hex_compare(print: &str, against: &str, mut stdout: &mut StandardStream) -> PrintResult {
     for (p, a) in print.chars().zip(against.chars()) {
         if p == a {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
+            stdout.set_color(&theme().good)?;
         } else {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+            stdout.set_color(&theme().bad)?;
         }
         write!(&mut stdout, "{}", p)?;
     }
@@ -60,45 +834,119 @@ fn print_hex_compare(print: &str, against: &str, mut stdout: &mut StandardStream
     Ok(())
 }
 
-fn write_source(
-    mut stdout: &mut StandardStream,
+/// Explain how a candidate differs from the calculated digest, recognising the mistakes that are
+/// commonly made when a hash is copied by hand
+fn describe_mismatch(calculated: &[u8], candidate: &[u8]) -> Vec<String> {
+    let (calculated_hex, candidate_hex) = (hex::encode(calculated), hex::encode(candidate));
+    let mut details = vec![];
+    if candidate.len() < calculated.len() && calculated_hex.starts_with(&candidate_hex) {
+        details.push(tr!(
+            "mismatch-truncated",
+            count = calculated_hex.len() - candidate_hex.len()
+        ));
+        return details;
+    }
+    let first = match calculated.iter().zip(candidate).position(|(a, b)| a != b) {
+        Some(first) => first,
+        None => return details,
+    };
+    let differing = calculated
+        .iter()
+        .zip(candidate)
+        .filter(|(a, b)| a != b)
+        .count();
+    details.push(tr!(
+        "mismatch-first-difference",
+        byte = first,
+        count = differing,
+        total = calculated.len()
+    ));
+
+    let differing_chars: Vec<usize> = calculated_hex
+        .bytes()
+        .zip(candidate_hex.bytes())
+        .enumerate()
+        .filter_map(|(i, (a, b))| (a != b).then_some(i))
+        .collect();
+    let (a, c) = (calculated_hex.as_bytes(), candidate_hex.as_bytes());
+    if let [i, j] = differing_chars[..] {
+        if j == i + 1 && a[i] == c[j] && a[j] == c[i] {
+            details.push(tr!("mismatch-transposed", position = i));
+        }
+    }
+    if calculated.len() > 1 && calculated.iter().rev().eq(candidate.iter()) {
+        details.push(tr!("mismatch-reversed"));
+    }
+    // A dropped or doubled character shifts the rest of the hash along
+    let shifted = (1..=4).find(|&n| {
+        differing_chars.len() > n
+            && (a[differing_chars[0]..a.len() - n] == c[differing_chars[0] + n..]
+                || a[differing_chars[0] + n..] == c[differing_chars[0]..c.len() - n])
+    });
+    if let Some(n) = shifted {
+        details.push(tr!(
+            "mismatch-shifted",
+            count = n,
+            position = differing_chars[0]
+        ));
+    }
+    details
+}
+
+/// Describe where the hash being compared against came from
+pub fn describe_source(
     verify_source: &VerificationSource,
     candidate_filename: &Option<String>,
-) -> PrintResult {
-    stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
-    match &verify_source {
-        VerificationSource::CommandArgument => {
-            writeln!(&mut stdout, "command line argument")?;
-        }
-        VerificationSource::Clipboard => {
-            writeln!(&mut stdout, "pasted from clipboard")?;
-        }
+) -> String {
+    match verify_source {
+        VerificationSource::CommandArgument => "command line argument".to_owned(),
+        VerificationSource::Clipboard => match candidate_filename {
+            Some(filename) => format!("'{}' in digests pasted from clipboard", filename),
+            None => "pasted from clipboard".to_owned(),
+        },
         VerificationSource::RawFile(raw_path) => match raw_path.as_str() {
-            "-" => {
-                writeln!(&mut stdout, "from standard input")?;
-            }
-            path => {
-                writeln!(&mut stdout, "from file '{}' containing raw hash", path)?;
-            }
+            "-" => "from standard input".to_owned(),
+            path => format!("from file '{}' containing raw hash", path),
         },
-        VerificationSource::DigestsFile(digest_path) => match digest_path.as_str() {
-            "-" => {
-                writeln!(
-                    &mut stdout,
-                    "'{}' from digests on standard input",
-                    candidate_filename.as_ref().unwrap()
-                )?;
-            }
-            path => {
-                writeln!(
-                    &mut stdout,
-                    "'{}' in digests file '{}'",
-                    candidate_filename.as_ref().unwrap(),
-                    path
-                )?;
+        // The filename is only unknown when no hash in the file matched
+        VerificationSource::DigestsFile(digest_path) => {
+            match (digest_path.as_str(), candidate_filename) {
+                ("-", Some(filename)) => format!("'{}' from digests on standard input", filename),
+                ("-", None) => "digests on standard input".to_owned(),
+                (path, Some(filename)) => format!("'{}' in digests file '{}'", filename, path),
+                (path, None) => format!("digests file '{}'", path),
             }
+        }
+        VerificationSource::ComparedFile(path) => format!("calculated from file '{}'", path),
+        VerificationSource::MagnetLink => match candidate_filename {
+            Some(filename) => format!("'{}' in magnet link", filename),
+            None => "from magnet link".to_owned(),
+        },
+        VerificationSource::QrCode(path) => match candidate_filename {
+            Some(filename) => format!("'{}' in QR code in '{}'", filename, path),
+            None => format!("QR code in '{}'", path),
+        },
+        VerificationSource::ExtendedAttribute(name) => format!("extended attribute '{}'", name),
+        VerificationSource::InputName => "part of the input filename".to_owned(),
+        VerificationSource::ContainerImage(path) => format!("blob digest in image '{}'", path),
+        VerificationSource::TorrentFile { path, .. } => match candidate_filename {
+            Some(filename) => format!("'{}' in torrent '{}'", filename, path),
+            None => format!("torrent '{}'", path),
         },
     }
+}
+
+fn write_source(
+    mut stdout: &mut StandardStream,
+    verify_source: &VerificationSource,
+    candidate_filename: &Option<String>,
+) -> PrintResult {
+    stdout.set_color(&theme().label)?;
+    writeln!(
+        &mut stdout,
+        "{}",
+        describe_source(verify_source, candidate_filename)
+    )?;
     stdout.reset()?;
     Ok(())
 }
@@ -109,15 +957,37 @@ pub fn print_hash(
     verify_source: Option<&VerificationSource>,
     no_colour: bool,
 ) -> PrintResult {
+    if recording() {
+        let mut record = pending();
+        record.filename = Some(hash.filename.clone());
+        record.alg = Some(hash.alg);
+        record.algorithm = Some(keyed_algorithm_name(hash.alg, hash.keyed));
+        record.digest = Some(digest_display(hash.alg, &hash.bytes));
+        record.expected = verify_hash.map(|v| digest_display(hash.alg, &v.bytes));
+        let candidate_filename = verify_hash.and_then(|v| v.filename.clone());
+        record.source = verify_source.map(|s| describe_source(s, &candidate_filename));
+        // Without anything to verify against there won't be a result to wait for
+        if verify_source.is_none() {
+            let record = std::mem::take(&mut *record);
+            write_record(record, None, None)?;
+        }
+        if structured() {
+            return Ok(());
+        }
+    }
     let mut stdout = get_stdout(no_colour);
 
     write_filename(&mut stdout, &hash.filename)?;
     write!(&mut stdout, " / ")?;
-    write_algorithm(&mut stdout, hash.alg)?;
+    write_algorithm(&mut stdout, hash.alg, hash.keyed)?;
     writeln!(&mut stdout)?;
 
     // Handle basic case first - nothing to compare it to
-    let hash_hex = hex::encode(&hash.bytes);
+    // Both rows are grouped the same way so the comparison stays aligned
+    let hash_hex = match hash.alg {
+        Algorithm::Ssdeep => digest_display(hash.alg, &hash.bytes),
+        _ => grouped(digest_display(hash.alg, &hash.bytes)),
+    };
     let verify_hash = match verify_hash {
         None => {
             write!(&mut stdout, "{}\n\n", hash_hex)?;
@@ -125,11 +995,22 @@ pub fn print_hash(
         }
         Some(verify_hash) => verify_hash,
     };
-    let other_hex = hex::encode(&verify_hash.bytes);
+    let other_hex = match hash.alg {
+        Algorithm::Ssdeep => digest_display(hash.alg, &verify_hash.bytes),
+        _ => grouped(digest_display(hash.alg, &verify_hash.bytes)),
+    };
 
     // Do a top-to-bottom comparison
-    print_hex_compare(&hash_hex, &other_hex, &mut stdout)?;
-    print_hex_compare(&other_hex, &hash_hex, &mut stdout)?;
+    // Fuzzy hashes are expected to differ so there is no point highlighting where
+    if hash.alg == Algorithm::Ssdeep {
+        writeln!(&mut stdout, "{}\n{}", hash_hex, other_hex)?;
+    } else {
+        print_hex_compare(&hash_hex, &other_hex, &mut stdout)?;
+        print_hex_compare(&other_hex, &hash_hex, &mut stdout)?;
+        for detail in describe_mismatch(&hash.bytes, &verify_hash.bytes) {
+            writeln!(&mut stdout, "{}", detail)?;
+        }
+    }
 
     // Show the source of our hash
     if let Some(source) = verify_source {
@@ -140,22 +1021,354 @@ pub fn print_hash(
     Ok(())
 }
 
+/// Summarise a comparison between two directory trees, listing each file which is not identical
+pub fn print_tree_differences(
+    input_dir: &str,
+    reference_dir: &str,
+    alg: Algorithm,
+    keyed: bool,
+    differences: &[(String, TreeDifference)],
+    identical: usize,
+    no_colour: bool,
+) -> PrintResult {
+    let source = format!("compared with directory '{}'", reference_dir);
+    if recording() {
+        for (name, difference) in differences.iter().filter(|_| !quiet()) {
+            let result = match difference {
+                TreeDifference::Different => "fail",
+                TreeDifference::Missing => "missing",
+                TreeDifference::Extra => "extra",
+            };
+            let record = Record {
+                filename: Some(name.clone()),
+                algorithm: Some(keyed_algorithm_name(alg, keyed)),
+                source: Some(source.clone()),
+                ..Default::default()
+            };
+            write_record(record, Some(result), None)?;
+        }
+        let mut record = pending();
+        record.filename = Some(input_dir.to_owned());
+        record.algorithm = Some(keyed_algorithm_name(alg, keyed));
+        record.source = Some(source.clone());
+        if structured() {
+            return Ok(());
+        }
+    }
+    let mut stdout = get_stdout(no_colour);
+
+    write_filename(&mut stdout, input_dir)?;
+    write!(&mut stdout, " / ")?;
+    write_algorithm(&mut stdout, alg, keyed)?;
+    writeln!(&mut stdout)?;
+    stdout.set_color(&theme().label)?;
+    writeln!(&mut stdout, "{}", source)?;
+    stdout.reset()?;
+
+    let mut counts = [0; 3];
+    for (name, difference) in differences {
+        let (label, colour, count) = match difference {
+            TreeDifference::Different => ("tree-different", &theme().bad, &mut counts[0]),
+            TreeDifference::Missing => ("tree-missing", &theme().bad, &mut counts[1]),
+            TreeDifference::Extra => ("tree-extra", &theme().warning, &mut counts[2]),
+        };
+        *count += 1;
+        stdout.set_color(colour)?;
+        write!(&mut stdout, "{} ", tr!(label))?;
+        stdout.reset()?;
+        writeln!(&mut stdout, "{}", name)?;
+    }
+    writeln!(
+        &mut stdout,
+        "{}",
+        tr!(
+            "tree-summary",
+            identical = identical,
+            different = counts[0],
+            missing = counts[1],
+            extra = counts[2]
+        )
+    )?;
+    writeln!(&mut stdout)?;
+    Ok(())
+}
+
+/// Print the outcome of checking one of the files listed in a digests file
+pub fn print_check_status(filename: &str, status: &CheckStatus, no_colour: bool) -> PrintResult {
+    if quiet() {
+        return Ok(());
+    }
+    if recording() {
+        let mut record = Record {
+            filename: Some(filename.to_owned()),
+            ..Default::default()
+        };
+        let result = match status {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Failed => "fail",
+            CheckStatus::Missing => "missing",
+            CheckStatus::Unreadable(e) => {
+                record.messages.push((MessageLevel::Error, e.clone()));
+                "unreadable"
+            }
+        };
+        write_record(record, Some(result), None)?;
+        if structured() {
+            return Ok(());
+        }
+    }
+    let mut stdout = get_stdout(no_colour);
+    let (label, colour) = match status {
+        CheckStatus::Ok => ("status-ok", &theme().good),
+        CheckStatus::Failed => ("status-failed", &theme().bad),
+        CheckStatus::Missing => ("status-missing", &theme().bad),
+        CheckStatus::Unreadable(_) => ("status-unreadable", &theme().bad),
+    };
+    stdout.set_color(colour)?;
+    write!(&mut stdout, "{} ", tr!(label))?;
+    stdout.reset()?;
+    match status {
+        CheckStatus::Unreadable(e) => writeln!(&mut stdout, "{}: {}", filename, e)?,
+        _ => writeln!(&mut stdout, "{}", filename)?,
+    }
+    Ok(())
+}
+
+/// Print the totals after checking all of the files listed in a digests file. The counts are of
+/// the files which were ok, failed, missing and unreadable, in that order. Skipped files are the
+/// missing ones which were ignored.
+pub fn print_check_summary(
+    candidates: &CandidateHashes,
+    keyed: bool,
+    counts: [usize; 4],
+    skipped: usize,
+    no_colour: bool,
+) -> PrintResult {
+    if recording() {
+        // The overall result is printed with the list of files, like a single verification
+        let mut record = pending();
+        record.algorithm = Some(keyed_algorithm_name(candidates.alg, keyed));
+        record.source = Some(describe_source(&candidates.source, &None));
+        if structured() {
+            return Ok(());
+        }
+    }
+    let mut stdout = get_stdout(no_colour);
+    writeln!(&mut stdout)?;
+    write!(
+        &mut stdout,
+        "{} / ",
+        tr!("check-files", count = candidates.hashes.len())
+    )?;
+    write_algorithm(&mut stdout, candidates.alg, keyed)?;
+    writeln!(&mut stdout)?;
+    if let VerificationSource::DigestsFile(path) = &candidates.source {
+        stdout.set_color(&theme().label)?;
+        match path.as_str() {
+            "-" => writeln!(&mut stdout, "{}", tr!("check-listed-stdin"))?,
+            path => writeln!(&mut stdout, "{}", tr!("check-listed-file", path = path))?,
+        }
+        stdout.reset()?;
+    }
+    let [ok, failed, missing, unreadable] = counts;
+    write!(
+        &mut stdout,
+        "{}",
+        tr!(
+            "check-summary",
+            ok = ok,
+            failed = failed,
+            missing = missing,
+            unreadable = unreadable
+        )
+    )?;
+    match skipped {
+        0 => writeln!(&mut stdout)?,
+        skipped => writeln!(&mut stdout, "{}", tr!("check-skipped", count = skipped))?,
+    }
+    let totals = totals();
+    writeln!(
+        &mut stdout,
+        "{}",
+        throughput(totals.bytes, totals.elapsed())
+    )?;
+    writeln!(&mut stdout)?;
+    Ok(())
+}
+
+/// Summarise a piecewise comparison against a torrent, where the individual hashes are too
+/// numerous to be worth printing
+pub fn print_pieces(hash: &Hash, candidates: &CandidateHashes, no_colour: bool) -> PrintResult {
+    if recording() {
+        let mut record = pending();
+        record.filename = Some(hash.filename.clone());
+        record.algorithm = Some(format!("{} pieces", algorithm_name(hash.alg)));
+        record.source = Some(describe_source(
+            &candidates.source,
+            &candidates.hashes[0].filename,
+        ));
+        if structured() {
+            return Ok(());
+        }
+    }
+    let mut stdout = get_stdout(no_colour);
+
+    write_filename(&mut stdout, &hash.filename)?;
+    write!(&mut stdout, " / ")?;
+    write_algorithm(&mut stdout, hash.alg, hash.keyed)?;
+    writeln!(&mut stdout, " {}", tr!("pieces"))?;
+    writeln!(
+        &mut stdout,
+        "{}",
+        tr!(
+            "pieces-summary",
+            calculated = hash.bytes.len() / 20,
+            expected = candidates.hashes.len()
+        )
+    )?;
+    write_source(
+        &mut stdout,
+        &candidates.source,
+        &candidates.hashes[0].filename,
+    )?;
+
+    writeln!(&mut stdout)?;
+    Ok(())
+}
+
+/// Report how much of the input was read and how quickly, which shows up a truncated read or slow
+/// storage
+pub fn print_throughput(bytes: u64, elapsed: Duration, no_colour: bool) -> PrintResult {
+    count_read(bytes, elapsed);
+    if structured() {
+        return Ok(());
+    }
+    let mut stdout = get_stdout(no_colour);
+    writeln!(&mut stdout, "{}\n", throughput(bytes, elapsed))?;
+    Ok(())
+}
+
+fn throughput(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    let rate = match seconds {
+        0.0 => 0.0,
+        seconds => bytes as f64 / seconds / 1_000_000.0,
+    };
+    tr!(
+        "throughput",
+        bytes = bytes,
+        seconds = format!("{:.2}", seconds),
+        rate = format!("{:.1}", rate)
+    )
+}
+
+/// Running totals over every input in this run, for the summary at the end
+struct Totals {
+    /// Number of OK, MAYBE, SIMILAR and FAIL results
+    results: [usize; 4],
+    bytes: u64,
+    /// When the first input started being read and the last one finished. Inputs may be read at
+    /// the same time, so this is the wall-clock time rather than a sum.
+    span: Option<(Instant, Instant)>,
+}
+
+static TOTALS: Mutex<Totals> = Mutex::new(Totals {
+    results: [0; 4],
+    bytes: 0,
+    span: None,
+});
+
+impl Totals {
+    fn elapsed(&self) -> Duration {
+        self.span
+            .map_or(Duration::ZERO, |(first, last)| last - first)
+    }
+}
+
+fn totals() -> MutexGuard<'static, Totals> {
+    TOTALS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Add an input which has been read in full to the totals for the summary
+pub fn count_read(bytes: u64, elapsed: Duration) {
+    let mut totals = totals();
+    totals.bytes += bytes;
+    let finished = Instant::now();
+    let started = finished.checked_sub(elapsed).unwrap_or(finished);
+    totals.span = Some(match totals.span {
+        Some((first, last)) => (first.min(started), last.max(finished)),
+        None => (started, finished),
+    });
+}
+
+/// Print how many of the inputs matched and how much was read altogether. Nothing is printed for a
+/// single result since it is already the last thing shown.
+pub fn print_summary(no_colour: bool) -> PrintResult {
+    let totals = totals();
+    if structured() || totals.results.iter().sum::<usize>() < 2 {
+        return Ok(());
+    }
+    let [ok, maybe, similar, fail] = totals.results;
+    let mut stdout = get_stdout(no_colour);
+    writeln!(&mut stdout)?;
+    stdout.set_color(&theme().label)?;
+    write!(&mut stdout, "{} ", tr!("summary"))?;
+    stdout.reset()?;
+    let mut counts = vec![
+        (ok, tr!("summary-ok", count = ok), &theme().good),
+        (maybe, tr!("summary-maybe", count = maybe), &theme().warning),
+    ];
+    // Fuzzy matches are rare so they are only mentioned when there are some
+    if similar > 0 {
+        counts.push((
+            similar,
+            tr!("summary-similar", count = similar),
+            &theme().warning,
+        ));
+    }
+    counts.push((fail, tr!("summary-fail", count = fail), &theme().bad));
+    for (i, (count, text, colour)) in counts.into_iter().enumerate() {
+        if i > 0 {
+            write!(&mut stdout, ", ")?;
+        }
+        if count > 0 {
+            stdout.set_color(colour)?;
+        }
+        write!(&mut stdout, "{}", text)?;
+        stdout.reset()?;
+    }
+    writeln!(&mut stdout)?;
+    writeln!(
+        &mut stdout,
+        "{}",
+        throughput(totals.bytes, totals.elapsed())
+    )?;
+    Ok(())
+}
+
 pub fn print_messages(messages: Vec<(MessageLevel, String)>, no_colour: bool) -> PrintResult {
+    if recording() {
+        // They are included with the next result instead
+        pending().messages.extend(messages.iter().cloned());
+        if structured() {
+            return Ok(());
+        }
+    }
     let mut stdout = get_stdout(no_colour);
 
     for (level, msg) in &messages {
         match level {
             MessageLevel::Error => {
-                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
-                write!(&mut stdout, "(error) ")?;
+                stdout.set_color(&theme().bad)?;
+                write!(&mut stdout, "{} ", tr!("level-error"))?;
             }
             MessageLevel::Warning => {
-                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
-                write!(&mut stdout, "(warning) ")?;
+                stdout.set_color(&theme().warning)?;
+                write!(&mut stdout, "{} ", tr!("level-warning"))?;
             }
             MessageLevel::Note => {
-                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
-                write!(&mut stdout, "(note) ")?;
+                stdout.set_color(&theme().note)?;
+                write!(&mut stdout, "{} ", tr!("level-note"))?;
             }
         }
         stdout.reset()?;
@@ -169,22 +1382,214 @@ pub fn print_messages(messages: Vec<(MessageLevel, String)>, no_colour: bool) ->
 }
 
 pub fn print_match_level(match_level: MatchLevel, no_colour: bool) -> PrintResult {
+    totals().results[match match_level {
+        MatchLevel::Ok => 0,
+        MatchLevel::Maybe => 1,
+        MatchLevel::Similar(_) => 2,
+        MatchLevel::Fail => 3,
+    }] += 1;
+    if recording() {
+        let record = std::mem::take(&mut *pending());
+        match match_level {
+            MatchLevel::Ok => write_record(record, Some("ok"), None)?,
+            MatchLevel::Maybe => write_record(record, Some("maybe"), None)?,
+            MatchLevel::Similar(score) => write_record(record, Some("similar"), Some(score))?,
+            MatchLevel::Fail => write_record(record, Some("fail"), None)?,
+        };
+        if structured() {
+            return Ok(());
+        }
+    }
     let mut stdout = get_stdout(no_colour);
-    write!(&mut stdout, "Result: ")?;
+    write!(&mut stdout, "{} ", tr!("result"))?;
     match match_level {
         MatchLevel::Ok => {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
-            writeln!(&mut stdout, "OK")?;
+            stdout.set_color(&theme().good)?;
+            writeln!(&mut stdout, "{}", tr!("result-ok"))?;
         }
         MatchLevel::Maybe => {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
-            writeln!(&mut stdout, "MAYBE")?;
+            stdout.set_color(&theme().warning)?;
+            writeln!(&mut stdout, "{}", tr!("result-maybe"))?;
+        }
+        MatchLevel::Similar(score) => {
+            stdout.set_color(&theme().warning)?;
+            writeln!(&mut stdout, "{}", tr!("result-similar", score = score))?;
         }
         MatchLevel::Fail => {
-            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
-            writeln!(&mut stdout, "FAIL")?;
+            stdout.set_color(&theme().bad)?;
+            writeln!(&mut stdout, "{}", tr!("result-fail"))?;
         }
     }
     stdout.reset()?;
+    if match_level == MatchLevel::Fail {
+        write_alerts(&mut stdout)?;
+    }
+    Ok(())
+}
+
+fn write_alerts(mut stdout: &mut StandardStream) -> PrintResult {
+    if BANNER.load(Ordering::Relaxed) {
+        let mut spec = ColorSpec::new();
+        spec.set_bg(theme().bad.fg().copied())
+            .set_fg(Some(Color::White))
+            .set_bold(true);
+        stdout.set_color(&spec)?;
+        write!(
+            &mut stdout,
+            "{:^width$}",
+            tr!("result-fail"),
+            width = terminal_width()
+        )?;
+        stdout.reset()?;
+        writeln!(&mut stdout)?;
+    }
+    if BELL.load(Ordering::Relaxed) {
+        write!(&mut stdout, "\x07")?;
+        stdout.flush()?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_theme() {
+        assert_eq!("default".parse::<Theme>().unwrap(), Theme::default());
+        let theme: Theme = "colourblind,bad=magenta,note=#ff8800".parse().unwrap();
+        assert_eq!(theme.bad.fg(), Some(&Color::Magenta));
+        assert_eq!(theme.note.fg(), Some(&Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!(theme.good.fg(), Some(&Color::Blue));
+        assert!("sepia".parse::<Theme>().is_err());
+        assert!("default,bad=nope".parse::<Theme>().is_err());
+    }
+
+    #[test]
+    fn test_utc_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_296_496);
+        assert_eq!(utc_timestamp(time), "2024-03-01T12:34:56Z");
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let passed = Record {
+            filename: Some("a.iso".to_owned()),
+            alg: Some(Algorithm::Sha256),
+            ..Default::default()
+        };
+        let failed = Record {
+            filename: Some("b&c.iso".to_owned()),
+            expected: Some("abcd".to_owned()),
+            ..Default::default()
+        };
+        let report = junit_report(&[(passed, "ok".to_owned()), (failed, "fail".to_owned())]);
+        assert!(report.contains(r#"<testsuite name="hashgood" tests="2" failures="1" errors="0">"#));
+        assert!(report.contains(r#"<testcase classname="hashgood.sha256" name="a.iso"/>"#));
+        assert!(report.contains(r#"name="b&amp;c.iso">"#));
+        assert!(report.contains(r#"<failure message="fail">Expected: abcd</failure>"#));
+    }
+
+    #[test]
+    fn test_json_record() {
+        let record = Record {
+            filename: Some("a.iso".to_owned()),
+            algorithm: Some("SHA-256".to_owned()),
+            digest: Some("abcd".to_owned()),
+            messages: vec![(MessageLevel::Warning, "Filename differs".to_owned())],
+            ..Default::default()
+        };
+        let json = json_record(&record, Some("similar"), Some(80));
+        assert_eq!(json["filename"], "a.iso");
+        assert_eq!(json["digest"], "abcd");
+        assert_eq!(json["expected"], Value::Null);
+        assert_eq!(json["result"], "similar");
+        assert_eq!(json["similarity"], 80);
+        assert_eq!(
+            json["messages"],
+            json!([{ "level": "warning", "message": "Filename differs" }])
+        );
+        let empty = json_record(&Record::default(), None, None);
+        assert_eq!(empty.as_object().unwrap().len(), 8);
+        assert_eq!(empty["messages"], json!([]));
+    }
+
+    #[test]
+    fn test_table_field() {
+        assert_eq!(table_field("a.iso", ','), "a.iso");
+        assert_eq!(table_field("a,b.iso", ','), "\"a,b.iso\"");
+        assert_eq!(table_field("say \"hi\".iso", ','), "\"say \"\"hi\"\".iso\"");
+        assert_eq!(table_field("two\nlines", ','), "\"two\nlines\"");
+        assert_eq!(table_field("tab\there", ','), "tab\there");
+
+        assert_eq!(table_field("a,b \"c\".iso", '\t'), "a,b \"c\".iso");
+        assert_eq!(table_field("tab\there", '\t'), "tab\\there");
+        assert_eq!(table_field("two\r\nlines", '\t'), "two\\r\\nlines");
+        assert_eq!(table_field("back\\slash", '\t'), "back\\\\slash");
+    }
+
+    #[test]
+    fn test_pending_record() {
+        // Each piece of a result is collected and written to the report once the result is known
+        let path = std::env::temp_dir().join(format!("hashgood-report-{}", std::process::id()));
+        set_format(OutputFormat::Silent);
+        set_report(&path).unwrap();
+        let hash = Hash::new(Algorithm::Md5, vec![0x12; 16], "a.iso");
+        let candidate = CandidateHash {
+            bytes: vec![0x12; 16],
+            filename: Some("b.iso".to_owned()),
+            size: None,
+        };
+        let source = VerificationSource::CommandArgument;
+        print_hash(&hash, Some(&candidate), Some(&source), true).unwrap();
+        let warning = (MessageLevel::Warning, "Filename differs".to_owned());
+        print_messages(vec![warning], true).unwrap();
+        print_match_level(MatchLevel::Maybe, true).unwrap();
+        // Without a candidate the digest is written straight away
+        print_hash(&hash, None, None, true).unwrap();
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records: Vec<Value> = report
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["filename"], "a.iso");
+        assert_eq!(records[0]["expected"], "12".repeat(16));
+        assert_eq!(records[0]["result"], "maybe");
+        assert_eq!(records[0]["messages"][0]["message"], "Filename differs");
+        assert!(records[0]["time"].is_string());
+        assert_eq!(records[1]["digest"], "12".repeat(16));
+        assert_eq!(records[1]["result"], Value::Null);
+        assert_eq!(records[1]["messages"], json!([]));
+    }
+
+    #[test]
+    fn test_describe_mismatch() {
+        let digest = hex::decode("87428fc522803d31065e7bce3cf03fe4").unwrap();
+        assert!(describe_mismatch(&digest, &digest).is_empty());
+
+        let transposed = hex::decode("87428fc522803d31065e7bce3cf03ef4").unwrap();
+        let details = describe_mismatch(&digest, &transposed);
+        assert_eq!(
+            details[0],
+            "First difference at byte 14, 2 of 16 bytes differ"
+        );
+        assert!(details[1].contains("transposed at position 29"));
+
+        let shifted = hex::decode("87428fc52803d31065e7bce3cf03fe40").unwrap();
+        let details = describe_mismatch(&digest, &shifted);
+        assert!(details[1].contains("shifted by 1 hex chars from position 9"));
+
+        let reversed: Vec<u8> = digest.iter().rev().cloned().collect();
+        assert!(describe_mismatch(&digest, &reversed)[1].contains("reverse order"));
+
+        let truncated = hex::decode("87428fc522803d31065e7bce3cf0").unwrap();
+        assert_eq!(
+            describe_mismatch(&digest, &truncated),
+            vec!["The candidate looks truncated by 4 hex chars"]
+        );
+    }
+}
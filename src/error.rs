@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while hashing and verifying, so that a program using hashgood can
+/// tell a missing file apart from an invalid hash or a failed verification
+#[derive(Debug, thiserror::Error)]
+pub enum HashgoodError {
+    /// The input or a file it refers to doesn't exist
+    #[error("The path '{}' does not exist.", .0.to_string_lossy())]
+    NotFound(PathBuf),
+    /// A file or stream couldn't be opened or read
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    /// A hash or check file couldn't be understood
+    #[error("{0}")]
+    InvalidHash(String),
+    /// The options given don't make sense together
+    #[error("{0}")]
+    Usage(String),
+    /// Something was asked for which this build or platform doesn't support
+    #[error("{0}")]
+    Unsupported(String),
+    /// Verification was performed and the input did not match
+    #[error("The input does not match the expected hash")]
+    Mismatch,
+    /// Any other problem, described for the user
+    #[error("{0}")]
+    Other(String),
+}
+
+impl HashgoodError {
+    /// An I/O error with a description of what was being done at the time
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        HashgoodError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, HashgoodError>;
+
+impl From<String> for HashgoodError {
+    fn from(message: String) -> Self {
+        HashgoodError::Other(message)
+    }
+}
+
+impl From<&str> for HashgoodError {
+    fn from(message: &str) -> Self {
+        HashgoodError::Other(message.to_owned())
+    }
+}
+
+impl From<std::io::Error> for HashgoodError {
+    fn from(e: std::io::Error) -> Self {
+        HashgoodError::io("Unable to read input", e)
+    }
+}
+
+/// Most of the program passes errors around boxed, so recover the original if it was one of ours
+impl From<Box<dyn Error>> for HashgoodError {
+    fn from(e: Box<dyn Error>) -> Self {
+        match e.downcast::<HashgoodError>() {
+            Ok(e) => *e,
+            Err(e) => HashgoodError::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<walkdir::Error> for HashgoodError {
+    fn from(e: walkdir::Error) -> Self {
+        HashgoodError::Other(e.to_string())
+    }
+}
+
+impl From<std::path::StripPrefixError> for HashgoodError {
+    fn from(e: std::path::StripPrefixError) -> Self {
+        HashgoodError::Other(e.to_string())
+    }
+}
+
+#[cfg(feature = "archive")]
+impl From<zip::result::ZipError> for HashgoodError {
+    fn from(e: zip::result::ZipError) -> Self {
+        HashgoodError::Other(e.to_string())
+    }
+}
+
+#[cfg(feature = "watch")]
+impl From<notify::Error> for HashgoodError {
+    fn from(e: notify::Error) -> Self {
+        HashgoodError::Other(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn recover_boxed() {
+        let boxed: Box<dyn Error> = Box::new(HashgoodError::NotFound(PathBuf::from("a.iso")));
+        assert!(matches!(
+            HashgoodError::from(boxed),
+            HashgoodError::NotFound(path) if path == Path::new("a.iso")
+        ));
+        let boxed: Box<dyn Error> = "Unable to read".into();
+        assert!(matches!(
+            HashgoodError::from(boxed),
+            HashgoodError::Other(_)
+        ));
+        assert!(matches!(
+            crate::parse_hash("abc"),
+            Err(HashgoodError::InvalidHash(_))
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+use super::calculate::Digester;
+
+// Context triggered piecewise hashing as implemented by ssdeep. Rather than guessing a block
+// size up front and retrying, which would require reading the input several times, a digest is
+// built for every candidate block size at once and the appropriate one is chosen at the end.
+
+const ROLLING_WINDOW: usize = 7;
+const MIN_BLOCKSIZE: u64 = 3;
+const HASH_PRIME: u32 = 0x0100_0193;
+const HASH_INIT: u32 = 0x2802_1967;
+const NUM_BLOCKHASHES: usize = 31;
+const SPAMSUM_LENGTH: usize = 64;
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn block_size(index: usize) -> u64 {
+    MIN_BLOCKSIZE << index
+}
+
+/// Rolling hash over the last few bytes of input, used to decide where pieces end
+#[derive(Default)]
+struct RollState {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl RollState {
+    fn hash(&mut self, c: u8) {
+        self.h2 = self.h2.wrapping_sub(self.h1);
+        self.h2 = self.h2.wrapping_add(ROLLING_WINDOW as u32 * c as u32);
+        self.h1 = self.h1.wrapping_add(c as u32);
+        self.h1 = self
+            .h1
+            .wrapping_sub(self.window[self.n % ROLLING_WINDOW] as u32);
+        self.window[self.n % ROLLING_WINDOW] = c;
+        self.n = self.n.wrapping_add(1);
+        self.h3 = (self.h3 << 5) ^ c as u32;
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+fn sum_hash(c: u8, h: u32) -> u32 {
+    h.wrapping_mul(HASH_PRIME) ^ c as u32
+}
+
+/// The digest being built for one particular block size
+#[derive(Clone)]
+struct BlockHash {
+    digest: Vec<u8>,
+    h: u32,
+    half_h: u32,
+    /// Once the digest is full, the character for everything since it filled up as of the end of
+    /// the latest piece
+    last: Option<u8>,
+    /// The character for everything after the first half of the digest as of the end of the
+    /// latest piece, once the digest is at least half full
+    half_last: Option<u8>,
+}
+
+impl BlockHash {
+    fn new() -> Self {
+        Self {
+            digest: vec![],
+            h: HASH_INIT,
+            half_h: HASH_INIT,
+            last: None,
+            half_last: None,
+        }
+    }
+}
+
+/// Calculates an ssdeep fuzzy hash of the input, in the form `blocksize:hash:hash`
+pub struct FuzzyHasher {
+    total_size: u64,
+    /// Index of the smallest block size still being considered
+    start: usize,
+    blocks: Vec<BlockHash>,
+    /// Once every block size is in use, the hash of a hypothetical next one, for the final
+    /// character of a digest that has no larger block size to go with it
+    last_h: Option<u32>,
+    roll: RollState,
+}
+
+impl FuzzyHasher {
+    pub fn new() -> Self {
+        Self {
+            total_size: 0,
+            start: 0,
+            blocks: vec![BlockHash::new()],
+            last_h: None,
+            roll: RollState::default(),
+        }
+    }
+
+    fn step(&mut self, c: u8) {
+        self.roll.hash(c);
+        let h = self.roll.sum() as u64;
+        for b in &mut self.blocks[self.start..] {
+            b.h = sum_hash(c, b.h);
+            b.half_h = sum_hash(c, b.half_h);
+        }
+        if let Some(last_h) = &mut self.last_h {
+            *last_h = sum_hash(c, *last_h);
+        }
+        let mut i = self.start;
+        while i < self.blocks.len() {
+            if h % block_size(i) != block_size(i) - 1 {
+                break;
+            }
+            // The first time a block size triggers, start considering the next larger one too
+            if self.blocks[i].digest.is_empty() {
+                let largest = &self.blocks[self.blocks.len() - 1];
+                if self.blocks.len() < NUM_BLOCKHASHES {
+                    let next = BlockHash {
+                        h: largest.h,
+                        half_h: largest.half_h,
+                        ..BlockHash::new()
+                    };
+                    self.blocks.push(next);
+                } else if self.last_h.is_none() {
+                    self.last_h = Some(largest.h);
+                }
+            }
+            let b = &mut self.blocks[i];
+            b.half_last = Some(B64[(b.half_h % 64) as usize]);
+            if b.digest.len() < SPAMSUM_LENGTH - 1 {
+                b.digest.push(B64[(b.h % 64) as usize]);
+                b.h = HASH_INIT;
+                if b.digest.len() < SPAMSUM_LENGTH / 2 {
+                    b.half_h = HASH_INIT;
+                    b.half_last = None;
+                }
+            } else {
+                // This block size is full so only its final character can still change. Stop
+                // tracking it if a larger block size has become viable.
+                b.last = Some(B64[(b.h % 64) as usize]);
+                self.try_reduce();
+            }
+            i += 1;
+        }
+    }
+
+    fn try_reduce(&mut self) {
+        if self.blocks.len() - self.start < 2 {
+            return;
+        }
+        if block_size(self.start) * SPAMSUM_LENGTH as u64 >= self.total_size {
+            return;
+        }
+        if self.blocks[self.start + 1].digest.len() < SPAMSUM_LENGTH / 2 {
+            return;
+        }
+        self.start += 1;
+    }
+
+    /// Produce the digest the same way as ssdeep's `fuzzy_digest`. Unless the input ended exactly
+    /// on a piece boundary, the unfinished piece adds a final character to each signature.
+    fn digest(&self) -> String {
+        let h = self.roll.sum();
+        let mut bi = self.start;
+        while block_size(bi) * (SPAMSUM_LENGTH as u64) < self.total_size && bi < NUM_BLOCKHASHES - 1
+        {
+            bi += 1;
+        }
+        while bi >= self.blocks.len() {
+            bi -= 1;
+        }
+        while bi > self.start && self.blocks[bi].digest.len() < SPAMSUM_LENGTH / 2 {
+            bi -= 1;
+        }
+
+        let mut result = format!("{}:", block_size(bi));
+        let b = &self.blocks[bi];
+        result.push_str(std::str::from_utf8(&b.digest).unwrap());
+        if h != 0 {
+            result.push(B64[(b.h % 64) as usize] as char);
+        } else if let Some(last) = b.last {
+            result.push(last as char);
+        }
+        result.push(':');
+        if bi < self.blocks.len() - 1 {
+            // The second signature only has room for half as many characters
+            let b = &self.blocks[bi + 1];
+            let len = b.digest.len().min(SPAMSUM_LENGTH / 2 - 1);
+            result.push_str(std::str::from_utf8(&b.digest[..len]).unwrap());
+            if h != 0 {
+                result.push(B64[(b.half_h % 64) as usize] as char);
+            } else if let Some(last) = b.half_last {
+                result.push(last as char);
+            }
+        } else if h != 0 {
+            let h = match bi {
+                0 => b.h,
+                _ => self.last_h.unwrap_or(b.h),
+            };
+            result.push(B64[(h % 64) as usize] as char);
+        }
+        result
+    }
+}
+
+impl Digester for FuzzyHasher {
+    fn update(&mut self, data: &[u8]) {
+        self.total_size += data.len() as u64;
+        for c in data {
+            self.step(*c);
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.digest().into_bytes()
+    }
+}
+
+/// A parsed ssdeep fuzzy hash
+struct FuzzyHash {
+    block_size: u64,
+    first: Vec<u8>,
+    second: Vec<u8>,
+}
+
+/// Check whether a string looks like an ssdeep fuzzy hash, `blocksize:hash:hash`
+pub fn is_fuzzy_hash(s: &str) -> bool {
+    parse(s).is_some()
+}
+
+fn parse(s: &str) -> Option<FuzzyHash> {
+    let mut parts = s.trim().splitn(3, ':');
+    let block_size: u64 = parts.next()?.parse().ok()?;
+    let first = parts.next()?;
+    let second = parts.next()?;
+    // Some tools append the filename in quotes after a comma
+    let second = second.split(',').next()?;
+    let valid = |p: &str| p.len() <= SPAMSUM_LENGTH && p.bytes().all(|c| B64.contains(&c));
+    if block_size < MIN_BLOCKSIZE || !valid(first) || !valid(second) {
+        return None;
+    }
+    Some(FuzzyHash {
+        block_size,
+        first: eliminate_sequences(first.as_bytes()),
+        second: eliminate_sequences(second.as_bytes()),
+    })
+}
+
+/// Runs of more than three identical characters carry little information and are shortened
+fn eliminate_sequences(s: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(s.len());
+    for &c in s {
+        let n = out.len();
+        if n >= 3 && out[n - 1] == c && out[n - 2] == c && out[n - 3] == c {
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Compare two ssdeep fuzzy hashes, returning a similarity score from 0 to 100.
+/// Returns None if either is not a valid fuzzy hash.
+pub fn compare(a: &str, b: &str) -> Option<u32> {
+    let a = parse(a)?;
+    let b = parse(b)?;
+    // Hashes can only be compared if their block sizes are the same or adjacent
+    let score = if a.block_size == b.block_size {
+        if a.first == b.first && a.second == b.second {
+            return Some(100);
+        }
+        score_strings(&a.first, &b.first, a.block_size).max(score_strings(
+            &a.second,
+            &b.second,
+            a.block_size * 2,
+        ))
+    } else if a.block_size * 2 == b.block_size {
+        score_strings(&a.second, &b.first, b.block_size)
+    } else if b.block_size * 2 == a.block_size {
+        score_strings(&a.first, &b.second, a.block_size)
+    } else {
+        0
+    };
+    Some(score)
+}
+
+fn score_strings(s1: &[u8], s2: &[u8], block_size: u64) -> u32 {
+    if !has_common_substring(s1, s2) {
+        return 0;
+    }
+    let distance = edit_distance(s1, s2) as u64;
+    let scaled = distance * SPAMSUM_LENGTH as u64 / (s1.len() + s2.len()) as u64;
+    let scaled = 100 * scaled / SPAMSUM_LENGTH as u64;
+    if scaled >= 100 {
+        return 0;
+    }
+    let mut score = 100 - scaled;
+    // Small block sizes can't produce confident matches, so cap the score
+    if block_size < (99 + ROLLING_WINDOW as u64) / ROLLING_WINDOW as u64 * MIN_BLOCKSIZE {
+        let cap = block_size / MIN_BLOCKSIZE * s1.len().min(s2.len()) as u64;
+        score = score.min(cap);
+    }
+    score as u32
+}
+
+/// Unrelated hashes are only compared if they share a run as long as the rolling window
+fn has_common_substring(s1: &[u8], s2: &[u8]) -> bool {
+    if s1.len() < ROLLING_WINDOW || s2.len() < ROLLING_WINDOW {
+        return false;
+    }
+    s1.windows(ROLLING_WINDOW)
+        .any(|w1| s2.windows(ROLLING_WINDOW).any(|w2| w1 == w2))
+}
+
+/// Edit distance where insertion and deletion cost 1 and substitution costs 2
+fn edit_distance(s1: &[u8], s2: &[u8]) -> usize {
+    let mut prev: Vec<usize> = (0..=s2.len()).collect();
+    let mut cur = vec![0; s2.len() + 1];
+    for (i, c1) in s1.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, c2) in s2.iter().enumerate() {
+            let substitute = prev[j] + if c1 == c2 { 0 } else { 2 };
+            cur[j + 1] = substitute.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[s2.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fuzzy_hash(data: &[u8]) -> String {
+        let mut hasher = Box::new(FuzzyHasher::new());
+        hasher.update(data);
+        String::from_utf8(hasher.finalize()).unwrap()
+    }
+
+    /// Deterministic pseudo-random text so that the piece boundaries are realistic
+    fn sample_data(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                b'a' + ((state >> 16) % 26) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(fuzzy_hash(b""), "3::");
+    }
+
+    #[test]
+    fn test_matches_ssdeep() {
+        // Output of `ssdeep -b` for files containing exactly these sentences
+        let ctph = b"Also called fuzzy hashes, Ctph can match inputs that have homologies.";
+        let upper = b"Also called fuzzy hashes, CTPH can match inputs that have homologies.";
+        let ctph_hash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+        let upper_hash = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2C";
+        assert_eq!(fuzzy_hash(ctph), ctph_hash);
+        assert_eq!(fuzzy_hash(upper), upper_hash);
+        assert_eq!(compare(ctph_hash, upper_hash), Some(22));
+    }
+
+    #[test]
+    fn test_full_digest() {
+        // Zeros leave the rolling hash at zero, so the last piece ends exactly at the end of the
+        // input. The character kept for the rest of a full digest is still included.
+        let mut data = sample_data(7300, 7);
+        data.extend_from_slice(&[0; 16]);
+        let hash = fuzzy_hash(&data);
+        let first = hash.split(':').nth(1).unwrap();
+        assert_eq!(first.len(), SPAMSUM_LENGTH, "{}", hash);
+    }
+
+    #[test]
+    fn test_similarity() {
+        let original = sample_data(20_000, 1);
+        let mut modified = original.clone();
+        modified[10_000..10_100].copy_from_slice(&[b'z'; 100]);
+        let unrelated = sample_data(20_000, 2);
+
+        let original_hash = fuzzy_hash(&original);
+        assert!(is_fuzzy_hash(&original_hash), "{}", original_hash);
+        assert_eq!(compare(&original_hash, &original_hash), Some(100));
+
+        let modified_score = compare(&original_hash, &fuzzy_hash(&modified)).unwrap();
+        assert!(modified_score > 50 && modified_score < 100);
+        assert_eq!(compare(&original_hash, &fuzzy_hash(&unrelated)), Some(0));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(is_fuzzy_hash("3:hMCEpn:hq"));
+        assert!(is_fuzzy_hash(
+            "96:BXfyaAHqa0mH5QGgKhhfCUF7pUKkjUO:BXfdAHqTmH5QlKhhfCUFoRj,\"file\""
+        ));
+        assert!(!is_fuzzy_hash("d229da563da18fe5d58cd95a6467d584"));
+        assert!(!is_fuzzy_hash("3:hM CE:hq"));
+        assert!(!is_fuzzy_hash("x:hMCE:hq"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance(b"abc", b"abc"), 0);
+        assert_eq!(edit_distance(b"abc", b"abd"), 2);
+        assert_eq!(edit_distance(b"abc", b"ab"), 1);
+    }
+}
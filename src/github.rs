@@ -0,0 +1,137 @@
+/// Find the checksums file published with a GitHub release and download it. The release is given
+/// as `owner/repo`, optionally followed by `@tag`, otherwise the latest release is used. Returns
+/// the URL of the checksums file and its contents.
+#[cfg(feature = "url")]
+pub fn download_checksums(
+    release: &str,
+    input_name: Option<&str>,
+) -> Result<(String, Vec<u8>), String> {
+    use serde_json::Value;
+
+    let (repo, tag) = match release.split_once('@') {
+        Some((repo, tag)) => (repo, Some(tag)),
+        None => (release, None),
+    };
+    if repo.split('/').filter(|p| !p.is_empty()).count() != 2 {
+        return Err(format!(
+            "'{}' is not a GitHub repository in the form owner/repo",
+            repo
+        ));
+    }
+    let api_url = match tag {
+        Some(tag) => format!(
+            "https://api.github.com/repos/{}/releases/tags/{}",
+            repo, tag
+        ),
+        None => format!("https://api.github.com/repos/{}/releases/latest", repo),
+    };
+    let response: Value = serde_json::from_slice(&download(&api_url)?)
+        .map_err(|_| format!("Unexpected response from '{}'", api_url))?;
+    let assets: Vec<(&str, &str)> = response["assets"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|a| Some((a["name"].as_str()?, a["browser_download_url"].as_str()?)))
+        .collect();
+    let url = choose_checksums_asset(&assets, input_name).ok_or_else(|| {
+        format!(
+            "No checksums file was found in release {} of {}",
+            response["tag_name"].as_str().unwrap_or("(unknown)"),
+            repo
+        )
+    })?;
+    Ok((url.to_owned(), download(url)?))
+}
+
+/// Download from GitHub, with a token from the environment if there is one
+#[cfg(feature = "url")]
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    let mut request = ureq::get(url).set("Accept", "application/vnd.github+json");
+    // Anonymous requests are rate limited quite heavily
+    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    super::input::download(request, &[404])?
+        .ok_or_else(|| format!("'{}' was not found on GitHub", url))
+}
+
+#[cfg(not(feature = "url"))]
+pub fn download_checksums(
+    _release: &str,
+    _input_name: Option<&str>,
+) -> Result<(String, Vec<u8>), String> {
+    Err(
+        "GitHub releases are not supported by this build of hashgood (requires the url feature)"
+            .to_owned(),
+    )
+}
+
+/// Choose the release asset most likely to hold the checksum of the input, preferring one made
+/// for that file alone, then a list of strong checksums. Returns its download URL.
+#[cfg_attr(not(feature = "url"), allow(dead_code))]
+fn choose_checksums_asset<'a>(
+    assets: &[(&str, &'a str)],
+    input_name: Option<&str>,
+) -> Option<&'a str> {
+    let input_name = input_name.map(str::to_lowercase);
+    let score = |name: &str| {
+        let name = name.to_lowercase();
+        if [".sig", ".asc", ".minisig", ".pem"]
+            .iter()
+            .any(|s| name.ends_with(s))
+        {
+            return 0;
+        }
+        let extension = input_name
+            .as_ref()
+            .and_then(|i| name.strip_prefix(i.as_str()));
+        if let Some(".sha256" | ".sha512" | ".sha256sum" | ".sha512sum" | ".sha1" | ".md5") =
+            extension
+        {
+            return 3;
+        }
+        if ["checksum", "sha256sum", "sha512sum", "shasum"]
+            .iter()
+            .any(|s| name.contains(s))
+        {
+            return 2;
+        }
+        if ["sha1sum", "md5sum"].iter().any(|s| name.contains(s)) {
+            return 1;
+        }
+        0
+    };
+    // Earlier assets win ties
+    assets
+        .iter()
+        .rev()
+        .map(|(name, url)| (score(name), *url))
+        .filter(|(score, _)| *score > 0)
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, url)| url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_checksums_asset() {
+        let assets = [
+            ("tool-linux.tar.gz", "1"),
+            ("tool-mac.tar.gz.sha256", "2"),
+            ("MD5SUMS", "3"),
+            ("checksums.txt", "4"),
+            ("checksums.txt.sig", "5"),
+            ("SHA256SUMS", "6"),
+        ];
+        assert_eq!(choose_checksums_asset(&assets, None), Some("4"));
+        assert_eq!(
+            choose_checksums_asset(&assets, Some("tool-mac.tar.gz")),
+            Some("2")
+        );
+        assert_eq!(choose_checksums_asset(&assets[..3], None), Some("3"));
+        assert_eq!(choose_checksums_asset(&assets[..2], None), None);
+    }
+}
@@ -0,0 +1,100 @@
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use std::sync::OnceLock;
+use unic_langid::LanguageIdentifier;
+
+/// Look up a message in the user's language, such as `tr!("result-ok")`, with any arguments
+/// given by name like `tr!("pieces-summary", calculated = 3, expected = 4)`
+macro_rules! tr {
+    ($id:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::message($id, vec![$((stringify!($name), $value.into())),*])
+    };
+}
+
+/// The message catalogue for each language, built into the binary. English is complete and is
+/// used for any message which a translation doesn't have.
+const CATALOGUES: &[(&str, &str)] = &[("en", include_str!("../i18n/en.ftl"))];
+
+static BUNDLES: OnceLock<Vec<FluentBundle<FluentResource>>> = OnceLock::new();
+
+/// The language asked for by the environment, in the same order of precedence as gettext
+fn requested_language() -> Option<LanguageIdentifier> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))?;
+    // Locales look like de_DE.UTF-8 or de_DE@euro
+    let tag = locale.split(['.', '@']).next()?.replace('_', "-");
+    tag.parse().ok()
+}
+
+fn bundle(language: &str, source: &str) -> FluentBundle<FluentResource> {
+    let id: LanguageIdentifier = language.parse().unwrap_or_default();
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Isolation marks are for mixing text directions in a GUI and appear as junk in a terminal
+    bundle.set_use_isolating(false);
+    let resource =
+        FluentResource::try_new(source.to_owned()).unwrap_or_else(|(resource, _)| resource);
+    let _ = bundle.add_resource(resource);
+    bundle
+}
+
+/// The catalogues to look for messages in, in order: the requested language, then English
+fn bundles() -> &'static [FluentBundle<FluentResource>] {
+    BUNDLES.get_or_init(|| {
+        let requested = requested_language();
+        let mut catalogues: Vec<&(&str, &str)> = CATALOGUES
+            .iter()
+            .filter(|(language, _)| {
+                requested.as_ref().map(|r| r.language.as_str()) == Some(*language)
+            })
+            .collect();
+        catalogues.extend(CATALOGUES.iter().filter(|(language, _)| *language == "en"));
+        catalogues
+            .into_iter()
+            .map(|(language, source)| bundle(language, source))
+            .collect()
+    })
+}
+
+/// Format the message with the given ID. Use the `tr!` macro rather than calling this directly.
+pub fn message(id: &str, args: Vec<(&str, FluentValue)>) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(name, value);
+    }
+    bundles()
+        .iter()
+        .find_map(|bundle| {
+            let pattern = bundle.get_message(id)?.value()?;
+            let mut errors = vec![];
+            Some(
+                bundle
+                    .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                    .into_owned(),
+            )
+        })
+        // A missing message is a bug, but the ID is better than nothing
+        .unwrap_or_else(|| id.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogues() {
+        for (language, source) in CATALOGUES {
+            assert!(
+                FluentResource::try_new(source.to_string()).is_ok(),
+                "{}.ftl has syntax errors",
+                language
+            );
+        }
+        assert_eq!(tr!("result-ok"), "OK");
+        assert_eq!(
+            tr!("pieces-summary", calculated = 3, expected = 4),
+            "3 pieces calculated, 4 expected"
+        );
+        assert_eq!(tr!("no-such-message"), "no-such-message");
+    }
+}
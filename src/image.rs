@@ -0,0 +1,360 @@
+use super::calculate::{self, InputData};
+use super::error::HashgoodError;
+use super::Algorithm;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::Path;
+
+/// Largest file inside an image which is read into memory to check whether it is a manifest
+const MAX_DOCUMENT_LEN: u64 = 4 * 1024 * 1024;
+
+/// The type of a function which verifies one blob of an image, given its path inside the image,
+/// the algorithm and digest it should have, and its contents
+pub type BlobFn<'f> =
+    dyn FnMut(&str, Algorithm, Vec<u8>, InputData) -> Result<(), HashgoodError> + 'f;
+
+/// The files in a container image and the JSON documents among them, such as manifests
+#[derive(Default)]
+struct Contents {
+    /// Path of every file, relative to the root of the image
+    files: BTreeSet<String>,
+    /// Small files which parsed as JSON, by path
+    documents: BTreeMap<String, Value>,
+}
+
+/// The documents an image layout is described by: an OCI index, or the manifest written by
+/// `docker save`
+const ROOTS: [&str; 2] = ["index.json", "manifest.json"];
+
+/// Pass each blob of a container image to the function along with the digest it should have.
+/// The image can be an OCI image layout directory, a `docker save` or OCI archive tarball, or a
+/// manifest file with its blobs alongside. Returns a description of each blob which is referenced
+/// by the image but couldn't be verified.
+pub fn for_each_blob(path: &Path, f: &mut BlobFn) -> Result<Vec<String>, HashgoodError> {
+    if is_tarball(path) {
+        let (expected, mut unverified) = read_tarball(path)?.expected_digests(&ROOTS);
+        for_each_tar_member(path, &mut |member, data| match expected.get(member) {
+            Some(digest) => verify_blob(member, digest, data, f, &mut unverified),
+            None => Ok(()),
+        })?;
+        return Ok(unverified);
+    }
+    let (dir, contents, roots) = if path.is_dir() {
+        (path, read_directory(path)?, ROOTS.to_vec())
+    } else {
+        // A manifest fetched from a registry, with the blobs saved in the same directory
+        let dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_str()
+            .unwrap_or_default();
+        let manifest = std::fs::read(path)
+            .map_err(|e| format!("Unable to read '{}': {}", path.to_string_lossy(), e))?;
+        let manifest = serde_json::from_slice(&manifest)
+            .map_err(|_| format!("'{}' is not an image manifest", path.to_string_lossy()))?;
+        let mut contents = list_blob_files(dir)?;
+        contents.documents.insert(name.to_owned(), manifest);
+        (dir, contents, vec![name])
+    };
+    let (expected, mut unverified) = contents.expected_digests(&roots);
+    for (file, digest) in &expected {
+        let data = InputData::Stream(calculate::get_input_reader(&dir.join(file))?);
+        verify_blob(file, digest, data, f, &mut unverified)?;
+    }
+    Ok(unverified)
+}
+
+fn verify_blob(
+    path: &str,
+    digest: &str,
+    data: InputData,
+    f: &mut BlobFn,
+    unverified: &mut Vec<String>,
+) -> Result<(), HashgoodError> {
+    match parse_digest(digest) {
+        Some((alg, bytes)) => f(path, alg, bytes, data),
+        None => {
+            unverified.push(format!("'{}' has an unsupported digest {}", path, digest));
+            Ok(())
+        }
+    }
+}
+
+impl Contents {
+    /// Work out which digest each file in the image should have, starting from the documents
+    /// at the roots and following the references between them. Returns the expected digest of
+    /// each file by path, and a description of each reference which couldn't be found.
+    fn expected_digests(&self, roots: &[&str]) -> (BTreeMap<String, String>, Vec<String>) {
+        let mut expected = BTreeMap::new();
+        let mut missing = vec![];
+        // Blobs in an OCI image layout are named after their own digest
+        for file in &self.files {
+            if let Some(digest) = digest_from_blob_path(file) {
+                expected.insert(file.clone(), digest);
+            }
+        }
+        let mut seen = BTreeSet::new();
+        let mut queue: Vec<&Value> = roots
+            .iter()
+            .filter_map(|r| self.documents.get(*r))
+            .collect();
+        if queue.is_empty() && expected.is_empty() {
+            missing.push("No image manifest or blobs were found".to_owned());
+        }
+        while let Some(document) = queue.pop() {
+            if let Some(entries) = document.as_array() {
+                for entry in entries {
+                    self.expect_docker_entry(entry, &mut expected, &mut missing);
+                }
+                continue;
+            }
+            for digest in referenced_digests(document) {
+                if !seen.insert(digest.clone()) {
+                    continue;
+                }
+                match self.find_blob(&digest) {
+                    Some(file) => {
+                        if let Some(child) = self.documents.get(&file) {
+                            queue.push(child);
+                        }
+                        expected.insert(file, digest);
+                    }
+                    None => missing.push(format!("Blob {} is missing", digest)),
+                }
+            }
+        }
+        (expected, missing)
+    }
+
+    /// Older versions of `docker save` name the config after its digest but give each layer an
+    /// arbitrary name. The layer digests are listed in order in the config instead.
+    fn expect_docker_entry(
+        &self,
+        entry: &Value,
+        expected: &mut BTreeMap<String, String>,
+        missing: &mut Vec<String>,
+    ) {
+        let mut expect = |file: &str, digest: Option<String>| match digest {
+            Some(_) if !self.files.contains(file) => {
+                missing.push(format!("'{}' is missing", file));
+            }
+            Some(digest) => {
+                expected.insert(file.to_owned(), digest);
+            }
+            None => missing.push(format!("The digest of '{}' is unknown", file)),
+        };
+        let config = match entry["Config"].as_str() {
+            Some(config) => config,
+            None => return,
+        };
+        let config_digest = digest_from_blob_path(config).or_else(|| {
+            let hex = config.strip_suffix(".json")?;
+            Some(format!("sha256:{}", hex))
+        });
+        expect(config, config_digest);
+        let diff_ids = self.documents.get(config).map(|c| &c["rootfs"]["diff_ids"]);
+        let layers = entry["Layers"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for (i, layer) in layers.iter().filter_map(Value::as_str).enumerate() {
+            let digest = digest_from_blob_path(layer).or_else(|| {
+                let diff_id = diff_ids?.get(i)?.as_str()?;
+                Some(diff_id.to_owned())
+            });
+            expect(layer, digest);
+        }
+    }
+
+    /// Find the file holding the blob with the given digest
+    fn find_blob(&self, digest: &str) -> Option<String> {
+        let (_, hex) = digest.split_once(':')?;
+        [
+            format!("blobs/{}", digest.replacen(':', "/", 1)),
+            hex.to_owned(),
+            digest.to_owned(),
+        ]
+        .into_iter()
+        .find(|f| self.files.contains(f))
+    }
+}
+
+/// The digests of the blobs described by an image index or manifest
+fn referenced_digests(document: &Value) -> Vec<String> {
+    let mut digests = vec![];
+    let mut add = |descriptor: &Value| {
+        if let Some(digest) = descriptor["digest"].as_str() {
+            digests.push(digest.to_owned());
+        }
+    };
+    add(&document["config"]);
+    for key in ["layers", "manifests"] {
+        for descriptor in document[key]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+        {
+            add(descriptor);
+        }
+    }
+    digests
+}
+
+/// Parse a digest in the `algorithm:hex` form used by container images
+fn parse_digest(digest: &str) -> Option<(Algorithm, Vec<u8>)> {
+    let (alg, hex) = digest.split_once(':')?;
+    let alg = match alg {
+        "sha256" => Algorithm::Sha256,
+        "sha512" => Algorithm::Sha512,
+        _ => return None,
+    };
+    let bytes = hex::decode(hex).ok()?;
+    (Algorithm::from_len(bytes.len()).ok()? == alg).then_some((alg, bytes))
+}
+
+/// The digest of a blob from its path in an OCI image layout, such as `blobs/sha256/<hex>`
+fn digest_from_blob_path(path: &str) -> Option<String> {
+    let (alg, hex) = path.strip_prefix("blobs/")?.split_once('/')?;
+    Some(format!("{}:{}", alg, hex))
+}
+
+/// Whether a file might be a JSON document worth reading into memory
+fn may_be_document(path: &str, len: u64) -> bool {
+    len <= MAX_DOCUMENT_LEN && (path.ends_with(".json") || path.starts_with("blobs/"))
+}
+
+fn read_directory(dir: &Path) -> Result<Contents, HashgoodError> {
+    let mut contents = Contents::default();
+    for entry in walkdir::WalkDir::new(dir) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(dir)?;
+        let path = path.to_string_lossy().replace('\\', "/");
+        if may_be_document(&path, entry.metadata()?.len()) {
+            if let Ok(document) = serde_json::from_slice(&std::fs::read(entry.path())?) {
+                contents.documents.insert(path.clone(), document);
+            }
+        }
+        contents.files.insert(path);
+    }
+    Ok(contents)
+}
+
+/// List the files which could be blobs next to a manifest, without reading any of them
+fn list_blob_files(dir: &Path) -> Result<Contents, HashgoodError> {
+    let mut contents = Contents::default();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            contents
+                .files
+                .insert(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    let blobs = dir.join("blobs");
+    if blobs.is_dir() {
+        contents.files.extend(
+            read_directory(&blobs)?
+                .files
+                .into_iter()
+                .map(|f| format!("blobs/{}", f)),
+        );
+    }
+    Ok(contents)
+}
+
+fn is_tarball(path: &Path) -> bool {
+    let name = path.to_string_lossy().to_lowercase();
+    name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// List the files in a tarball and read its documents. The blobs themselves are read later, so
+/// the tarball is read twice.
+fn read_tarball(path: &Path) -> Result<Contents, HashgoodError> {
+    let mut contents = Contents::default();
+    for_each_tar_member(path, &mut |member, data| {
+        contents.files.insert(member.to_owned());
+        let mut reader = match data {
+            InputData::Stream(reader) => reader,
+            _ => return Ok(()),
+        };
+        // Skip anything which obviously isn't JSON, such as layers, without reading it all
+        let mut first = [0];
+        if reader.read(&mut first)? == 0 || !b"{[".contains(&first[0]) {
+            return Ok(());
+        }
+        let mut document = first.to_vec();
+        reader.take(MAX_DOCUMENT_LEN).read_to_end(&mut document)?;
+        if may_be_document(member, document.len() as u64) {
+            if let Ok(document) = serde_json::from_slice(&document) {
+                contents.documents.insert(member.to_owned(), document);
+            }
+        }
+        Ok(())
+    })?;
+    Ok(contents)
+}
+
+#[cfg(feature = "archive")]
+fn for_each_tar_member(path: &Path, f: &mut super::archive::MemberFn) -> Result<(), HashgoodError> {
+    super::archive::for_each_member(path, &mut |member, data| {
+        f(member.strip_prefix("./").unwrap_or(member), data)
+    })
+}
+
+#[cfg(not(feature = "archive"))]
+fn for_each_tar_member(
+    _path: &Path,
+    _f: &mut super::archive::MemberFn,
+) -> Result<(), HashgoodError> {
+    Err(
+        "Image tarballs are not supported by this build of hashgood (requires the archive feature)"
+            .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_expected_digests() {
+        let layer = format!("sha256:{}", "1".repeat(64));
+        let config = format!("sha256:{}", "2".repeat(64));
+        let missing = format!("sha256:{}", "3".repeat(64));
+        let mut contents = Contents::default();
+        contents
+            .files
+            .insert(format!("blobs/sha256/{}", "1".repeat(64)));
+        contents.files.insert("index.json".to_owned());
+        contents.files.insert(format!("{}.json", "2".repeat(64)));
+        contents.files.insert("old/layer.tar".to_owned());
+        contents.documents.insert(
+            "index.json".to_owned(),
+            json!({ "manifests": [{ "digest": layer }, { "digest": missing }] }),
+        );
+        contents.documents.insert(
+            "manifest.json".to_owned(),
+            json!([{ "Config": format!("{}.json", "2".repeat(64)), "Layers": ["old/layer.tar"] }]),
+        );
+        contents.documents.insert(
+            format!("{}.json", "2".repeat(64)),
+            json!({ "rootfs": { "diff_ids": [layer] } }),
+        );
+
+        let (expected, unverified) = contents.expected_digests(&ROOTS);
+        assert_eq!(expected.len(), 3);
+        assert_eq!(expected[&format!("blobs/sha256/{}", "1".repeat(64))], layer);
+        assert_eq!(expected[&format!("{}.json", "2".repeat(64))], config);
+        assert_eq!(expected["old/layer.tar"], layer);
+        assert_eq!(unverified, vec![format!("Blob {} is missing", missing)]);
+    }
+}
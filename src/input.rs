@@ -0,0 +1,438 @@
+use super::{MessageLevel, Opt};
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// How to treat symbolic links found while walking a directory
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Symlinks {
+    /// Hash links to files, but don't descend into links to directories
+    Files,
+    /// Follow all links, skipping any which would form a loop
+    Follow,
+    /// Ignore all links
+    Skip,
+}
+
+impl Symlinks {
+    fn from_opt(opt: &Opt) -> Self {
+        if opt.follow_symlinks {
+            Symlinks::Follow
+        } else if opt.skip_symlinks {
+            Symlinks::Skip
+        } else {
+            Symlinks::Files
+        }
+    }
+}
+
+/// Options affecting how a path is expanded into a list of inputs
+#[derive(Debug, Clone, Copy)]
+struct ExpandOptions {
+    recursive: bool,
+    symlinks: Symlinks,
+}
+
+/// A single input to be hashed
+#[derive(Debug, PartialEq)]
+pub struct Input {
+    /// Where to read the data from, which may be "-" for standard input
+    pub path: PathBuf,
+    /// The name used to display the input and to match it against filenames in a digests file
+    pub name: String,
+}
+
+/// Determine the list of inputs to be hashed from the options provided by the user. Any inputs
+/// which are skipped are described in `messages`.
+///
+/// Normally this is exactly the path specified. Standard input is named "-" unless a name was
+/// specified with `--stdin-name`. If the path doesn't exist but looks like a glob
+/// pattern such as `*.iso`, it is expanded to all matching paths. This is necessary on Windows,
+/// where the shell does not do it for us.
+///
+/// If `recursive` is set and a path is a directory, every regular file inside it is an input,
+/// named by its path relative to that directory.
+///
+/// With `--files-from`, the inputs are the paths listed in a file instead, each named exactly as
+/// listed.
+pub fn get_inputs(
+    opt: &Opt,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Vec<Input>, String> {
+    let options = ExpandOptions {
+        recursive: opt.recursive,
+        symlinks: Symlinks::from_opt(opt),
+    };
+    if let Some(list) = &opt.files_from {
+        return get_listed_inputs(list, opt.null, options, messages);
+    }
+    let path = opt.input.as_deref().unwrap_or(Path::new("-"));
+    if path.to_str() == Some("-") {
+        return Ok(vec![Input {
+            path: path.to_owned(),
+            name: opt.stdin_name.clone().unwrap_or_else(|| "-".to_owned()),
+        }]);
+    }
+    expand_path(path, options, messages)
+}
+
+/// Find every regular file inside a directory, named by its path relative to that directory
+pub fn get_directory_inputs(
+    root: &Path,
+    opt: &Opt,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Vec<Input>, String> {
+    walk_directory(root, Symlinks::from_opt(opt), messages)
+}
+
+/// Determine the inputs from the paths listed in a file, or standard input if it is `-`
+fn get_listed_inputs(
+    list: &Path,
+    null: bool,
+    options: ExpandOptions,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Vec<Input>, String> {
+    let mut data = vec![];
+    let read = match list.to_str() {
+        Some("-") => std::io::stdin().read_to_end(&mut data),
+        _ => std::fs::File::open(list).and_then(|mut f| f.read_to_end(&mut data)),
+    };
+    read.map_err(|e| format!("Unable to read file list '{}': {}", list.display(), e))?;
+    let mut inputs = vec![];
+    for path in split_file_list(&data, null) {
+        let name = path.to_string_lossy().into_owned();
+        if !path.is_dir() {
+            inputs.push(Input { path, name });
+        } else if options.recursive {
+            for input in walk_directory(&path, options.symlinks, messages)? {
+                inputs.push(Input {
+                    name: format!("{}/{}", name.trim_end_matches('/'), input.name),
+                    ..input
+                });
+            }
+        } else {
+            messages.push((
+                MessageLevel::Note,
+                format!("Skipped '{}' because it is a directory.", name),
+            ));
+        }
+    }
+    if inputs.is_empty() {
+        return Err(format!("No files are listed in '{}'.", list.display()));
+    }
+    Ok(inputs)
+}
+
+/// Split a list of paths separated by newlines, or NUL bytes if `null` is set as produced by
+/// `find -print0`. Empty entries are ignored.
+fn split_file_list(data: &[u8], null: bool) -> Vec<PathBuf> {
+    let separator = if null { b'\0' } else { b'\n' };
+    data.split(|b| *b == separator)
+        .map(|entry| match null {
+            true => entry,
+            false => entry.strip_suffix(b"\r").unwrap_or(entry),
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(bytes_to_os_string(entry)))
+        .collect()
+}
+
+#[cfg(unix)]
+fn bytes_to_os_string(bytes: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::OsStr::from_bytes(bytes).to_owned()
+}
+
+#[cfg(not(unix))]
+fn bytes_to_os_string(bytes: &[u8]) -> OsString {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
+
+/// Determine the inputs for a path, URL or glob pattern
+fn expand_path(
+    path: &Path,
+    options: ExpandOptions,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Vec<Input>, String> {
+    if let Some(url) = as_url(path) {
+        return Ok(vec![Input {
+            path: path.to_owned(),
+            name: url_filename(url).to_owned(),
+        }]);
+    }
+    let pattern = path.to_string_lossy();
+    if path.exists() || !is_glob_pattern(&pattern) {
+        return get_path_inputs(path, options, messages);
+    }
+    let paths =
+        glob::glob(&pattern).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+    let mut inputs = vec![];
+    for path in paths {
+        let path = path.map_err(|e| format!("Unable to read path: {}", e))?;
+        // Without -r, directories matched by the pattern aren't relevant
+        if path.is_dir() && !options.recursive {
+            continue;
+        }
+        inputs.extend(get_path_inputs(&path, options, messages)?);
+    }
+    if inputs.is_empty() {
+        return Err(format!("No files match the pattern '{}'.", pattern));
+    }
+    Ok(inputs)
+}
+
+/// The name of the file which would be produced by joining split parts together, based on the
+/// name of the first part. Suffixes such as `.part01`, `.001` and `.aa` (from `split`) are removed.
+pub fn joined_name(first_part: &str) -> String {
+    if let Some((base, suffix)) = first_part.rsplit_once('.') {
+        let number = suffix.strip_prefix("part").unwrap_or(suffix);
+        let numbered = !number.is_empty() && number.chars().all(|c| c.is_ascii_digit());
+        // split(1) names the first part "aa"
+        if !base.is_empty() && (numbered || suffix == "aa") {
+            return base.to_owned();
+        }
+    }
+    first_part.to_owned()
+}
+
+/// If the input path is actually an HTTP or HTTPS URL, return it as a string
+pub fn as_url(path: &Path) -> Option<&str> {
+    let path = path.to_str()?;
+    if path.starts_with("http://") || path.starts_with("https://") {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// The largest checksums file or API response which will be downloaded. Anything bigger is not
+/// what we were looking for.
+#[cfg(feature = "url")]
+const MAX_DOWNLOAD: u64 = 16 * 1024 * 1024;
+
+/// Make a request for a small document such as a checksums file and read all of the response.
+/// Returns None if the server answers with one of the `missing` statuses.
+#[cfg(feature = "url")]
+pub fn download(request: ureq::Request, missing: &[u16]) -> Result<Option<Vec<u8>>, String> {
+    let url = request.url().to_owned();
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, _)) if missing.contains(&code) => return Ok(None),
+        Err(ureq::Error::Status(code, _)) => {
+            return Err(format!(
+                "Request to '{}' failed with HTTP status {}",
+                url, code
+            ));
+        }
+        Err(e) => return Err(format!("Request to '{}' failed: {}", url, e)),
+    };
+    let mut data = vec![];
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Download of '{}' failed: {}", url, e))?;
+    if data.len() as u64 > MAX_DOWNLOAD {
+        return Err(format!(
+            "Download of '{}' is larger than {} bytes",
+            url, MAX_DOWNLOAD
+        ));
+    }
+    Ok(Some(data))
+}
+
+/// Guess the filename of a download from the last segment of its URL, ignoring any query string
+fn url_filename(url: &str) -> &str {
+    let without_query = url.split(['?', '#']).next().unwrap_or(url);
+    let without_scheme = without_query
+        .split_once("://")
+        .map_or(without_query, |(_, r)| r);
+    let (host, path) = without_scheme
+        .split_once('/')
+        .unwrap_or((without_scheme, ""));
+    path.rsplit('/')
+        .find(|segment| !segment.is_empty())
+        .unwrap_or(host)
+}
+
+/// Does this path contain any of the special characters used by glob patterns?
+fn is_glob_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Determine the inputs for a single path which is known not to be a glob pattern
+fn get_path_inputs(
+    path: &Path,
+    options: ExpandOptions,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Vec<Input>, String> {
+    if options.recursive && path.is_dir() {
+        return walk_directory(path, options.symlinks, messages);
+    }
+    // Taking the filename component should always work?
+    // If not, just fall back to the full path
+    let name = match path.file_name() {
+        Some(filename) => filename.to_string_lossy(),
+        None => path.to_string_lossy(),
+    };
+    Ok(vec![Input {
+        path: path.to_owned(),
+        name: name.to_string(),
+    }])
+}
+
+/// Find all the regular files within a directory, sorted by name for consistent output
+fn walk_directory(
+    root: &Path,
+    symlinks: Symlinks,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Vec<Input>, String> {
+    let mut inputs = vec![];
+    let walk = WalkDir::new(root)
+        .follow_links(symlinks == Symlinks::Follow)
+        .sort_by_file_name();
+    for entry in walk {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if e.loop_ancestor().is_some() => {
+                let path = e.path().unwrap_or(root);
+                messages.push((
+                    MessageLevel::Warning,
+                    format!(
+                        "Skipped '{}' because it is a symlink loop.",
+                        relative_name(root, path)
+                    ),
+                ));
+                continue;
+            }
+            Err(e) if e.path().is_some_and(|p| p.is_symlink() && !p.exists()) => {
+                let path = e.path().unwrap_or(root);
+                messages.push((
+                    MessageLevel::Warning,
+                    format!(
+                        "Skipped '{}' because it is a broken symlink.",
+                        relative_name(root, path)
+                    ),
+                ));
+                continue;
+            }
+            Err(e) => return Err(format!("Unable to read directory: {}", e)),
+        };
+        // When links are followed, entries report the type of their target instead
+        if entry.path_is_symlink() && symlinks != Symlinks::Follow {
+            let target = entry.path();
+            let reason = if symlinks == Symlinks::Skip {
+                "it is a symlink"
+            } else if target.is_dir() {
+                "it is a symlink to a directory"
+            } else if !target.exists() {
+                "it is a broken symlink"
+            } else {
+                ""
+            };
+            if !reason.is_empty() {
+                messages.push((
+                    MessageLevel::Note,
+                    format!(
+                        "Skipped '{}' because {}.",
+                        relative_name(root, entry.path()),
+                        reason
+                    ),
+                ));
+                continue;
+            }
+        } else if !entry.file_type().is_file() {
+            continue;
+        }
+        inputs.push(Input {
+            path: entry.path().to_owned(),
+            name: relative_name(root, entry.path()),
+        });
+    }
+    Ok(inputs)
+}
+
+/// Name a file by its path relative to the root, using forward slashes like a digests file would
+fn relative_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_name() {
+        let root = Path::new("release");
+        assert_eq!(
+            relative_name(root, &root.join("bin").join("tool")),
+            "bin/tool"
+        );
+        assert_eq!(relative_name(root, &root.join("README")), "README");
+    }
+
+    #[test]
+    fn test_url_filename() {
+        assert_eq!(
+            url_filename("https://example.com/releases/file.iso?download=1"),
+            "file.iso"
+        );
+        assert_eq!(url_filename("https://example.com/dir/"), "dir");
+        assert_eq!(url_filename("http://example.com"), "example.com");
+        assert_eq!(url_filename("http://example.com/"), "example.com");
+    }
+
+    #[test]
+    fn test_joined_name() {
+        assert_eq!(joined_name("image.iso.part01"), "image.iso");
+        assert_eq!(joined_name("image.iso.001"), "image.iso");
+        assert_eq!(joined_name("image.iso.aa"), "image.iso");
+        assert_eq!(joined_name("image.iso"), "image.iso");
+        assert_eq!(joined_name("notes.txt"), "notes.txt");
+    }
+
+    #[test]
+    fn test_glob_pattern() {
+        assert!(is_glob_pattern("downloads/*.iso"));
+        assert!(is_glob_pattern("file-?.tar.gz"));
+        assert!(!is_glob_pattern("downloads/file.iso"));
+        assert!(!is_glob_pattern("-"));
+    }
+
+    #[test]
+    fn test_split_file_list() {
+        assert_eq!(
+            split_file_list(b"./a.txt\nsub dir/b.txt\r\n\n", false),
+            vec![PathBuf::from("./a.txt"), PathBuf::from("sub dir/b.txt")]
+        );
+        assert_eq!(
+            split_file_list(b"./a.txt\0line\nbreak.txt\0", true),
+            vec![PathBuf::from("./a.txt"), PathBuf::from("line\nbreak.txt")]
+        );
+    }
+
+    #[test]
+    fn test_single_input() {
+        assert_eq!(
+            expand_path(
+                Path::new("some/dir/file.iso"),
+                ExpandOptions {
+                    recursive: false,
+                    symlinks: Symlinks::Files
+                },
+                &mut vec![]
+            )
+            .unwrap(),
+            vec![Input {
+                path: PathBuf::from("some/dir/file.iso"),
+                name: "file.iso".to_owned(),
+            }]
+        );
+    }
+}
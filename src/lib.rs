@@ -0,0 +1,368 @@
+//! Verify files against checksums, as done by the `hashgood` command line tool.
+//!
+//! Hashes can be parsed from any of the forms hashgood accepts, such as a bare hex digest, a
+//! `sha256sum` listing or a Media Hash List, and then checked against digests calculated from a
+//! file or any other stream of data.
+//!
+//! ```
+//! use hashgood::{create_digests, parse_hash, verify_hash, Hash, InputData, MatchLevel, NamePolicy};
+//!
+//! let candidates = parse_hash("5eb63bbbe01eeed093cb22bb8f5acdc3")?;
+//! let data = InputData::Stream(Box::new(&b"hello world"[..]));
+//! let digests = create_digests(&[candidates.alg()], data)?;
+//! let (alg, bytes) = digests.hashes.into_iter().next().unwrap();
+//! let hash = Hash::new(alg, bytes, "hello.txt");
+//! let verification = verify_hash(&hash, &candidates, NamePolicy::default());
+//! assert!(*verification.match_level() == MatchLevel::Ok);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+/// Look up messages in the user's language
+#[macro_use]
+mod i18n;
+
+/// Read the files inside .zip and .tar archives
+mod archive;
+
+/// Generate a man page from the command line definition
+mod man;
+
+/// Calculate digests for given input data
+mod calculate;
+
+/// Save and resume the progress of long hashing jobs
+mod checkpoint;
+
+/// Read the clipboard in whichever way works in this session
+#[cfg(all(feature = "paste", not(target_family = "wasm")))]
+mod clipboard;
+
+/// Read default settings from the configuration file
+mod config;
+
+/// Remember the digests of files so that unchanged ones aren't hashed again
+mod cache;
+
+/// Check signatures made over digests files
+mod signature;
+
+/// Find checksum files published next to a download
+mod sidecar;
+
+/// Fuzzy hashing compatible with ssdeep, for measuring similarity
+mod fuzzy;
+
+/// Display output nicely in the terminal
+mod display;
+
+/// Find checksums published with GitHub releases
+mod github;
+
+/// Verify the blobs of container images against their digests
+mod image;
+
+/// Read checksum manifests in JSON and YAML formats
+mod manifest;
+
+/// Parse magnet links for the hash of the file they refer to
+mod magnet;
+
+/// Read Media Hash Lists used to check media in film and TV workflows
+mod mhl;
+
+/// Decode self-describing multihash digests and IPFS content identifiers
+mod multihash;
+
+/// Parse .torrent files for their piece hashes
+mod torrent;
+
+/// Read hashes from QR codes in images
+mod qr;
+
+/// Read extended attributes of files
+mod xattr;
+
+/// Determine which files are to be hashed
+mod input;
+
+/// Report progress while reading large inputs
+mod progress;
+
+/// Decide how to warn about matches made with weak algorithms
+mod policy;
+
+/// Collect candidate hashes based on options and match them against a calculated hash
+mod verify;
+
+/// Hash inputs again whenever they change
+mod watch;
+
+/// Errors which can be told apart by programs using the library
+pub mod error;
+
+/// Verify an input in a single call from the library
+mod verifier;
+
+/// The command line interface of the hashgood binary. It is only public so that the binary can
+/// reach it, and isn't part of the library's API.
+#[doc(hidden)]
+pub mod cli;
+
+#[cfg(feature = "async")]
+pub use calculate::create_digests_async;
+pub use calculate::{
+    create_digests, create_digests_with_progress, create_hmacs, CalculateResult, Digests,
+    InputData, ProgressFn,
+};
+/// Run the hashgood command line tool with the arguments of this process, after any
+/// `CandidateSource`s have been registered. It exits the process when it is finished.
+pub use cli::main;
+pub use error::HashgoodError;
+pub use verifier::{Verifier, VerifierResult};
+pub use verify::{
+    parse_check_data, parse_hash, register_candidate_source, verify_hash, CandidateSource,
+    CheckFileOptions, NamePolicy, SourceContext,
+};
+
+use cli::Opt;
+
+/// Types of supported digest algorithm
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+    /// The SHA-256 tree hash used by Amazon Glacier and S3
+    Sha256Tree,
+    /// An ssdeep fuzzy hash, which measures similarity rather than identity
+    Ssdeep,
+    /// 64-bit xxHash, used in media workflows. Fast but not cryptographic.
+    Xxh64,
+    /// 128-bit XXH3, used in media workflows. Fast but not cryptographic.
+    Xxh128,
+}
+
+impl Algorithm {
+    /// Assume a hash type from the binary length. Fortunately the algorithms we care about are different lengths.
+    pub fn from_len(len: usize) -> Result<Algorithm, String> {
+        match len {
+            16 => Ok(Algorithm::Md5),
+            20 => Ok(Algorithm::Sha1),
+            32 => Ok(Algorithm::Sha256),
+            48 => Ok(Algorithm::Sha384),
+            64 => Ok(Algorithm::Sha512),
+            _ => Err(format!("Unrecognised hash length: {} bytes", len)),
+        }
+    }
+}
+
+/// The method by which one or more hashes were supplied to verify the calculated digest
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VerificationSource {
+    CommandArgument,
+    Clipboard,
+    RawFile(String),
+    DigestsFile(String),
+    /// The digest of another file which was hashed alongside the input
+    ComparedFile(String),
+    /// The digest a blob is stored under in a container image
+    ContainerImage(String),
+    /// Part of the name of the input itself
+    InputName,
+    /// The exact topic of a magnet link
+    MagnetLink,
+    /// An extended attribute of the input with the given name
+    ExtendedAttribute(String),
+    /// A QR code in an image file
+    QrCode(String),
+    /// A .torrent file listing the SHA-1 hash of each piece of the given length
+    TorrentFile {
+        path: String,
+        piece_length: u64,
+    },
+}
+
+/// A complete standalone hash result
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hash {
+    alg: Algorithm,
+    #[cfg_attr(feature = "serde", serde(with = "hex::serde"))]
+    bytes: Vec<u8>,
+    filename: String,
+    /// Whether this is an HMAC calculated with a secret key
+    keyed: bool,
+}
+
+impl Hash {
+    pub fn new(alg: Algorithm, bytes: Vec<u8>, filename: &str) -> Self {
+        Self {
+            alg,
+            bytes,
+            filename: filename.to_owned(),
+            keyed: false,
+        }
+    }
+
+    /// The same as `new`, for an HMAC calculated with a secret key. It is compared against
+    /// candidates in constant time.
+    pub fn new_keyed(alg: Algorithm, bytes: Vec<u8>, filename: &str) -> Self {
+        Self {
+            keyed: true,
+            ..Self::new(alg, bytes, filename)
+        }
+    }
+}
+
+/// A possible hash to match against. The algorithm is assumed.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CandidateHash {
+    #[cfg_attr(feature = "serde", serde(with = "hex::serde"))]
+    bytes: Vec<u8>,
+    filename: Option<String>,
+    /// The size of the file in bytes, if the check file lists it
+    size: Option<u64>,
+}
+
+impl CandidateHash {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The file this hash belongs to, if it was listed with one
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+}
+
+/// A list of candidate hashes that our input could potentially match. At this point it is
+/// assumed that we will be verifying a digest of a particular, single algorithm.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CandidateHashes {
+    alg: Algorithm,
+    hashes: Vec<CandidateHash>,
+    source: VerificationSource,
+}
+
+impl CandidateHashes {
+    /// The algorithm to calculate in order to verify against these hashes
+    pub fn alg(&self) -> Algorithm {
+        self.alg
+    }
+
+    pub fn hashes(&self) -> &[CandidateHash] {
+        &self.hashes
+    }
+
+    pub fn source(&self) -> &VerificationSource {
+        &self.source
+    }
+}
+
+/// Summary of an atetmpt to match the calculated digest against candidates
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MatchLevel {
+    Ok,
+    Maybe,
+    /// A fuzzy hash comparison found the input to be partially similar, scored out of 100
+    Similar(u32),
+    Fail,
+}
+
+/// How a file in the input directory differs from the reference directory when comparing trees
+#[derive(Debug, PartialEq)]
+pub enum TreeDifference {
+    /// The file is in both directories but the contents are different
+    Different,
+    /// The file is only in the reference directory
+    Missing,
+    /// The file is only in the input directory
+    Extra,
+}
+
+/// The outcome of verifying one of the files listed in a digests file
+#[derive(Debug, PartialEq)]
+pub enum CheckStatus {
+    Ok,
+    /// The file's digest is different to the one listed
+    Failed,
+    /// The file doesn't exist
+    Missing,
+    /// The file exists but couldn't be read
+    Unreadable(String),
+}
+
+/// The severity of any informational messages to be printed before the final result
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MessageLevel {
+    Error,
+    Warning,
+    Note,
+}
+
+/// Overall details of an attempt to match the calculated digest against candidates
+/// Results can be serialised, but not deserialised since they borrow the matching candidate
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Verification<'a> {
+    match_level: MatchLevel,
+    comparison_hash: Option<&'a CandidateHash>,
+    messages: Vec<(MessageLevel, String)>,
+}
+
+impl<'a> Verification<'a> {
+    pub fn match_level(&self) -> &MatchLevel {
+        &self.match_level
+    }
+
+    /// The candidate which matched, or the only candidate if there was one and it didn't
+    pub fn comparison_hash(&self) -> Option<&'a CandidateHash> {
+        self.comparison_hash
+    }
+
+    /// Warnings and notes about the result, such as a filename which didn't match
+    pub fn messages(&self) -> &[(MessageLevel, String)] {
+        &self.messages
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialise_results() {
+        let candidate = CandidateHash {
+            bytes: vec![0xab, 0xcd],
+            filename: Some("a.iso".to_owned()),
+            size: None,
+        };
+        let json = serde_json::to_string(&candidate).unwrap();
+        assert_eq!(json, r#"{"bytes":"abcd","filename":"a.iso","size":null}"#);
+        assert_eq!(
+            serde_json::from_str::<CandidateHash>(&json).unwrap(),
+            candidate
+        );
+
+        let verification = Verification {
+            match_level: MatchLevel::Similar(80),
+            comparison_hash: Some(&candidate),
+            messages: vec![(MessageLevel::Note, "Close".to_owned())],
+        };
+        let json = serde_json::to_value(&verification).unwrap();
+        assert_eq!(json["match_level"], serde_json::json!({ "Similar": 80 }));
+        assert_eq!(json["messages"][0][0], "Note");
+    }
+}
@@ -0,0 +1,130 @@
+use super::Algorithm;
+use data_encoding::BASE32_NOPAD;
+
+/// The parts of a magnet link that are needed to verify the file it refers to
+#[derive(Debug, PartialEq)]
+pub struct Magnet {
+    /// Suggested filename, from the display name
+    pub name: Option<String>,
+    /// Size of the file in bytes, from the exact length
+    pub length: Option<u64>,
+    /// The strongest hash of the file contents given as an exact topic
+    pub alg: Algorithm,
+    pub bytes: Vec<u8>,
+}
+
+/// Parse a `magnet:?` URI. Only exact topics which are a hash of the file's contents can be used,
+/// such as `urn:sha1:` in base32. A BitTorrent info hash describes the torrent metadata instead.
+pub fn parse_magnet(uri: &str) -> Result<Magnet, String> {
+    let query = uri
+        .strip_prefix("magnet:?")
+        .ok_or_else(|| "Not a magnet link".to_owned())?;
+    let mut name = None;
+    let mut length = None;
+    let mut topics = vec![];
+    let mut info_hash = false;
+    for param in query.split('&') {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        let value = percent_decode(value);
+        // Several exact topics are numbered like xt.1, xt.2
+        match key.split('.').next().unwrap_or(key) {
+            "dn" => name = Some(value),
+            "xl" => length = value.parse().ok(),
+            "xt" if value.starts_with("urn:btih:") || value.starts_with("urn:btmh:") => {
+                info_hash = true
+            }
+            "xt" => topics.extend(parse_topic(&value)),
+            _ => (),
+        }
+    }
+    let strength = |alg: &Algorithm| {
+        [Algorithm::Md5, Algorithm::Sha1]
+            .iter()
+            .position(|a| a == alg)
+    };
+    match topics.into_iter().max_by_key(|(alg, _)| strength(alg)) {
+        Some((alg, bytes)) => Ok(Magnet {
+            name,
+            length,
+            alg,
+            bytes,
+        }),
+        None if info_hash => Err(
+            "The magnet link only has a BitTorrent info hash, which identifies the torrent rather \
+             than the contents of the file"
+                .to_owned(),
+        ),
+        None => Err("The magnet link does not contain a supported hash of the file".to_owned()),
+    }
+}
+
+/// Decode an exact topic URN which is a hash of the file contents
+fn parse_topic(urn: &str) -> Option<(Algorithm, Vec<u8>)> {
+    let (alg, hash) = urn.strip_prefix("urn:")?.split_once(':')?;
+    let bytes = match alg.to_ascii_lowercase().as_str() {
+        "sha1" => decode_sha1(hash)?,
+        // A bitprint is a SHA-1 followed by a Tiger tree hash
+        "bitprint" => decode_sha1(hash.split('.').next()?)?,
+        "md5" => hex::decode(hash).ok()?,
+        _ => return None,
+    };
+    let alg = Algorithm::from_len(bytes.len()).ok()?;
+    Some((alg, bytes))
+}
+
+/// SHA-1 topics are usually base32 but some clients write them in hex
+fn decode_sha1(hash: &str) -> Option<Vec<u8>> {
+    let bytes = BASE32_NOPAD
+        .decode(hash.to_ascii_uppercase().as_bytes())
+        .ok()
+        .or_else(|| hex::decode(hash).ok())?;
+    (bytes.len() == 20).then_some(bytes)
+}
+
+/// Decode the %XX escapes and `+` for space used in URI query values
+fn percent_decode(value: &str) -> String {
+    let mut decoded = vec![];
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let escape: Vec<u8> = bytes.clone().take(2).collect();
+                match std::str::from_utf8(&escape)
+                    .ok()
+                    .and_then(|e| u8::from_str_radix(e, 16).ok())
+                {
+                    Some(c) if escape.len() == 2 => {
+                        decoded.push(c);
+                        bytes.nth(1);
+                    }
+                    _ => decoded.push(b),
+                }
+            }
+            b => decoded.push(b),
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_magnet() {
+        let uri = "magnet:?xt=urn:sha1:JOI7PI4HU3W5JJ6AV6ZIS7Y4VFUMS2K3&dn=hello%20world.txt&xl=12\
+            &xt.2=urn:md5:5eb63bbbe01eeed093cb22bb8f5acdc3";
+        let magnet = parse_magnet(uri).unwrap();
+        assert_eq!(magnet.alg, Algorithm::Sha1);
+        assert_eq!(
+            hex::encode(magnet.bytes),
+            "4b91f7a387a6edd4a7c0afb2897f1ca968c9695b"
+        );
+        assert_eq!(magnet.name.as_deref(), Some("hello world.txt"));
+        assert_eq!(magnet.length, Some(12));
+
+        let torrent = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&dn=x";
+        assert!(parse_magnet(torrent).unwrap_err().contains("info hash"));
+    }
+}
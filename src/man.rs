@@ -0,0 +1,100 @@
+use structopt::clap::{App, ErrorKind};
+
+/// Write a roff man page describing each subcommand of `app`, using the same help text as
+/// `--help` so that the two never disagree
+pub fn man_page(app: App, subcommands: &[&str]) -> String {
+    let name = app.get_name().to_owned();
+    let mut page = format!(
+        ".TH {} 1 \"\" \"{} {}\"\n.SH NAME\n{} \\- {}\n.SH SYNOPSIS\n",
+        name.to_uppercase(),
+        name,
+        env!("CARGO_PKG_VERSION"),
+        name,
+        escape(env!("CARGO_PKG_DESCRIPTION"))
+    );
+    // Long lines are easier to split into each argument and its description
+    let app = app.set_term_width(usize::MAX);
+    let helps: Vec<(&str, String)> = subcommands
+        .iter()
+        .map(|sub| (*sub, help_text(app.clone(), &name, sub)))
+        .collect();
+    for (_, help) in &helps {
+        if let Some(usage) = section(help, "USAGE").next() {
+            page.push_str(&format!(".B {}\n.br\n", escape(usage.trim())));
+        }
+    }
+    page.push_str(&format!(
+        ".SH DESCRIPTION\nRunning {} without a subcommand is the same as \\fB{} verify\\fR.\n",
+        name, name
+    ));
+    for (sub, help) in &helps {
+        page.push_str(&format!(
+            ".SH \"{} {}\"\n",
+            name.to_uppercase(),
+            sub.to_uppercase()
+        ));
+        if let Some(about) = help.lines().nth(1).filter(|l| !l.is_empty()) {
+            page.push_str(&format!("{}\n", escape(about)));
+        }
+        for heading in ["ARGS", "FLAGS", "OPTIONS"] {
+            let mut lines = section(help, heading).peekable();
+            if lines.peek().is_none() {
+                continue;
+            }
+            page.push_str(&format!(".SS {}\n", heading));
+            for line in lines {
+                let line = line.trim();
+                let (spec, description) = line.split_once("  ").unwrap_or((line, ""));
+                page.push_str(&format!(
+                    ".TP\n\\fB{}\\fR\n{}\n",
+                    escape(spec),
+                    escape(description.trim())
+                ));
+            }
+        }
+    }
+    page.push_str(concat!(
+        ".SH \"EXIT STATUS\"\n",
+        ".TP\n0\nEvery input was verified, or its digests were calculated.\n",
+        ".TP\n1\nThere was a problem, such as a file which could not be read.\n",
+        ".TP\n2\nAn input did not match its hash.\n",
+    ));
+    page
+}
+
+/// The `--help` output of one subcommand
+fn help_text(app: App, name: &str, subcommand: &str) -> String {
+    match app.get_matches_from_safe([name, subcommand, "--help"]) {
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => e.message,
+        _ => String::new(),
+    }
+}
+
+/// The indented lines which follow a heading such as `FLAGS:` in help output
+fn section<'a>(help: &'a str, heading: &'a str) -> impl Iterator<Item = &'a str> {
+    help.lines()
+        .skip_while(move |l| l.strip_suffix(':') != Some(heading))
+        .skip(1)
+        .take_while(|l| l.starts_with(' '))
+}
+
+/// Protect text from being interpreted as roff requests or escapes
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    match text.starts_with('.') || text.starts_with('\'') {
+        true => format!("\\&{}", text),
+        false => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_roff() {
+        assert_eq!(escape("--paste"), "\\-\\-paste");
+        assert_eq!(escape(".hidden"), "\\&.hidden");
+        assert_eq!(escape("a\\b"), "a\\eb");
+    }
+}
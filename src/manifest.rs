@@ -0,0 +1,172 @@
+use serde_json::{Map, Number, Value};
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// A file listed in a JSON or YAML checksum manifest, before its digest has been decoded
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// Name or path of the file
+    pub name: String,
+    /// The algorithm, if the manifest names it separately from the digest
+    pub algorithm: Option<String>,
+    /// The digest as written in the manifest, such as `sha256:<hex>` or plain hex
+    pub digest: String,
+}
+
+/// Keys which might hold the list of files when the manifest has other information at the top level
+const LIST_KEYS: &[&str] = &["files", "artifacts", "checksums", "digests"];
+/// Keys which might hold the name of a file in one of its entries
+const NAME_KEYS: &[&str] = &["name", "file", "filename", "path"];
+/// Keys which might hold the digest of a file in one of its entries
+const DIGEST_KEYS: &[&str] = &["digest", "hash", "checksum"];
+/// Keys which might hold the name of the algorithm in one of its entries
+const ALGORITHM_KEYS: &[&str] = &["algorithm", "alg", "type"];
+/// Algorithms which might be used as keys for the digest itself, strongest first
+const ALGORITHM_NAMES: &[&str] = &["sha512", "sha384", "sha256", "sha1", "md5"];
+
+/// Parse a manifest which maps filenames to digests, or lists objects each with a name and digest.
+/// Returns the entries and the number of items which weren't understood, or None if the data
+/// isn't a manifest at all.
+pub fn parse_manifest(data: &[u8]) -> Option<(Vec<Entry>, usize)> {
+    // YAML is a superset of JSON but JSON is much more common, and strict
+    let value: Value = match serde_json::from_slice(data) {
+        Ok(value) => value,
+        Err(_) => {
+            let text = std::str::from_utf8(data).ok()?;
+            yaml_to_json(YamlLoader::load_from_str(text).ok()?.into_iter().next()?)?
+        }
+    };
+    let value = match &value {
+        Value::Object(map) => LIST_KEYS.iter().find_map(|k| map.get(*k)).unwrap_or(&value),
+        _ => &value,
+    };
+    let items: Vec<(Option<&str>, &Value)> = match value {
+        Value::Object(map) => map.iter().map(|(k, v)| (Some(k.as_str()), v)).collect(),
+        Value::Array(list) => list.iter().map(|v| (None, v)).collect(),
+        _ => return None,
+    };
+    let entries: Vec<Entry> = items
+        .iter()
+        .filter_map(|(name, value)| parse_entry(*name, value))
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+    let improper = items.len() - entries.len();
+    Some((entries, improper))
+}
+
+/// Interpret one item, which is either a digest keyed by its filename or an object describing a
+/// file. Names inside an object take priority over the key it is stored under.
+fn parse_entry(key: Option<&str>, value: &Value) -> Option<Entry> {
+    match value {
+        Value::String(digest) => Some(Entry {
+            name: key?.to_owned(),
+            algorithm: None,
+            digest: digest.clone(),
+        }),
+        Value::Object(fields) => {
+            let name = find_string(fields, NAME_KEYS).or(key)?;
+            let (algorithm, digest) = match find_string(fields, DIGEST_KEYS) {
+                Some(digest) => (find_string(fields, ALGORITHM_KEYS), digest),
+                None => ALGORITHM_NAMES
+                    .iter()
+                    .find_map(|a| Some((Some(*a), find_string(fields, &[a])?)))?,
+            };
+            Some(Entry {
+                name: name.to_owned(),
+                algorithm: algorithm.map(str::to_owned),
+                digest: digest.to_owned(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Convert a YAML document to the equivalent JSON, so both forms are read the same way. Keys which
+/// aren't strings are written as they would be in YAML, and aliases aren't followed.
+fn yaml_to_json(yaml: Yaml) -> Option<Value> {
+    Some(match yaml {
+        Yaml::Null | Yaml::Alias(_) => Value::Null,
+        Yaml::Boolean(b) => Value::Bool(b),
+        Yaml::Integer(i) => Value::Number(i.into()),
+        Yaml::Real(r) => match r.parse().ok().and_then(Number::from_f64) {
+            Some(n) => Value::Number(n),
+            None => Value::String(r),
+        },
+        Yaml::String(s) => Value::String(s),
+        Yaml::Array(list) => {
+            Value::Array(list.into_iter().map(yaml_to_json).collect::<Option<_>>()?)
+        }
+        Yaml::Hash(hash) => Value::Object(
+            hash.into_iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Yaml::String(s) | Yaml::Real(s) => s,
+                        Yaml::Integer(i) => i.to_string(),
+                        Yaml::Boolean(b) => b.to_string(),
+                        _ => return None,
+                    };
+                    Some((key, yaml_to_json(v)?))
+                })
+                .collect::<Option<_>>()?,
+        ),
+        Yaml::BadValue => return None,
+    })
+}
+
+/// The first of `keys` which has a string value, ignoring the case of the keys
+fn find_string<'a>(fields: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().find_map(|k| {
+        fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(k))
+            .and_then(|(_, v)| v.as_str())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let json = br#"{"a.iso": "sha256:abcd", "b.iso": "1234", "size": 5}"#;
+        let (entries, improper) = parse_manifest(json).unwrap();
+        assert_eq!(entries[0].name, "a.iso");
+        assert_eq!(entries[0].digest, "sha256:abcd");
+        assert_eq!(improper, 1);
+
+        let yaml = b"version: 2
+files:
+  - name: a.iso
+    algorithm: SHA-256
+    digest: abcd
+  - path: b.iso
+    SHA512: '1234'
+";
+        let (entries, improper) = parse_manifest(yaml).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    name: "a.iso".to_owned(),
+                    algorithm: Some("SHA-256".to_owned()),
+                    digest: "abcd".to_owned(),
+                },
+                Entry {
+                    name: "b.iso".to_owned(),
+                    algorithm: Some("sha512".to_owned()),
+                    digest: "1234".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(improper, 0);
+
+        let (entries, improper) = parse_manifest(b"a.iso: sha256:abcd\n1: [x]\n").unwrap();
+        assert_eq!(entries[0].name, "a.iso");
+        assert_eq!(entries[0].digest, "sha256:abcd");
+        assert_eq!(improper, 1);
+
+        assert_eq!(parse_manifest(b"abcd  a.iso\n"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+/// A file listed in a Media Hash List, before its hashes have been decoded
+#[derive(Debug, Default, PartialEq)]
+pub struct Entry {
+    /// Path of the file relative to the MHL file
+    pub path: String,
+    /// Size of the file in bytes, if listed
+    pub size: Option<u64>,
+    /// Each hash of the file along with the name of its element, such as `md5` or `xxh64`
+    pub hashes: Vec<(String, String)>,
+}
+
+/// Elements inside a `<hash>` which hold a hash of the file. Older MHL files write xxHash as
+/// `xxhash64be` or `xxhash64` while ASC-MHL uses `xxh64`.
+const HASH_ELEMENTS: &[&str] = &[
+    "md5",
+    "sha1",
+    "xxh64",
+    "xxhash64be",
+    "xxhash64",
+    "xxh3",
+    "xxh128",
+    "c4",
+];
+
+/// Parse a Media Hash List in either the original MHL format or ASC-MHL. Each `<hash>` element
+/// describes one file. Returns None if the data isn't an MHL file.
+pub fn parse_mhl(data: &[u8]) -> Option<Vec<Entry>> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut entries = vec![];
+    let mut entry: Option<Entry> = None;
+    // Elements which are currently open and the one whose attributes are being read
+    let mut open: Vec<&str> = vec![];
+    let mut starting = "";
+    for token in Tokenizer::from(text) {
+        match token.ok()? {
+            Token::ElementStart { local, .. } => {
+                starting = local.as_str();
+                if open.is_empty() && starting != "hashlist" {
+                    return None;
+                }
+                if starting == "hash" {
+                    entry = Some(Entry::default());
+                }
+            }
+            // ASC-MHL gives the size as an attribute of the path
+            Token::Attribute { local, value, .. } if starting == "path" => {
+                if let (Some(e), "size") = (&mut entry, local.as_str()) {
+                    e.size = value.as_str().parse().ok();
+                }
+            }
+            Token::ElementEnd {
+                end: ElementEnd::Open,
+                ..
+            } => open.push(starting),
+            Token::ElementEnd {
+                end: ElementEnd::Close(_, local),
+                ..
+            } => {
+                open.pop();
+                if local.as_str() == "hash" {
+                    entries.extend(entry.take());
+                }
+            }
+            Token::Text { text } | Token::Cdata { text, .. } => {
+                if let (Some(e), Some(element)) = (&mut entry, open.last()) {
+                    let value = unescape(text.as_str().trim());
+                    match *element {
+                        "file" | "path" => e.path = value,
+                        "size" => e.size = value.parse().ok(),
+                        name if HASH_ELEMENTS.contains(&name) => {
+                            e.hashes.push((name.to_owned(), value))
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    // Anything else is truncated or has no elements at all
+    (open.is_empty() && !starting.is_empty()).then_some(entries)
+}
+
+/// Replace the predefined XML entities and numeric character references in text
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                reference => match reference.strip_prefix("#x") {
+                    Some(hex) => char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+                    None => char::from_u32(reference.strip_prefix('#')?.parse().ok()?)?,
+                },
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mhl() {
+        let v1 = br#"<?xml version="1.0" encoding="UTF-8"?>
+<hashlist version="1.1">
+  <creatorinfo><name>Someone</name></creatorinfo>
+  <hash>
+    <file>A001/clip &amp; take.mov</file>
+    <size>1024</size>
+    <lastmodificationdate>2020-01-01T00:00:00Z</lastmodificationdate>
+    <xxhash64be>0123456789abcdef</xxhash64be>
+    <md5>5eb63bbbe01eeed093cb22bb8f5acdc3</md5>
+  </hash>
+</hashlist>
+"#;
+        assert_eq!(
+            parse_mhl(v1).unwrap(),
+            vec![Entry {
+                path: "A001/clip & take.mov".to_owned(),
+                size: Some(1024),
+                hashes: vec![
+                    ("xxhash64be".to_owned(), "0123456789abcdef".to_owned()),
+                    (
+                        "md5".to_owned(),
+                        "5eb63bbbe01eeed093cb22bb8f5acdc3".to_owned()
+                    ),
+                ],
+            }]
+        );
+
+        let v2 = br#"<?xml version="1.0" encoding="UTF-8"?>
+<hashlist version="2.0" xmlns="urn:ASC:MHL:v2.0">
+  <hashes>
+    <hash>
+      <path size="12" lastmodificationdate="2020-01-01T00:00:00Z">b.txt</path>
+      <xxh64 action="original" hashdate="2020-01-01T00:00:00Z">fedcba9876543210</xxh64>
+    </hash>
+  </hashes>
+</hashlist>
+"#;
+        let entries = parse_mhl(v2).unwrap();
+        assert_eq!(entries[0].path, "b.txt");
+        assert_eq!(entries[0].size, Some(12));
+        assert_eq!(
+            entries[0].hashes,
+            vec![("xxh64".to_owned(), "fedcba9876543210".to_owned())]
+        );
+
+        assert_eq!(parse_mhl(b"<html><body></body></html>"), None);
+        assert_eq!(parse_mhl(b"abcd  a.iso\n"), None);
+    }
+}
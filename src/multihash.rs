@@ -0,0 +1,90 @@
+use super::Algorithm;
+
+// A multihash prefixes a digest with varints giving the hash function and the digest length, so
+// that content-addressed systems such as IPFS can identify data without any other metadata.
+
+/// Multicodec code for raw binary content, the only kind of CID which addresses a file's bytes
+/// directly rather than a chunked representation of them
+const RAW_CODEC: u64 = 0x55;
+
+fn algorithm_for_code(code: u64) -> Option<Algorithm> {
+    match code {
+        0xd5 => Some(Algorithm::Md5),
+        0x11 => Some(Algorithm::Sha1),
+        0x12 => Some(Algorithm::Sha256),
+        0x20 => Some(Algorithm::Sha384),
+        0x13 => Some(Algorithm::Sha512),
+        _ => None,
+    }
+}
+
+/// Read an unsigned varint from the front of `bytes`, returning the value and the remainder
+fn read_varint(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0;
+    for (i, b) in bytes.iter().enumerate().take(9) {
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, &bytes[i + 1..]));
+        }
+    }
+    None
+}
+
+/// Split a binary multihash into its algorithm and digest
+fn decode(bytes: &[u8]) -> Option<(Algorithm, Vec<u8>)> {
+    let (code, rest) = read_varint(bytes)?;
+    let (len, digest) = read_varint(rest)?;
+    let alg = algorithm_for_code(code)?;
+    if digest.len() as u64 != len || Algorithm::from_len(digest.len()).ok()? != alg {
+        return None;
+    }
+    Some((alg, digest.to_vec()))
+}
+
+/// Parse a multihash written as hex or with a multibase prefix, or a CID of raw content
+pub fn parse(s: &str) -> Option<(Algorithm, Vec<u8>)> {
+    let s = s.trim();
+    if let Some(found) = hex::decode(s).ok().and_then(|bytes| decode(&bytes)) {
+        return Some(found);
+    }
+    // A CIDv0 is a bare base58 multihash, which always starts with Qm since it must be SHA-256
+    if s.starts_with("Qm") {
+        return decode(&multibase::Base::Base58Btc.decode(s).ok()?);
+    }
+    let (_, bytes) = multibase::decode(s).ok()?;
+    // A CIDv1 is the version, the content type and then the multihash
+    match read_varint(&bytes)? {
+        (1, rest) => match read_varint(rest)? {
+            (RAW_CODEC, multihash) => decode(multihash),
+            _ => None,
+        },
+        _ => decode(&bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let expected = Some((Algorithm::Sha256, hex::decode(sha256).unwrap()));
+        assert_eq!(parse(&format!("1220{}", sha256)), expected);
+        assert_eq!(
+            parse("QmRN6wdp1S2A5EtjW9A3M1vKSBuQQGcgvuhoMUoEz4iiT5"),
+            expected
+        );
+        assert_eq!(
+            parse("bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq"),
+            expected
+        );
+        // The same digest in a CID of chunked dag-pb data doesn't describe the file's bytes
+        assert_eq!(
+            parse("bafybeibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq"),
+            None
+        );
+        assert_eq!(parse(&format!("1320{}", sha256)), None);
+        assert_eq!(parse(sha256), None);
+    }
+}
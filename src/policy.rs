@@ -0,0 +1,106 @@
+use super::{Algorithm, MessageLevel};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How seriously to take a match made with an algorithm which can't be relied on against
+/// deliberate tampering
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WarnLevel {
+    /// Say nothing
+    Off = 0,
+    Note = 1,
+    Warning = 2,
+    /// Fail the verification even though the hash matched
+    Error = 3,
+}
+
+impl FromStr for WarnLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(WarnLevel::Off),
+            "note" => Ok(WarnLevel::Note),
+            "warning" => Ok(WarnLevel::Warning),
+            "error" => Ok(WarnLevel::Error),
+            _ => Err(format!(
+                "Unknown warning level '{}'. Use off, note, warning or error.",
+                s
+            )),
+        }
+    }
+}
+
+static MD5_LEVEL: AtomicU8 = AtomicU8::new(WarnLevel::Note as u8);
+static SHA1_LEVEL: AtomicU8 = AtomicU8::new(WarnLevel::Note as u8);
+static XXHASH_LEVEL: AtomicU8 = AtomicU8::new(WarnLevel::Note as u8);
+
+/// Where the level for a weak algorithm is kept, or None if the algorithm isn't considered weak
+fn level_for(alg: Algorithm) -> Option<&'static AtomicU8> {
+    match alg {
+        Algorithm::Md5 => Some(&MD5_LEVEL),
+        Algorithm::Sha1 => Some(&SHA1_LEVEL),
+        Algorithm::Xxh64 | Algorithm::Xxh128 => Some(&XXHASH_LEVEL),
+        _ => None,
+    }
+}
+
+/// Apply a policy such as `error`, which sets the level for every weak algorithm, or
+/// `md5=error,sha1=warning,xxhash=off` to set them individually
+pub fn set_policy(spec: &str) -> Result<(), String> {
+    for entry in spec.split(',').map(str::trim) {
+        let (levels, level): (&[&AtomicU8], _) = match entry.split_once('=') {
+            None => (&[&MD5_LEVEL, &SHA1_LEVEL, &XXHASH_LEVEL], entry),
+            Some(("md5", level)) => (&[&MD5_LEVEL], level),
+            Some(("sha1", level)) => (&[&SHA1_LEVEL], level),
+            Some(("xxhash", level)) => (&[&XXHASH_LEVEL], level),
+            Some((alg, _)) => {
+                return Err(format!(
+                    "Unknown algorithm '{}' in warning policy. Use md5, sha1 or xxhash.",
+                    alg
+                ))
+            }
+        };
+        let level = level.parse::<WarnLevel>()?;
+        for l in levels {
+            l.store(level as u8, Ordering::Relaxed);
+        }
+    }
+    Ok(())
+}
+
+/// The message to give when a hash made with `alg` matched, according to the policy. Keyed
+/// digests are exempt from the MD5 and SHA-1 warnings because HMAC does not depend on collision
+/// resistance.
+pub fn weak_match_message(alg: Algorithm, keyed: bool) -> Option<(MessageLevel, String)> {
+    if keyed && matches!(alg, Algorithm::Md5 | Algorithm::Sha1) {
+        return None;
+    }
+    let level = match level_for(alg)?.load(Ordering::Relaxed) {
+        0 => return None,
+        1 => MessageLevel::Note,
+        2 => MessageLevel::Warning,
+        _ => MessageLevel::Error,
+    };
+    let message = match alg {
+        Algorithm::Md5 => tr!("md5-weak"),
+        Algorithm::Sha1 => tr!("sha1-weak"),
+        _ => tr!("xxhash-weak"),
+    };
+    Some((level, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_levels() {
+        assert_eq!("error".parse::<WarnLevel>(), Ok(WarnLevel::Error));
+        assert!("loud".parse::<WarnLevel>().is_err());
+        assert!(set_policy("sha256=off").is_err());
+        assert!(set_policy("md5=loud").is_err());
+        assert!(weak_match_message(Algorithm::Sha256, false).is_none());
+        assert!(weak_match_message(Algorithm::Md5, true).is_none());
+    }
+}
@@ -0,0 +1,157 @@
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the progress line is redrawn
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reports on stderr how much of an input of known length has been hashed, along with the speed
+/// and the estimated time remaining. The progress line is erased once it is finished or dropped.
+pub struct Progress {
+    total: u64,
+    done: u64,
+    start: Instant,
+    last_update: Instant,
+    shown: bool,
+}
+
+impl Progress {
+    /// Start tracking progress, if stderr is a terminal where it can be shown
+    pub fn new(total: u64) -> Option<Self> {
+        let now = Instant::now();
+        can_show_progress().then_some(Self {
+            total,
+            done: 0,
+            start: now,
+            // Nothing is shown for inputs that are done in less time than this
+            last_update: now,
+            shown: false,
+        })
+    }
+
+    pub fn advance(&mut self, bytes: u64) {
+        self.done += bytes;
+        if self.last_update.elapsed() < UPDATE_INTERVAL {
+            return;
+        }
+        self.last_update = Instant::now();
+        self.shown = true;
+        let percent = match self.total {
+            0 => 100,
+            total => self.done.min(total) * 100 / total,
+        };
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = self.done as f64 / elapsed;
+        let mut line = format!(
+            "{:>3}% ({} of {}, {}/s",
+            percent,
+            format_size(self.done),
+            format_size(self.total),
+            format_size(rate as u64)
+        );
+        if rate > 0.0 && self.done < self.total {
+            let remaining = (self.total - self.done) as f64 / rate;
+            line += &format!(", {} remaining", format_duration(remaining as u64));
+        }
+        let _ = write!(io::stderr(), "\r\x1b[K{})", line);
+    }
+
+    /// Erase the progress line
+    pub fn finish(&mut self) {
+        if self.shown {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+            self.shown = false;
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Wraps a reader of known length and reports how much of it has been read on stderr, as long as
+/// stderr is a terminal
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Option<Progress>,
+}
+
+impl<R: Read> ProgressReader<R> {
+    pub fn new(inner: R, total: u64) -> Self {
+        Self {
+            inner,
+            progress: Progress::new(total),
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        if let Some(progress) = &mut self.progress {
+            match size {
+                0 => progress.finish(),
+                size => progress.advance(size as u64),
+            }
+        }
+        Ok(size)
+    }
+}
+
+/// Set when several inputs are hashed at once, since their progress lines would overwrite each other
+static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// Don't show progress for any further inputs
+pub fn hide_progress() {
+    HIDDEN.store(true, Ordering::Relaxed);
+}
+
+/// Whether progress can be shown without interfering with redirected output
+pub fn can_show_progress() -> bool {
+    !HIDDEN.load(Ordering::Relaxed) && io::stderr().is_terminal()
+}
+
+/// Format a number of bytes for humans, such as "1.5 GiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} bytes", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+/// Format a number of seconds for humans, such as "3m 05s"
+pub fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 bytes");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(4 * 1024 * 1024 * 1024), "4.0 GiB");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(format_duration(185), "3m 05s");
+        assert_eq!(format_duration(3720), "1h 02m");
+    }
+}
@@ -0,0 +1,49 @@
+use std::io::Write;
+use std::process::Command;
+
+/// Whether the data looks like an image file which might contain a QR code
+pub fn is_image(data: &[u8]) -> bool {
+    data.starts_with(b"\x89PNG\r\n\x1a\n")
+        || data.starts_with(b"\xff\xd8\xff")
+        || data.starts_with(b"GIF8")
+        || (data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP"))
+}
+
+/// Decode the text of a QR code in an image using `zbarimg` from ZBar
+pub fn decode_qr(image: &[u8]) -> Result<String, String> {
+    // zbarimg needs a file, and the image may have come from standard input. The temporary file
+    // has a random name that nobody else can open and is removed when dropped.
+    let temp = tempfile::Builder::new()
+        .prefix("hashgood-")
+        .suffix(".img")
+        .tempfile()
+        .and_then(|mut temp| temp.write_all(image).map(|_| temp))
+        .map_err(|e| format!("Unable to write temporary image: {}", e))?;
+    let output = Command::new("zbarimg")
+        .args(["--quiet", "--raw", "-Sdisable", "-Sqrcode.enable"])
+        .arg(temp.path())
+        .output();
+    drop(temp);
+    let output = output.map_err(|e| format!("Unable to run zbarimg to read the QR code: {}", e))?;
+    // zbarimg exits with status 4 when the image has no QR code in it
+    match output.status.code() {
+        Some(0) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        Some(4) => Err("No QR code was found in the image".to_owned()),
+        _ => Err(format!(
+            "Unable to read the QR code: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_image() {
+        assert!(is_image(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(is_image(b"RIFF\x24\0\0\0WEBPVP8 "));
+        assert!(!is_image(b"87428fc522803d31065e7bce3cf03fe4  a.txt\n"));
+    }
+}
@@ -0,0 +1,68 @@
+/// Try each of the usual places a checksum file might be published for the download at `url`,
+/// returning the URL and contents of the first one that exists
+#[cfg(feature = "url")]
+pub fn download_sidecar(url: &str) -> Result<(String, Vec<u8>), String> {
+    let candidates = sidecar_urls(url);
+    for candidate in &candidates {
+        // Anything that isn't there is just the wrong guess
+        if let Some(data) = super::input::download(ureq::get(candidate), &[404, 403, 410])? {
+            return Ok((candidate.clone(), data));
+        }
+    }
+    Err(format!(
+        "No checksums file was found next to '{}'. Tried: {}",
+        url,
+        candidates.join(", ")
+    ))
+}
+
+#[cfg(not(feature = "url"))]
+pub fn download_sidecar(_url: &str) -> Result<(String, Vec<u8>), String> {
+    Err(
+        "Fetching checksums is not supported by this build of hashgood (requires the url feature)"
+            .to_owned(),
+    )
+}
+
+/// The URLs to try for a checksum file, in order of preference. A query string or fragment on the
+/// download URL is dropped since it rarely applies to the checksums.
+#[cfg_attr(not(feature = "url"), allow(dead_code))]
+fn sidecar_urls(url: &str) -> Vec<String> {
+    // Extensions are appended to the download's own URL, lists are looked for in its directory
+    const EXTENSIONS: &[&str] = &[".sha256", ".sha512", ".sha256sum", ".sha512sum"];
+    const LISTS: &[&str] = &[
+        "SHA256SUMS",
+        "SHA512SUMS",
+        "sha256sums.txt",
+        "checksums.txt",
+    ];
+    let base = url.split(['?', '#']).next().unwrap_or(url);
+    let mut urls: Vec<String> = EXTENSIONS
+        .iter()
+        .map(|ext| format!("{}{}", base, ext))
+        .collect();
+    // Only look in the directory if there is a path after the host
+    if let Some((dir, _)) = base
+        .split_once("://")
+        .and_then(|(_, rest)| rest.contains('/').then(|| base.rsplit_once('/'))?)
+    {
+        urls.extend(LISTS.iter().map(|list| format!("{}/{}", dir, list)));
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_urls() {
+        let urls = sidecar_urls("https://example.com/releases/tool.iso?download=1");
+        assert_eq!(urls[0], "https://example.com/releases/tool.iso.sha256");
+        assert!(urls.contains(&"https://example.com/releases/SHA256SUMS".to_owned()));
+        assert_eq!(urls.len(), 8);
+
+        let urls = sidecar_urls("https://example.com");
+        assert_eq!(urls.len(), 4);
+    }
+}
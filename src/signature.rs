@@ -0,0 +1,290 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// First line of a check file with an OpenPGP signature wrapped around its contents
+pub const CLEARSIGN_HEADER: &[u8] = b"-----BEGIN PGP SIGNED MESSAGE-----";
+
+/// Verify an OpenPGP signature over the contents of a check file using `gpgv`, trusting only the
+/// keys in the given file. Without a detached signature the check file must be clearsigned.
+/// Returns a description of the signer.
+pub fn verify_pgp(data: &[u8], signature: Option<&Path>, key: &Path) -> Result<String, String> {
+    let key_data = std::fs::read(key)
+        .map_err(|e| format!("Unable to read key file '{}': {}", key.to_string_lossy(), e))?;
+    // gpgv only understands binary keyrings, so armored keys are converted in a temporary file.
+    // It is created with a random name that nobody else can open and removed when dropped.
+    match dearmor(&key_data) {
+        Some(binary) => {
+            let temp = tempfile::Builder::new()
+                .prefix("hashgood-")
+                .suffix(".gpg")
+                .tempfile()
+                .and_then(|mut temp| temp.write_all(&binary).map(|_| temp))
+                .map_err(|e| format!("Unable to write temporary keyring: {}", e))?;
+            run_gpgv(data, signature, temp.path())
+        }
+        // A keyring without a directory would be looked for inside the GnuPG home directory
+        None => run_gpgv(
+            data,
+            signature,
+            &std::path::absolute(key).map_err(|e| e.to_string())?,
+        ),
+    }
+}
+
+fn run_gpgv(data: &[u8], signature: Option<&Path>, keyring: &Path) -> Result<String, String> {
+    let mut child = Command::new("gpgv")
+        .arg("--status-fd=1")
+        .arg("--keyring")
+        .arg(keyring)
+        .args(signature)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Unable to run gpgv to verify the signature: {}", e))?;
+    // The check file is small enough that gpgv will never block writing output before it has read
+    // all of its input
+    let written = child.stdin.take().unwrap().write_all(data);
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Unable to run gpgv to verify the signature: {}", e))?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    let signer = status.lines().find_map(|line| {
+        let rest = line.strip_prefix("[GNUPG:] GOODSIG ")?;
+        rest.split_once(' ').map(|(_, user)| user.to_owned())
+    });
+    let fingerprint = status.lines().find_map(|line| {
+        let rest = line.strip_prefix("[GNUPG:] VALIDSIG ")?;
+        rest.split(' ').next().map(str::to_owned)
+    });
+    match (
+        output.status.success() && written.is_ok(),
+        signer,
+        fingerprint,
+    ) {
+        (true, Some(signer), Some(fingerprint)) => Ok(format!("{} ({})", signer, fingerprint)),
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr
+                .lines()
+                .rfind(|l| l.starts_with("gpgv: "))
+                .unwrap_or("unknown problem");
+            let name = match signature {
+                Some(path) => format!("'{}'", path.to_string_lossy()),
+                None => "in the check file".to_owned(),
+            };
+            Err(format!(
+                "The signature {} is not valid: {}",
+                name,
+                reason.trim_start_matches("gpgv: ")
+            ))
+        }
+    }
+}
+
+/// Verify a minisign signature over the contents of a check file. The key can be the path to a
+/// public key file or the key itself in base64. Returns the signature's trusted comment.
+pub fn verify_minisign(data: &[u8], signature: &Path, key: &str) -> Result<String, String> {
+    let public_key = match Path::new(key).is_file() {
+        true => minisign_verify::PublicKey::from_file(key),
+        false => minisign_verify::PublicKey::from_base64(key),
+    }
+    .map_err(|e| format!("Unable to read minisign public key '{}': {}", key, e))?;
+    let sig = minisign_verify::Signature::from_file(signature).map_err(|e| {
+        format!(
+            "Unable to read minisign signature '{}': {}",
+            signature.to_string_lossy(),
+            e
+        )
+    })?;
+    public_key.verify(data, &sig, true).map_err(|e| {
+        format!(
+            "The signature '{}' is not valid: {}",
+            signature.to_string_lossy(),
+            e
+        )
+    })?;
+    Ok(sig.trusted_comment().to_owned())
+}
+
+/// Verify an OpenBSD signify signature over a check file. If no separate signature is given then
+/// the check file must have its signature embedded at the start, as made by `signify -S -e`.
+/// Returns the comment on the public key, which usually identifies it, and the signed contents.
+pub fn verify_signify<'a>(
+    data: &'a [u8],
+    signature: Option<&Path>,
+    key: &Path,
+) -> Result<(String, &'a [u8]), String> {
+    let key_data = std::fs::read(key).map_err(|e| {
+        format!(
+            "Unable to read signify public key '{}': {}",
+            key.to_string_lossy(),
+            e
+        )
+    })?;
+    let (key_comment, public_key, _) = parse_signify_file(&key_data, SIGNIFY_KEY_LEN)
+        .ok_or_else(|| format!("'{}' is not a signify public key", key.to_string_lossy()))?;
+    let (sig_name, sig_data) = match signature {
+        Some(path) => (
+            path.to_string_lossy().into_owned(),
+            std::fs::read(path).map_err(|e| {
+                format!(
+                    "Unable to read signature '{}': {}",
+                    path.to_string_lossy(),
+                    e
+                )
+            })?,
+        ),
+        None => ("embedded in the check file".to_owned(), data.to_vec()),
+    };
+    let (_, sig, rest) = parse_signify_file(&sig_data, SIGNIFY_SIG_LEN)
+        .ok_or_else(|| format!("No signify signature was found {}", sig_name))?;
+    let message = match signature {
+        Some(_) => data,
+        None => &data[data.len() - rest.len()..],
+    };
+    if sig[2..10] != public_key[2..10] {
+        return Err(format!(
+            "The signature {} was made with a different key",
+            sig_name
+        ));
+    }
+    let public_key: [u8; 32] = public_key[10..].try_into().unwrap();
+    let sig: [u8; 64] = sig[10..].try_into().unwrap();
+    ed25519_compact::PublicKey::new(public_key)
+        .verify(message, &ed25519_compact::Signature::new(sig))
+        .map_err(|_| format!("The signature {} is not valid", sig_name))?;
+    Ok((key_comment, message))
+}
+
+/// Length of a signify public key: the algorithm, key number and Ed25519 key
+const SIGNIFY_KEY_LEN: usize = 2 + 8 + 32;
+/// Length of a signify signature: the algorithm, key number and Ed25519 signature
+const SIGNIFY_SIG_LEN: usize = 2 + 8 + 64;
+
+/// Parse a signify key or signature, which is an untrusted comment line followed by the base64
+/// data. Returns the comment, the decoded data and whatever follows it in the file.
+fn parse_signify_file(data: &[u8], len: usize) -> Option<(String, Vec<u8>, &[u8])> {
+    let rest = data.strip_prefix(b"untrusted comment: ")?;
+    let comment_end = rest.iter().position(|b| *b == b'\n')?;
+    let comment = std::str::from_utf8(&rest[..comment_end]).ok()?;
+    let rest = &rest[comment_end + 1..];
+    let line_end = rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len());
+    let line = std::str::from_utf8(&rest[..line_end]).ok()?;
+    let decoded = STANDARD.decode(line.trim()).ok()?;
+    if decoded.len() != len || &decoded[..2] != b"Ed" {
+        return None;
+    }
+    let rest = &rest[(line_end + 1).min(rest.len())..];
+    Some((comment.trim().to_owned(), decoded, rest))
+}
+
+/// Extract the signed text from a clearsigned check file, without checking the signature. Returns
+/// None if the data isn't clearsigned.
+pub fn strip_clearsign(data: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(data.strip_prefix(CLEARSIGN_HEADER)?).ok()?;
+    // Armor headers such as "Hash: SHA256" end at a blank line
+    let mut lines = text
+        .lines()
+        .skip(1)
+        .skip_while(|l| !l.trim_end().is_empty())
+        .skip(1);
+    let mut signed = String::new();
+    for line in lines.by_ref() {
+        if line.trim_end() == "-----BEGIN PGP SIGNATURE-----" {
+            return Some(signed.into_bytes());
+        }
+        // Lines starting with a dash are escaped so they can't be mistaken for the armor
+        signed.push_str(line.strip_prefix("- ").unwrap_or(line));
+        signed.push('\n');
+    }
+    None
+}
+
+/// Convert an ASCII-armored OpenPGP key to binary. Returns None if the data isn't armored.
+fn dearmor(data: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| !l.starts_with("-----BEGIN PGP "))
+        .skip(1);
+    // Header lines such as "Comment: ..." end at a blank line
+    let mut body: Vec<&str> = vec![];
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        if !line.contains(": ") {
+            body.push(line);
+            break;
+        }
+    }
+    body.extend(
+        lines
+            .take_while(|l| !l.starts_with("-----END PGP "))
+            .filter(|l| !l.starts_with('=')),
+    );
+    if body.is_empty() {
+        return None;
+    }
+    STANDARD.decode(body.concat()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signify_embedded() {
+        let key = "untrusted comment: test public key\n\
+            RWQRIjNEVWZ3iAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4\n";
+        let signed = "untrusted comment: verify with test.pub\n\
+            RWQRIjNEVWZ3iB8NYsxIIhlw+P0x88N5eAVuZp6M8FKKKhbvXYPIeb8dgU/hVVkUa2tBUxqhZ6ogRVPPeDyTVvbJpS+Gcm3C0ww=\n\
+            SHA256 (a.txt) = 87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf25c7\n";
+        let path = std::env::temp_dir().join(format!("hashgood-test-{}.pub", std::process::id()));
+        std::fs::write(&path, key).unwrap();
+        let (comment, message) = verify_signify(signed.as_bytes(), None, &path).unwrap();
+        assert_eq!(comment, "test public key");
+        assert!(message.starts_with(b"SHA256 (a.txt)"));
+
+        let tampered = signed.replace("a.txt", "b.txt");
+        assert!(verify_signify(tampered.as_bytes(), None, &path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_strip_clearsign() {
+        let clearsigned = "-----BEGIN PGP SIGNED MESSAGE-----\n\
+            Hash: SHA256\n\
+            \n\
+            87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf25c7  a.txt\n\
+            - --dashed.txt\n\
+            -----BEGIN PGP SIGNATURE-----\n\
+            \n\
+            iQEzBAEBCAAdFiEE\n\
+            -----END PGP SIGNATURE-----\n";
+        assert_eq!(
+            strip_clearsign(clearsigned.as_bytes()).unwrap(),
+            b"87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf25c7  a.txt\n--dashed.txt\n"
+        );
+        assert_eq!(strip_clearsign(b"87428fc5  a.txt\n"), None);
+    }
+
+    #[test]
+    fn test_dearmor() {
+        let armored = "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\
+            Comment: test\n\
+            \n\
+            aGVsbG8g\n\
+            d29ybGQ=\n\
+            =abcd\n\
+            -----END PGP PUBLIC KEY BLOCK-----\n";
+        assert_eq!(dearmor(armored.as_bytes()), Some(b"hello world".to_vec()));
+        assert_eq!(dearmor(b"\x99\x01\x0d"), None);
+    }
+}
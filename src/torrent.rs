@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+/// A value in the bencode format used by .torrent files
+#[derive(Debug, PartialEq)]
+enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    Dict(BTreeMap<&'a [u8], Value<'a>>),
+}
+
+/// The parts of a single-file .torrent that are needed to verify its payload
+#[derive(Debug, PartialEq)]
+pub struct Torrent {
+    /// Suggested filename for the payload
+    pub name: String,
+    /// Total size of the payload in bytes
+    pub length: u64,
+    /// Size of each piece in bytes. The last piece may be shorter.
+    pub piece_length: u64,
+    /// SHA-1 hash of each piece in order
+    pub pieces: Vec<Vec<u8>>,
+}
+
+/// How deeply lists and dictionaries may be nested. Real torrents need only a few levels, and a
+/// limit stops a malicious file from overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Parse the contents of a .torrent file. Returns None if the data doesn't look like a torrent at
+/// all, or an error if it is a torrent that can't be used for verification.
+pub fn parse_torrent(data: &[u8]) -> Option<Result<Torrent, String>> {
+    let (value, rest) = parse_value(data, 0)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let info = match &value {
+        Value::Dict(d) => match d.get(&b"info"[..]) {
+            Some(Value::Dict(info)) => info,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(torrent_from_info(info))
+}
+
+fn torrent_from_info(info: &BTreeMap<&[u8], Value>) -> Result<Torrent, String> {
+    if info.contains_key(&b"files"[..]) {
+        return Err("Multi-file torrents are not supported".to_owned());
+    }
+    let name = match info.get(&b"name"[..]) {
+        Some(Value::Bytes(b)) => String::from_utf8_lossy(b).to_string(),
+        _ => return Err("Torrent has no name".to_owned()),
+    };
+    let length = match info.get(&b"length"[..]) {
+        Some(Value::Int(i)) if *i >= 0 => *i as u64,
+        _ => return Err("Torrent has no valid length".to_owned()),
+    };
+    let piece_length = match info.get(&b"piece length"[..]) {
+        Some(Value::Int(i)) if *i > 0 => *i as u64,
+        _ => return Err("Torrent has no valid piece length".to_owned()),
+    };
+    let pieces = match info.get(&b"pieces"[..]) {
+        Some(Value::Bytes(b)) if b.len() % 20 == 0 => b.chunks(20).map(|p| p.to_vec()).collect(),
+        _ => return Err("Torrent has no valid piece hashes".to_owned()),
+    };
+    Ok(Torrent {
+        name,
+        length,
+        piece_length,
+        pieces,
+    })
+}
+
+/// Parse one bencoded value from the start of the data, returning it and the remaining data.
+/// `depth` is the number of lists and dictionaries the value is inside, which must be less than `MAX_DEPTH`.
+fn parse_value(data: &[u8], depth: usize) -> Option<(Value<'_>, &[u8])> {
+    if depth >= MAX_DEPTH {
+        return None;
+    }
+    match data.first()? {
+        b'i' => {
+            let end = data.iter().position(|b| *b == b'e')?;
+            let i = std::str::from_utf8(&data[1..end]).ok()?.parse().ok()?;
+            Some((Value::Int(i), &data[end + 1..]))
+        }
+        b'l' => {
+            let mut list = vec![];
+            let mut rest = &data[1..];
+            while rest.first()? != &b'e' {
+                let (value, r) = parse_value(rest, depth + 1)?;
+                list.push(value);
+                rest = r;
+            }
+            Some((Value::List(list), &rest[1..]))
+        }
+        b'd' => {
+            let mut dict = BTreeMap::new();
+            let mut rest = &data[1..];
+            while rest.first()? != &b'e' {
+                let (key, r) = parse_bytes(rest)?;
+                let (value, r) = parse_value(r, depth + 1)?;
+                dict.insert(key, value);
+                rest = r;
+            }
+            Some((Value::Dict(dict), &rest[1..]))
+        }
+        _ => {
+            let (bytes, rest) = parse_bytes(data)?;
+            Some((Value::Bytes(bytes), rest))
+        }
+    }
+}
+
+/// Parse a length-prefixed byte string such as `4:spam`
+fn parse_bytes(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let colon = data.iter().position(|b| *b == b':')?;
+    let len: usize = std::str::from_utf8(&data[..colon]).ok()?.parse().ok()?;
+    let rest = &data[colon + 1..];
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_torrent() {
+        let mut data = b"d8:announce14:http://tracker4:infod6:lengthi40000e4:name8:file.iso12:piece lengthi32768e6:pieces40:".to_vec();
+        data.extend_from_slice(&[0xaa; 20]);
+        data.extend_from_slice(&[0xbb; 20]);
+        data.extend_from_slice(b"ee");
+
+        assert_eq!(
+            parse_torrent(&data),
+            Some(Ok(Torrent {
+                name: "file.iso".to_owned(),
+                length: 40000,
+                piece_length: 32768,
+                pieces: vec![vec![0xaa; 20], vec![0xbb; 20]],
+            }))
+        );
+    }
+
+    #[test]
+    fn test_not_a_torrent() {
+        assert_eq!(parse_torrent(b"d3:foo3:bare"), None);
+        assert_eq!(
+            parse_torrent(b"4b91f7a387a6edd4a7c0afb2897f1ca968c9695b  cp"),
+            None
+        );
+        assert_eq!(
+            parse_torrent(b"d4:infod5:filesleee").map(|r| r.is_err()),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let nested = |depth| [vec![b'l'; depth], vec![b'e'; depth]].concat();
+        assert!(parse_value(&nested(MAX_DEPTH), 0).is_some());
+        assert!(parse_value(&nested(MAX_DEPTH + 1), 0).is_none());
+        assert_eq!(parse_torrent(&nested(1_000_000)), None);
+    }
+}
@@ -0,0 +1,268 @@
+use super::calculate::{self, Digests, InputData, ProgressFn};
+use super::error::HashgoodError;
+use super::verify::{self, CheckFileOptions, NamePolicy};
+use super::{Algorithm, CandidateHash, CandidateHashes, Hash, MatchLevel, MessageLevel};
+use super::{Verification, VerificationSource};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Where the hashes to verify against come from
+enum Candidates {
+    Hash(String),
+    File(PathBuf),
+}
+
+/// Verify an input against a hash or check file, or just calculate its digests, in one call.
+///
+/// ```no_run
+/// use hashgood::{MatchLevel, Verifier};
+///
+/// let result = Verifier::new("debian.iso")
+///     .candidate_file("SHA256SUMS")
+///     .run()?;
+/// if result.match_level != Some(MatchLevel::Ok) {
+///     eprintln!("debian.iso is corrupt");
+/// }
+/// # Ok::<(), hashgood::HashgoodError>(())
+/// ```
+pub struct Verifier {
+    input: PathBuf,
+    algorithms: Vec<Algorithm>,
+    candidates: Option<Candidates>,
+    check_file: CheckFileOptions,
+    names: NamePolicy,
+    progress: Option<Box<ProgressFn<'static>>>,
+}
+
+/// The outcome of running a `Verifier`
+#[derive(Debug)]
+pub struct VerifierResult {
+    /// Each digest which was calculated
+    pub digests: Vec<(Algorithm, Vec<u8>)>,
+    /// How well the input matched, or None if there was nothing to verify against
+    pub match_level: Option<MatchLevel>,
+    /// The candidate which matched, or the only candidate if there was one and it didn't
+    pub comparison_hash: Option<CandidateHash>,
+    /// Warnings and notes about the check file and the result
+    pub messages: Vec<(MessageLevel, String)>,
+    /// How much of the input was read
+    pub bytes: u64,
+}
+
+impl Verifier {
+    /// Start verifying the file at `input`. It can also be `-` for standard input, or an HTTP(S)
+    /// URL if the `url` feature is enabled.
+    pub fn new(input: impl Into<PathBuf>) -> Self {
+        Verifier {
+            input: input.into(),
+            algorithms: vec![],
+            candidates: None,
+            check_file: CheckFileOptions::default(),
+            names: NamePolicy::default(),
+            progress: None,
+        }
+    }
+
+    /// Calculate this digest of the input. It can be given more than once for several digests.
+    /// With a hash to verify against, its algorithm is used and this must match it. Otherwise
+    /// MD5, SHA-1 and SHA-256 are calculated unless others are chosen.
+    pub fn algorithm(mut self, alg: Algorithm) -> Self {
+        self.algorithms.push(alg);
+        self
+    }
+
+    /// Verify the input against a single hash, in any form accepted by `parse_hash`
+    pub fn hash(mut self, hash: &str) -> Self {
+        self.candidates = Some(Candidates::Hash(hash.to_owned()));
+        self
+    }
+
+    /// Verify the input against a check file, in any format accepted by `parse_check_data`
+    pub fn candidate_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.candidates = Some(Candidates::File(path.into()));
+        self
+    }
+
+    /// Fail if any line of the check file can't be understood, instead of warning about it
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.check_file.strict = strict;
+        self
+    }
+
+    /// Read the hash from a QR code if the check file is an image, which requires `zbarimg` from
+    /// ZBar to be installed
+    pub fn qr_codes(mut self, qr_codes: bool) -> Self {
+        self.check_file.qr_codes = qr_codes;
+        self
+    }
+
+    /// What to make of a matching hash listed under a different filename in the check file.
+    /// Normally it is a Maybe result.
+    pub fn name_policy(mut self, names: NamePolicy) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Call `progress` with the number of bytes read so far and the size of the input, if known,
+    /// each time another chunk has been hashed
+    pub fn progress(mut self, progress: impl FnMut(u64, Option<u64>) + 'static) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Hash the input and verify it against the candidates, if there are any
+    pub fn run(mut self) -> Result<VerifierResult, HashgoodError> {
+        let mut messages = vec![];
+        let candidates = match &self.candidates {
+            Some(Candidates::Hash(hash)) => Some(verify::parse_hash(hash)?),
+            Some(Candidates::File(path)) => {
+                Some(read_candidate_file(path, self.check_file, &mut messages)?)
+            }
+            None => None,
+        };
+        // Match against filenames in the check file the same way as the command line does
+        let name = match self.input.file_name() {
+            Some(filename) => filename.to_string_lossy(),
+            None => self.input.to_string_lossy(),
+        };
+        let data = match calculate::is_fast_input(&self.input) {
+            true => InputData::FastStream(calculate::get_input_reader(&self.input)?),
+            false => InputData::Stream(calculate::get_input_reader(&self.input)?),
+        };
+        let total = std::fs::metadata(&self.input)
+            .ok()
+            .filter(|m| m.is_file())
+            .map(|m| m.len());
+        let mut no_progress = |_, _| ();
+        let progress = self.progress.as_deref_mut().unwrap_or(&mut no_progress);
+        let Some(candidates) = candidates else {
+            let algorithms = match self.algorithms.is_empty() {
+                true => vec![Algorithm::Md5, Algorithm::Sha1, Algorithm::Sha256],
+                false => self.algorithms,
+            };
+            let digests =
+                calculate::create_digests_with_progress(&algorithms, data, total, progress)?;
+            return Ok(VerifierResult {
+                digests: digests.hashes,
+                match_level: None,
+                comparison_hash: None,
+                messages,
+                bytes: digests.bytes,
+            });
+        };
+        if let Some(alg) = self.algorithms.iter().find(|a| **a != candidates.alg) {
+            return Err(HashgoodError::Usage(format!(
+                "Unable to verify a {:?} hash with {:?}",
+                candidates.alg, alg
+            )));
+        }
+        let digests = match candidates.source {
+            // A torrent lists the hash of each piece rather than the whole input
+            VerificationSource::TorrentFile { piece_length, .. } => {
+                calculate::create_piece_digests(piece_length, data)?
+            }
+            _ => calculate::create_digests_with_progress(&[candidates.alg], data, total, progress)?,
+        };
+        let Digests { hashes, bytes, .. } = digests;
+        let (alg, digest) = hashes[0].clone();
+        let hash = Hash::new(alg, digest, &name);
+        let verification = match candidates.source {
+            VerificationSource::TorrentFile { .. } => {
+                verify::verify_pieces(&hash, &candidates, self.names)
+            }
+            _ => verify::verify_hash(&hash, &candidates, self.names),
+        };
+        let Verification {
+            match_level,
+            comparison_hash,
+            messages: verification_messages,
+        } = verification;
+        messages.extend(verification_messages);
+        Ok(VerifierResult {
+            digests: hashes,
+            match_level: Some(match_level),
+            comparison_hash: comparison_hash.cloned(),
+            messages,
+            bytes,
+        })
+    }
+}
+
+/// Read the hashes in a check file, which may be `-` for standard input
+fn read_candidate_file(
+    path: &Path,
+    options: CheckFileOptions,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<CandidateHashes, HashgoodError> {
+    let mut data = vec![];
+    let read = match path.to_str() {
+        Some("-") => std::io::stdin().read_to_end(&mut data).map(|_| ()),
+        _ => std::fs::read(path).map(|contents| data = contents),
+    };
+    read.map_err(|e| {
+        let context = format!("Unable to read check file '{}'", path.to_string_lossy());
+        HashgoodError::io(context, e)
+    })?;
+    verify::parse_check_data(data, path, options, messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_file() {
+        let path = std::env::temp_dir().join(format!("hashgood-verifier-{}", std::process::id()));
+        std::fs::write(&path, b"hello world").unwrap();
+
+        let result = Verifier::new(&path)
+            .hash("5eb63bbbe01eeed093cb22bb8f5acdc3")
+            .run()
+            .unwrap();
+        assert!(result.match_level == Some(MatchLevel::Ok));
+        assert_eq!(result.bytes, 11);
+        assert_eq!(result.digests[0].0, Algorithm::Md5);
+
+        let result = Verifier::new(&path)
+            .hash("5eb63bbbe01eeed093cb22bb8f5acdc4")
+            .run()
+            .unwrap();
+        assert!(result.match_level == Some(MatchLevel::Fail));
+
+        let result = Verifier::new(&path)
+            .algorithm(Algorithm::Sha512)
+            .run()
+            .unwrap();
+        assert!(result.match_level.is_none());
+        assert_eq!(result.digests.len(), 1);
+
+        let mismatched = Verifier::new(&path)
+            .algorithm(Algorithm::Sha1)
+            .hash("5eb63bbbe01eeed093cb22bb8f5acdc3")
+            .run();
+        assert!(matches!(mismatched, Err(HashgoodError::Usage(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn verify_with_check_file() {
+        let dir =
+            std::env::temp_dir().join(format!("hashgood-verifier-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hello.txt");
+        std::fs::write(&path, b"hello world").unwrap();
+        let sums = dir.join("MD5SUMS");
+        std::fs::write(
+            &sums,
+            "d41d8cd98f00b204e9800998ecf8427e  other.txt\n\
+             5eb63bbbe01eeed093cb22bb8f5acdc3  hello.txt\n",
+        )
+        .unwrap();
+
+        let result = Verifier::new(&path).candidate_file(&sums).run().unwrap();
+        assert!(result.match_level == Some(MatchLevel::Ok));
+        let matched = result.comparison_hash.unwrap();
+        assert_eq!(matched.filename(), Some("hello.txt"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,37 +1,293 @@
+#[cfg(all(feature = "paste", not(target_family = "wasm")))]
+use super::clipboard;
+use super::display;
+use super::error::HashgoodError;
+use super::fuzzy;
+use super::github;
+use super::input::{as_url, Input};
+use super::magnet;
+use super::manifest;
+use super::mhl;
+use super::multihash;
+use super::policy;
+use super::qr;
+use super::sidecar;
+use super::signature;
+use super::torrent;
+use super::xattr;
 use super::{
     Algorithm, CandidateHash, CandidateHashes, Hash, MatchLevel, MessageLevel, Opt, Verification,
     VerificationSource,
 };
-#[cfg(feature = "paste")]
-use copypasta::{ClipboardContext, ClipboardProvider};
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+
+/// A place the hashes to verify against can come from, such as the command line, the clipboard
+/// or a check file. Sources provided by other crates can be added with `register_candidate_source`.
+pub trait CandidateSource: Send + Sync {
+    /// A short description of the source for verbose output
+    fn name(&self) -> &str;
+
+    /// Get the candidate hashes if the arguments in `context` ask for this source, otherwise
+    /// None so that the next source is tried. Any messages about where the hashes came from are
+    /// added to `messages`.
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>>;
+}
+
+/// The arguments a `CandidateSource` is asked to find hashes for
+#[derive(Clone, Copy)]
+pub struct SourceContext<'a> {
+    /// The input to be verified, if one was given
+    pub input: Option<&'a Path>,
+    /// The hash given as a command line argument, if any
+    pub hash: Option<&'a str>,
+    /// The check file, or other source of hashes given with `-c`, if any
+    pub hash_file: Option<&'a Path>,
+    /// Everything else on the command line, for the built-in sources
+    opt: &'a Opt,
+}
+
+impl<'a> SourceContext<'a> {
+    fn new(opt: &'a Opt) -> Self {
+        SourceContext {
+            input: opt.input.as_deref(),
+            hash: opt.hash.as_deref(),
+            hash_file: opt.hash_file.as_deref(),
+            opt,
+        }
+    }
+}
+
+/// The built-in sources, in the order they are tried
+static SOURCES: &[&dyn CandidateSource] = &[
+    &ParameterSource,
+    &ClipboardSource,
+    &MagnetSource,
+    &CheckFileSource,
+    &GitHubSource,
+    &SidecarSource,
+    &InteractiveSource,
+];
+
+/// Sources added by other crates, which are tried before the built-in ones
+static REGISTERED: Mutex<Vec<Box<dyn CandidateSource>>> = Mutex::new(Vec::new());
+
+/// Add a source of candidate hashes. It is asked before the built-in sources, so it can claim
+/// a hash or check file argument in a form of its own, such as a `vault:` prefix.
+pub fn register_candidate_source(source: Box<dyn CandidateSource>) {
+    REGISTERED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(source);
+}
 
 /// Calculate a list of candidate hashes based on the options specified.
 /// If no hash options have been specified returns None.
 /// It is assumed to be verified previously that at most one mode has been specified.
-pub fn get_candidate_hashes(opt: &Opt) -> Result<Option<CandidateHashes>, String> {
-    if let Some(hash_string) = &opt.hash {
-        return Ok(Some(get_by_parameter(hash_string)?));
-    } else if opt.get_paste() {
-        return Ok(Some(get_from_clipboard()?));
-    } else if let Some(hash_file) = &opt.hash_file {
-        return Ok(Some(get_from_file(hash_file)?));
+/// Any messages about where the hashes came from are added to `messages`.
+pub fn get_candidate_hashes(
+    opt: &Opt,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Option<CandidateHashes>, HashgoodError> {
+    let registered = REGISTERED.lock().unwrap_or_else(|e| e.into_inner());
+    let sources = registered
+        .iter()
+        .map(|s| s.as_ref())
+        .chain(SOURCES.iter().copied());
+    let context = SourceContext::new(opt);
+    for source in sources {
+        if let Some(candidates) = source.candidates(&context, messages) {
+            display::log(2, || format!("Reading hashes from {}", source.name()));
+            return candidates.map(Some);
+        }
     }
     Ok(None)
 }
 
-/// Generate a candidate hash from the provided command line parameter, or throw an error.
-fn get_by_parameter(param: &str) -> Result<CandidateHashes, String> {
-    let bytes =
-        hex::decode(&param).map_err(|_| "Provided hash is invalid or truncated hex".to_owned())?;
-    let alg = Algorithm::from_len(bytes.len())?;
+/// A hash given as a command line argument
+struct ParameterSource;
+
+impl CandidateSource for ParameterSource {
+    fn name(&self) -> &str {
+        "the command line"
+    }
+
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        _messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>> {
+        context.hash.map(parse_hash)
+    }
+}
+
+/// A hash pasted from the clipboard
+struct ClipboardSource;
+
+impl CandidateSource for ClipboardSource {
+    fn name(&self) -> &str {
+        "the clipboard"
+    }
+
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        _messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>> {
+        context.opt.get_paste().then(get_from_clipboard)
+    }
+}
+
+/// A magnet link given in place of a check file
+struct MagnetSource;
+
+impl CandidateSource for MagnetSource {
+    fn name(&self) -> &str {
+        "a magnet link"
+    }
+
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        _messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>> {
+        let uri = context.hash_file?.to_str()?;
+        uri.starts_with("magnet:")
+            .then(|| get_from_magnet(uri, context.opt))
+    }
+}
+
+/// A check file, torrent or manifest, for one input or a whole list
+struct CheckFileSource;
+
+impl CandidateSource for CheckFileSource {
+    fn name(&self) -> &str {
+        "a check file"
+    }
+
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>> {
+        let opt = context.opt;
+        let path = context.hash_file.or(opt.check_all.as_deref())?;
+        Some(get_from_file(path, opt, messages))
+    }
+}
+
+/// The checksum assets of a GitHub release
+struct GitHubSource;
+
+impl CandidateSource for GitHubSource {
+    fn name(&self) -> &str {
+        "a GitHub release"
+    }
+
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>> {
+        let release = context.opt.github.as_ref()?;
+        Some(get_from_github(release, context.opt, messages))
+    }
+}
+
+/// A checksum file published next to a downloaded input
+struct SidecarSource;
+
+impl CandidateSource for SidecarSource {
+    fn name(&self) -> &str {
+        "a sidecar checksum file"
+    }
+
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>> {
+        let opt = context.opt;
+        opt.auto_sums.then(|| get_from_sidecar(opt, messages))
+    }
+}
+
+/// A hash typed or pasted in answer to a prompt. Nothing but Enter means there is no hash, so
+/// the digests are only calculated.
+struct InteractiveSource;
+
+impl CandidateSource for InteractiveSource {
+    fn name(&self) -> &str {
+        "the prompt"
+    }
+
+    fn candidates(
+        &self,
+        context: &SourceContext,
+        _messages: &mut Vec<(MessageLevel, String)>,
+    ) -> Option<Result<CandidateHashes, HashgoodError>> {
+        if !context.opt.interactive {
+            return None;
+        }
+        eprint!("{} ", tr!("prompt-hash"));
+        let mut line = String::new();
+        if let Err(e) = io::stdin().read_line(&mut line) {
+            return Some(Err(HashgoodError::io("Unable to read the hash", e)));
+        }
+        let line = line.trim();
+        (!line.is_empty()).then(|| parse_hash(line))
+    }
+}
+
+/// Parse a single hash, such as one given on the command line. It can be hex, base32 or base64
+/// of a known length, an SRI string, a multihash or an ssdeep fuzzy hash.
+pub fn parse_hash(param: &str) -> Result<CandidateHashes, HashgoodError> {
+    if fuzzy::is_fuzzy_hash(param) {
+        return Ok(CandidateHashes {
+            alg: Algorithm::Ssdeep,
+            hashes: vec![CandidateHash {
+                filename: None,
+                bytes: param.trim().as_bytes().to_vec(),
+                size: None,
+            }],
+            source: VerificationSource::CommandArgument,
+        });
+    }
+    let self_describing = try_parse_sri(param)
+        .or_else(|| try_parse_prefixed(param))
+        .or_else(|| multihash::parse(param));
+    let (alg, bytes) = match self_describing {
+        Some(sri) => sri,
+        None => {
+            if let Some(truncated) = describe_truncated(param) {
+                return Err(HashgoodError::InvalidHash(truncated));
+            }
+            let bytes = decode_unprefixed(param).ok_or_else(|| {
+                HashgoodError::InvalidHash(
+                    "Provided hash is invalid or truncated hex, base32 or base64".to_owned(),
+                )
+            })?;
+            let alg = Algorithm::from_len(bytes.len()).map_err(HashgoodError::InvalidHash)?;
+            (alg, bytes)
+        }
+    };
     let candidate = CandidateHash {
         filename: None,
         bytes,
+        size: None,
     };
     Ok(CandidateHashes {
         alg,
@@ -40,55 +296,404 @@ fn get_by_parameter(param: &str) -> Result<CandidateHashes, String> {
     })
 }
 
-/// Generate a candidate hash from the system clipboard, or throw an error.
-fn get_from_clipboard() -> Result<CandidateHashes, String> {
-    #[cfg(feature = "paste")]
-    {
-        let mut ctx: ClipboardContext = match ClipboardContext::new() {
-            Ok(ctx) => ctx,
-            Err(e) => return Err(format!("Error getting system clipboard: {}", e)),
-        };
+/// Recognise hex which is a few characters short of a complete hash, as happens when the end is
+/// missed while copying it
+fn describe_truncated(s: &str) -> Option<String> {
+    const HEX_LENGTHS: &[(&str, usize)] = &[
+        ("MD5", 32),
+        ("SHA-1", 40),
+        ("SHA-256", 64),
+        ("SHA-384", 96),
+        ("SHA-512", 128),
+    ];
+    if !s.bytes().all(|b| b.is_ascii_hexdigit()) || HEX_LENGTHS.iter().any(|(_, l)| *l == s.len()) {
+        return None;
+    }
+    HEX_LENGTHS.iter().find_map(|(name, len)| {
+        let missing = len.checked_sub(s.len())?;
+        (1..=8).contains(&missing).then(|| {
+            format!(
+                "Provided hash looks like a {} hash truncated by {} hex chars",
+                name, missing
+            )
+        })
+    })
+}
 
-        let possible_hash = match ctx.get_contents() {
-            Ok(value) => value,
-            Err(e) => format!("Error reading from clipboard: {}", e),
-        };
+/// Generate candidate hashes from hex found in the filename of an input, ignoring its directory
+pub fn get_from_name(name: &str) -> Result<CandidateHashes, HashgoodError> {
+    let filename = Path::new(name).file_name().unwrap_or(name.as_ref());
+    let (alg, hashes) = find_hex_hashes(&filename.to_string_lossy())
+        .ok_or_else(|| format!("No hash was found in the filename '{}'", name))?;
+    Ok(CandidateHashes {
+        alg,
+        hashes: hashes
+            .into_iter()
+            .map(|bytes| CandidateHash {
+                filename: None,
+                bytes,
+                size: None,
+            })
+            .collect(),
+        source: VerificationSource::InputName,
+    })
+}
 
-        let bytes = hex::decode(&possible_hash)
-            .map_err(|_| "Clipboard contains invalid or truncated hex".to_owned())?;
-        let alg = Algorithm::from_len(bytes.len())?;
-        let candidate = CandidateHash {
-            filename: None,
+/// Attributes where integrity tools commonly store checksums, strongest first
+const CHECKSUM_XATTRS: &[&str] = &[
+    "user.checksum.sha512",
+    "user.checksum.sha256",
+    "user.checksum.sha1",
+    "user.checksum.md5",
+    "user.shatag.sha512",
+    "user.shatag.sha256",
+];
+
+/// Generate a candidate hash from a checksum stored in an extended attribute of the input. If no
+/// attribute is named, the first of the common ones which the file has is used.
+pub fn get_from_xattr(path: &Path, name: Option<&str>) -> Result<CandidateHashes, HashgoodError> {
+    let names = match name {
+        Some(name) => vec![name],
+        None => CHECKSUM_XATTRS.to_vec(),
+    };
+    let mut found = None;
+    for name in names {
+        if let Some(value) = xattr::get_xattr(path, name)? {
+            found = Some((name, value));
+            break;
+        }
+    }
+    let (name, value) = found.ok_or_else(|| match name {
+        Some(name) => format!("'{}' has no attribute '{}'", path.to_string_lossy(), name),
+        None => format!("'{}' has no checksum attribute", path.to_string_lossy()),
+    })?;
+    // Most tools store the hash as text but some store the raw bytes
+    let text = std::str::from_utf8(&value)
+        .ok()
+        .map(|v| v.trim_end_matches('\0'));
+    let (alg, bytes) = match text.and_then(try_parse_hash) {
+        Some(hash) => hash,
+        None => (
+            Algorithm::from_len(value.len()).map_err(HashgoodError::InvalidHash)?,
+            value,
+        ),
+    };
+    // The attribute name usually says which algorithm it is
+    let named_alg = name.rsplit('.').next().and_then(algorithm_from_name);
+    if named_alg.is_some_and(|a| a != alg) {
+        return Err(HashgoodError::InvalidHash(format!(
+            "Attribute '{}' does not contain a hash of the expected length",
+            name
+        )));
+    }
+    Ok(CandidateHashes {
+        alg,
+        hashes: vec![CandidateHash {
             bytes,
-        };
-        Ok(CandidateHashes {
-            alg,
-            hashes: vec![candidate],
-            source: VerificationSource::Clipboard,
+            filename: None,
+            size: None,
+        }],
+        source: VerificationSource::ExtendedAttribute(name.to_owned()),
+    })
+}
+
+/// Generate a candidate hash from the exact topic of a magnet link
+fn get_from_magnet(uri: &str, opt: &Opt) -> Result<CandidateHashes, HashgoodError> {
+    if opt.key.is_some() || opt.minisign_pub.is_some() || opt.signify_pub.is_some() {
+        return Err(HashgoodError::Usage(
+            "A magnet link cannot be signed".to_owned(),
+        ));
+    }
+    let magnet = magnet::parse_magnet(uri).map_err(HashgoodError::InvalidHash)?;
+    Ok(CandidateHashes {
+        alg: magnet.alg,
+        hashes: vec![CandidateHash {
+            bytes: magnet.bytes,
+            filename: magnet.name,
+            size: magnet.length,
+        }],
+        source: VerificationSource::MagnetLink,
+    })
+}
+
+/// Set by --timeout to give up on standard input or the clipboard if nothing arrives. Zero means
+/// wait for ever.
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(0);
+
+/// Give up reading hashes from standard input or the clipboard after this many seconds
+pub fn set_timeout(seconds: u64) {
+    TIMEOUT_SECS.store(seconds, Ordering::Relaxed);
+}
+
+/// Run a read which might wait for ever, such as on a terminal which nothing was piped into.
+/// After the timeout the read is abandoned with an error saying what was being waited for. The
+/// read is told when that will happen, if there is a timeout, so that it can finish first.
+fn with_timeout<T: Send + 'static>(
+    waiting_for: &str,
+    read: impl FnOnce(Option<Instant>) -> T + Send + 'static,
+) -> Result<T, HashgoodError> {
+    let seconds = TIMEOUT_SECS.load(Ordering::Relaxed);
+    // Without threads there is no way to stop waiting
+    if seconds == 0 || cfg!(target_family = "wasm") {
+        return Ok(read(None));
+    }
+    let deadline = Instant::now() + Duration::from_secs(seconds);
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(read(Some(deadline)));
+    });
+    rx.recv_timeout(Duration::from_secs(seconds)).map_err(|_| {
+        HashgoodError::Other(format!(
+            "Timed out after {} seconds waiting for {}",
+            seconds, waiting_for
+        ))
+    })
+}
+
+/// Generate a candidate hash from the system clipboard, or throw an error.
+fn get_from_clipboard() -> Result<CandidateHashes, HashgoodError> {
+    #[cfg(all(feature = "paste", not(target_family = "wasm")))]
+    {
+        let (contents, provider) = with_timeout("the clipboard", clipboard::get_contents)??;
+        display::log(1, || format!("Read the clipboard with {}", provider));
+
+        parse_loose_text(&contents, VerificationSource::Clipboard).ok_or_else(|| {
+            HashgoodError::InvalidHash("Clipboard does not contain a hex hash".to_owned())
         })
     }
-    #[cfg(not(feature = "paste"))]
+    #[cfg(not(all(feature = "paste", not(target_family = "wasm"))))]
     {
-        Err("Paste not implemented".to_owned())
+        Err(HashgoodError::Unsupported(
+            "Paste not implemented".to_owned(),
+        ))
+    }
+}
+
+/// Interpret text which was copied or scanned rather than saved as a check file. It could be part
+/// of a digests file, or a hash along with the text around it on a web page.
+fn parse_loose_text(contents: &str, source: VerificationSource) -> Option<CandidateHashes> {
+    let lines = || contents.lines().map(io::Result::Ok);
+    let digests = read_coreutils_digests_from_file(lines(), Path::new("-"))
+        .or_else(|| read_bsd_digests_from_file(lines(), Path::new("-")));
+    // Stray lines are expected when copying from a web page so they aren't reported
+    if let Some((candidates, _)) = digests {
+        return Some(CandidateHashes {
+            source,
+            ..candidates
+        });
+    }
+    // A hash on its own may be in any of the formats accepted on the command line
+    let (alg, hashes) = match try_parse_hash(contents) {
+        Some((alg, bytes)) => (alg, vec![bytes]),
+        None => find_hex_hashes(contents)?,
+    };
+    Some(CandidateHashes {
+        alg,
+        hashes: hashes
+            .into_iter()
+            .map(|bytes| CandidateHash {
+                filename: None,
+                bytes,
+                size: None,
+            })
+            .collect(),
+        source,
+    })
+}
+
+/// Find the hex tokens in some text which are the length of a known hash. If there are several
+/// lengths, only the hashes of the strongest algorithm are returned.
+fn find_hex_hashes(text: &str) -> Option<(Algorithm, Vec<Vec<u8>>)> {
+    let mut found: Vec<Vec<u8>> = vec![];
+    for token in text.split(|c: char| !c.is_ascii_alphanumeric()) {
+        if let Ok(bytes) = hex::decode(token) {
+            if Algorithm::from_len(bytes.len()).is_ok() && !found.contains(&bytes) {
+                found.push(bytes);
+            }
+        }
+    }
+    let longest = found.iter().map(Vec::len).max()?;
+    found.retain(|b| b.len() == longest);
+    Some((Algorithm::from_len(longest).ok()?, found))
+}
+
+/// Verify the signature on a check file if a trusted key was given, returning the signed contents
+fn check_signature(
+    data: Vec<u8>,
+    path: &Path,
+    opt: &Opt,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<Vec<u8>, String> {
+    // Unless told otherwise, look for the signature next to the check file
+    let sig_path = |extension: &str| match &opt.sig {
+        Some(sig) => Ok(sig.clone()),
+        None if path.to_str() == Some("-") => {
+            Err("--sig is required when the check file is standard input".to_owned())
+        }
+        None => {
+            let mut sig = path.as_os_str().to_owned();
+            sig.push(extension);
+            Ok(PathBuf::from(sig))
+        }
+    };
+    let clearsigned = data.starts_with(signature::CLEARSIGN_HEADER);
+    if let Some(key) = &opt.key {
+        // A clearsigned check file carries its own signature
+        let sig = match opt.sig.is_none() && clearsigned {
+            true => None,
+            false => Some(sig_path(".asc")?),
+        };
+        let signer = signature::verify_pgp(&data, sig.as_deref(), key)?;
+        messages.push((MessageLevel::Note, tr!("signature-gpg", signer = signer)));
+    } else if let Some(key) = &opt.minisign_pub {
+        let comment = signature::verify_minisign(&data, &sig_path(".minisig")?, key)?;
+        messages.push((
+            MessageLevel::Note,
+            tr!("signature-minisign", comment = comment),
+        ));
+    } else if let Some(key) = &opt.signify_pub {
+        // OpenBSD releases embed the signature at the start of the check file itself
+        let sig = match opt.sig.is_none() && data.starts_with(b"untrusted comment: ") {
+            true => None,
+            false => Some(sig_path(".sig")?),
+        };
+        let (comment, signed) = signature::verify_signify(&data, sig.as_deref(), key)?;
+        messages.push((
+            MessageLevel::Note,
+            tr!("signature-signify", comment = comment),
+        ));
+        return Ok(signed.to_vec());
+    } else if clearsigned {
+        messages.push((MessageLevel::Note, tr!("signature-unchecked")));
+    }
+    match clearsigned {
+        true => signature::strip_clearsign(&data)
+            .ok_or_else(|| "The signed check file is incomplete".to_owned()),
+        false => Ok(data),
     }
 }
 
 /// Generate a candidate hash from the digests file specified (could be "-" for STDIN), or throw an error.
-fn get_from_file(path: &Path) -> Result<CandidateHashes, String> {
-    // Get a reader for either standard input or the chosen path
-    let reader: Box<dyn Read> = if path.to_str() == Some("-") {
-        Box::new(std::io::stdin())
+fn get_from_file(
+    path: &Path,
+    opt: &Opt,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<CandidateHashes, HashgoodError> {
+    // Read either standard input or the chosen path
+    let data = if path.to_str() == Some("-") {
+        with_timeout("the check file on standard input", |_| {
+            let mut data = vec![];
+            std::io::stdin().read_to_end(&mut data).map(|_| data)
+        })?
     } else {
-        Box::new(File::open(path).map_err(|_| {
-            format!(
+        let mut data = vec![];
+        let mut file = File::open(path).map_err(|e| {
+            let context = format!(
                 "Unable to open check file at path '{}'",
                 path.to_string_lossy()
-            )
-        })?)
-    };
+            );
+            HashgoodError::io(context, e)
+        })?;
+        file.read_to_end(&mut data).map(|_| data)
+    }
+    .map_err(|e| HashgoodError::io("Error reading from check file", e))?;
+
+    // If it is signed, nothing in it can be trusted until the signature has been checked
+    let data = check_signature(data, path, opt, messages)?;
+    parse_check_data(data, path, CheckFileOptions::from_opt(opt), messages)
+}
+
+/// Generate candidate hashes from the checksums file attached to a GitHub release
+fn get_from_github(
+    release: &str,
+    opt: &Opt,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<CandidateHashes, HashgoodError> {
+    let input_name = opt.input.as_ref().and_then(|i| i.file_name());
+    let input_name = input_name.map(|n| n.to_string_lossy());
+    let (url, data) = github::download_checksums(release, input_name.as_deref())?;
+    parse_check_data(
+        data,
+        Path::new(&url),
+        CheckFileOptions::from_opt(opt),
+        messages,
+    )
+}
+
+/// Generate candidate hashes from a checksums file published next to the input URL
+fn get_from_sidecar(
+    opt: &Opt,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<CandidateHashes, HashgoodError> {
+    let url = opt
+        .input
+        .as_deref()
+        .and_then(as_url)
+        .ok_or_else(|| "--auto-sums requires the input to be an HTTP(S) URL".to_owned())?;
+    let (url, data) = sidecar::download_sidecar(url)?;
+    parse_check_data(
+        data,
+        Path::new(&url),
+        CheckFileOptions::from_opt(opt),
+        messages,
+    )
+}
+
+/// How `parse_check_data` should treat a check file
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CheckFileOptions {
+    /// Fail if any line of the check file can't be understood, instead of warning about it
+    pub strict: bool,
+    /// Read the hash from a QR code if the check file is an image. This runs `zbarimg`, so it is
+    /// off unless asked for.
+    pub qr_codes: bool,
+}
+
+impl CheckFileOptions {
+    /// The options chosen on the command line, where QR codes are always read
+    fn from_opt(opt: &Opt) -> Self {
+        CheckFileOptions {
+            strict: opt.strict,
+            qr_codes: true,
+        }
+    }
+}
+
+/// Interpret the contents of a check file, which is named by `path` in the output. Any format
+/// hashgood recognises is accepted, from a raw hash to a digests file or a torrent. Warnings about
+/// the file are added to `messages`, unless `options.strict` is set in which case they are errors.
+pub fn parse_check_data(
+    data: Vec<u8>,
+    path: &Path,
+    options: CheckFileOptions,
+    messages: &mut Vec<(MessageLevel, String)>,
+) -> Result<CandidateHashes, HashgoodError> {
+    let name = path.to_string_lossy();
+    display::log(2, || {
+        format!("Read {} bytes from check file '{}'", data.len(), name)
+    });
+    // Is it a binary .torrent file? If so, use its piece hashes
+    if let Some(torrent) = torrent::parse_torrent(&data) {
+        display::log(1, || format!("Interpreted '{}' as a torrent", name));
+        return read_torrent_candidates(torrent.map_err(HashgoodError::InvalidHash)?, path);
+    }
+
+    // Is it a photo or screenshot of a QR code?
+    if options.qr_codes && qr::is_image(&data) {
+        let text = qr::decode_qr(&data)?;
+        display::log(1, || format!("Read QR code in '{}': {}", name, text.trim()));
+        let source = VerificationSource::QrCode(path.to_string_lossy().to_string());
+        return parse_loose_text(&text, source).ok_or_else(|| {
+            HashgoodError::InvalidHash(format!(
+                "The QR code does not contain a hash: {}",
+                text.trim()
+            ))
+        });
+    }
 
     // Read the first line, trimmed
-    let mut reader = BufReader::new(reader);
+    let data = normalise_text(data);
+    let mut reader = BufReader::new(Cursor::new(&data));
     let mut line = String::new();
     reader
         .read_line(&mut line)
@@ -97,31 +702,95 @@ fn get_from_file(path: &Path) -> Result<CandidateHashes, String> {
 
     // Does our first line look like a raw hash on its own? If so, use that
     if let Some(candidate) = read_raw_candidate_from_file(&line, path) {
+        display::log(1, || format!("Interpreted '{}' as a raw hash", name));
         return Ok(candidate);
     }
+    display::log(2, || "The first line is not a raw hash".to_owned());
 
     // Maybe it's a digests file
-    // Reconstruct the full iterator by joining our already-read line with the others
-    let full_lines = vec![Ok(line)].into_iter().chain(reader.lines());
+    // Reconstruct the full list by joining our already-read line with the others
+    let full_lines = std::iter::once(Ok(line))
+        .chain(reader.lines())
+        .collect::<io::Result<Vec<String>>>()
+        .map_err(|e| HashgoodError::io("Error reading from check file", e))?;
 
-    // Does the entire file look like a coreutils-style digests file? (SHA1SUMS, etc.)
-    if let Some(candidate) = read_coreutils_digests_from_file(full_lines, path) {
-        return Ok(candidate);
+    // Does the file look like a coreutils-style digests file (SHA1SUMS, etc.), the BSD tagged
+    // equivalent, an apt Release file, a hashdeep audit file, a Media Hash List, or a structured
+    // manifest?
+    type Reader<'a> = &'a dyn Fn() -> Option<(CandidateHashes, usize)>;
+    let formats: [(&str, Reader); 6] = [
+        ("coreutils digests file", &|| {
+            read_coreutils_digests_from_file(full_lines.iter().map(Ok), path)
+        }),
+        ("BSD tagged digests file", &|| {
+            read_bsd_digests_from_file(full_lines.iter().map(Ok), path)
+        }),
+        ("Debian Release file", &|| {
+            read_release_from_file(&full_lines, path)
+        }),
+        ("hashdeep audit file", &|| {
+            read_hashdeep_from_file(&full_lines, path)
+        }),
+        ("Media Hash List", &|| read_mhl_candidates(&data, path)),
+        ("JSON or YAML manifest", &|| {
+            read_manifest_candidates(&data, path)
+        }),
+    ];
+    let digests = formats.iter().find_map(|(format, read)| {
+        let digests = read();
+        match &digests {
+            Some((candidates, improper)) => display::log(1, || {
+                format!(
+                    "Interpreted '{}' as a {} with {} hash(es) and {} improper line(s)",
+                    name,
+                    format,
+                    candidates.hashes.len(),
+                    improper
+                )
+            }),
+            None => display::log(2, || format!("The check file is not a {}", format)),
+        }
+        digests
+    });
+    if let Some((candidates, improper)) = digests {
+        if improper > 0 {
+            if options.strict {
+                return Err(HashgoodError::InvalidHash(format!(
+                    "{} lines in check file '{}' are improperly formatted",
+                    improper,
+                    path.to_string_lossy()
+                )));
+            }
+            messages.push((
+                MessageLevel::Warning,
+                tr!("improper-lines", count = improper),
+            ));
+        }
+        return Ok(candidates);
     }
 
     // If neither of these techniques worked this is a fatal error
     // The user requested we use this input but we couldn't
-    Err(format!(
+    Err(HashgoodError::InvalidHash(format!(
         "Provided check file '{}' was neither a hash nor a valid digests file",
         path.to_string_lossy()
-    ))
+    )))
 }
 
 fn try_parse_hash(s: &str) -> Option<(Algorithm, Vec<u8>)> {
-    let bytes = match hex::decode(s.trim()) {
-        Ok(bytes) => bytes,
-        _ => return None,
-    };
+    if fuzzy::is_fuzzy_hash(s) {
+        return Some((Algorithm::Ssdeep, s.trim().as_bytes().to_vec()));
+    }
+    if let Some(sri) = try_parse_sri(s) {
+        return Some(sri);
+    }
+    if let Some(prefixed) = try_parse_prefixed(s) {
+        return Some(prefixed);
+    }
+    if let Some(multihash) = multihash::parse(s) {
+        return Some(multihash);
+    }
+    let bytes = decode_unprefixed(s.trim())?;
     let alg = match Algorithm::from_len(bytes.len()) {
         Ok(alg) => alg,
         _ => return None,
@@ -129,6 +798,132 @@ fn try_parse_hash(s: &str) -> Option<(Algorithm, Vec<u8>)> {
     Some((alg, bytes))
 }
 
+/// Decode a hash which doesn't say how it is encoded, trying hex first. A hex hash with a typo in
+/// it could also be valid base32 or base64 of a different length, so those are only tried when
+/// the string doesn't look like hex. A Nix SHA-1 is as long as a hex MD5 so it needs a `sha1:`
+/// prefix.
+fn decode_unprefixed(s: &str) -> Option<Vec<u8>> {
+    hex::decode(s)
+        .ok()
+        .or_else(|| decode_separated_hex(s))
+        .or_else(|| {
+            (!is_hex_length(s.len()))
+                .then(|| decode_nix_base32(s))
+                .flatten()
+        })
+        .or_else(|| (!looks_like_hex(s)).then(|| decode_base64(s)).flatten())
+}
+
+/// Whether a hex digest of a known algorithm would have this many characters
+fn is_hex_length(len: usize) -> bool {
+    len.is_multiple_of(2) && Algorithm::from_len(len / 2).is_ok()
+}
+
+/// Whether `s` has the length of a hex digest and none of the symbols or mixed case letters that
+/// base64 of that length would almost certainly contain
+fn looks_like_hex(s: &str) -> bool {
+    let has = |f: fn(&char) -> bool| s.chars().any(|c| f(&c));
+    is_hex_length(s.len())
+        && !(has(char::is_ascii_uppercase) && has(char::is_ascii_lowercase))
+        && !has(|c| "+/=-_".contains(*c))
+}
+
+/// Decode a hash given as base64 rather than hex, as returned by many cloud storage APIs. Both
+/// the standard and URL-safe alphabets are accepted, with or without padding.
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    [STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD]
+        .iter()
+        .find_map(|engine| engine.decode(s).ok())
+}
+
+/// Decode hex written in groups of two or four characters separated by colons or spaces, as shown
+/// for certificate and SSH key fingerprints
+fn decode_separated_hex(s: &str) -> Option<Vec<u8>> {
+    let groups: Vec<&str> = s.split(|c: char| c == ':' || c.is_whitespace()).collect();
+    let group_len = groups.first()?.len();
+    if groups.len() < 2 || ![2, 4].contains(&group_len) {
+        return None;
+    }
+    if groups.iter().any(|g| g.len() != group_len) {
+        return None;
+    }
+    hex::decode(groups.concat()).ok()
+}
+
+/// Decode a hash in the base32 encoding used by Nix, which has its own alphabet and puts the least
+/// significant bits first. Only lengths which match a known algorithm are accepted.
+fn decode_nix_base32(s: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+    let len = s.len() * 5 / 8;
+    if Algorithm::from_len(len).is_err() || (len * 8 - 1) / 5 + 1 != s.len() {
+        return None;
+    }
+    let mut bytes = vec![0u8; len];
+    for (n, c) in s.bytes().rev().enumerate() {
+        let digit = ALPHABET.iter().position(|a| *a == c)? as u16;
+        let (i, j) = (n * 5 / 8, n * 5 % 8);
+        let shifted = digit << j;
+        bytes[i] |= shifted as u8;
+        // Bits which don't fit in the last byte must be zero
+        match bytes.get_mut(i + 1) {
+            Some(next) => *next |= (shifted >> 8) as u8,
+            None if shifted >> 8 != 0 => return None,
+            None => (),
+        }
+    }
+    Some(bytes)
+}
+
+/// Parse a hash prefixed with the name of its algorithm, such as `sha256:<hex>` as used by OCI
+/// images or `sha256:<base32>` as used by Nix
+fn try_parse_prefixed(s: &str) -> Option<(Algorithm, Vec<u8>)> {
+    let (name, digest) = s.trim().split_once(':')?;
+    let alg = algorithm_from_name(name)?;
+    let bytes = hex::decode(digest)
+        .ok()
+        .or_else(|| decode_nix_base32(digest))
+        .or_else(|| decode_base64(digest))?;
+    (Algorithm::from_len(bytes.len()) == Ok(alg)).then_some((alg, bytes))
+}
+
+/// Parse a Subresource Integrity value such as `sha384-<base64>`, as found in HTML and package
+/// lock files. If several hashes are listed, the strongest supported one is used.
+fn try_parse_sri(s: &str) -> Option<(Algorithm, Vec<u8>)> {
+    s.split_whitespace()
+        .filter_map(|token| {
+            let (prefix, encoded) = token.split_once('-')?;
+            let alg = match prefix {
+                "sha256" => Algorithm::Sha256,
+                "sha384" => Algorithm::Sha384,
+                "sha512" => Algorithm::Sha512,
+                _ => return None,
+            };
+            // Options may follow a question mark, though none are defined yet
+            let encoded = encoded.split('?').next().unwrap_or(encoded);
+            let bytes = STANDARD.decode(encoded).ok()?;
+            (Algorithm::from_len(bytes.len()).ok()? == alg).then_some((alg, bytes))
+        })
+        .max_by_key(|(_, bytes)| bytes.len())
+}
+
+/// Remove the byte order mark and CRLF line endings which check files saved on Windows often have
+fn normalise_text(data: Vec<u8>) -> Vec<u8> {
+    let data = match data.strip_prefix(b"\xef\xbb\xbf") {
+        Some(rest) => rest.to_vec(),
+        None => data,
+    };
+    if !data.contains(&b'\r') {
+        return data;
+    }
+    let mut normalised = Vec::with_capacity(data.len());
+    for (i, b) in data.iter().enumerate() {
+        if *b != b'\r' || data.get(i + 1) != Some(&b'\n') {
+            normalised.push(*b);
+        }
+    }
+    normalised
+}
+
 fn read_raw_candidate_from_file(line: &str, path: &Path) -> Option<CandidateHashes> {
     let (alg, bytes) = try_parse_hash(line)?;
     Some(CandidateHashes {
@@ -137,46 +932,193 @@ fn read_raw_candidate_from_file(line: &str, path: &Path) -> Option<CandidateHash
         hashes: vec![CandidateHash {
             bytes,
             filename: None,
+            size: None,
         }],
     })
 }
 
-fn read_coreutils_digests_from_file<I, S>(lines: I, path: &Path) -> Option<CandidateHashes>
+/// Read a Media Hash List. Files often have several hashes, so the strongest algorithm listed is
+/// used and any file without a hash of that type counts as improper.
+fn read_mhl_candidates(data: &[u8], path: &Path) -> Option<(CandidateHashes, usize)> {
+    // Element names in order of preference and the length of their digests
+    const ALGORITHMS: &[(&str, Algorithm, usize)] = &[
+        ("sha1", Algorithm::Sha1, 20),
+        ("md5", Algorithm::Md5, 16),
+        ("xxh128", Algorithm::Xxh128, 16),
+        ("xxh64", Algorithm::Xxh64, 8),
+        ("xxhash64be", Algorithm::Xxh64, 8),
+        ("xxhash64", Algorithm::Xxh64, 8),
+    ];
+    let entries = mhl::parse_mhl(data)?;
+    let (_, alg, len) = ALGORITHMS.iter().find(|(name, _, _)| {
+        entries
+            .iter()
+            .any(|e| e.hashes.iter().any(|(n, _)| n == name))
+    })?;
+    let mut hashes = vec![];
+    let mut improper = 0;
+    for entry in entries {
+        let bytes = entry
+            .hashes
+            .iter()
+            .filter(|(name, _)| ALGORITHMS.iter().any(|(n, a, _)| n == name && a == alg))
+            .find_map(|(_, hash)| hex::decode(hash).ok().filter(|b| b.len() == *len));
+        match bytes {
+            Some(bytes) if !entry.path.is_empty() => hashes.push(CandidateHash {
+                bytes,
+                filename: Some(entry.path),
+                size: entry.size,
+            }),
+            _ => improper += 1,
+        }
+    }
+    let candidates = CandidateHashes {
+        alg: *alg,
+        source: VerificationSource::DigestsFile(path.to_string_lossy().to_string()),
+        hashes,
+    };
+    Some((candidates, improper))
+}
+
+/// Read a JSON or YAML manifest of filenames and their digests. All of the entries must use the
+/// same algorithm. Returns the candidates and the number of entries which couldn't be understood.
+fn read_manifest_candidates(data: &[u8], path: &Path) -> Option<(CandidateHashes, usize)> {
+    let (entries, mut improper) = manifest::parse_manifest(data)?;
+    let mut alg: Option<Algorithm> = None;
+    let mut hashes = vec![];
+    for entry in entries {
+        // The algorithm may be named separately, as a prefix of the digest, or both
+        let mut expected = vec![];
+        if let Some(name) = &entry.algorithm {
+            expected.push(algorithm_from_name(name));
+        }
+        let digest = match entry.digest.split_once(':') {
+            Some((prefix, digest)) if algorithm_from_name(prefix).is_some() => {
+                expected.push(algorithm_from_name(prefix));
+                digest
+            }
+            _ => entry.digest.as_str(),
+        };
+        let (entry_alg, bytes) = match try_parse_hash(digest) {
+            Some((a, bytes)) if expected.iter().all(|e| *e == Some(a)) => (a, bytes),
+            _ => {
+                improper += 1;
+                continue;
+            }
+        };
+        if alg.is_some() && alg != Some(entry_alg) {
+            return None;
+        }
+        alg = Some(entry_alg);
+        hashes.push(CandidateHash {
+            bytes,
+            filename: Some(entry.name),
+            size: None,
+        });
+    }
+    let candidates = CandidateHashes {
+        alg: alg?,
+        source: VerificationSource::DigestsFile(path.to_string_lossy().to_string()),
+        hashes,
+    };
+    Some((candidates, improper))
+}
+
+/// Identify an algorithm from a name such as `SHA-256` or `sha256`
+fn algorithm_from_name(name: &str) -> Option<Algorithm> {
+    let name: String = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    match name.as_str() {
+        "md5" => Some(Algorithm::Md5),
+        "sha1" => Some(Algorithm::Sha1),
+        "sha256" => Some(Algorithm::Sha256),
+        "sha384" => Some(Algorithm::Sha384),
+        "sha512" => Some(Algorithm::Sha512),
+        _ => None,
+    }
+}
+
+fn read_torrent_candidates(
+    torrent: torrent::Torrent,
+    path: &Path,
+) -> Result<CandidateHashes, HashgoodError> {
+    if torrent.pieces.is_empty() {
+        return Err(HashgoodError::InvalidHash(
+            "Torrent does not contain any pieces".to_owned(),
+        ));
+    }
+    Ok(CandidateHashes {
+        alg: Algorithm::Sha1,
+        source: VerificationSource::TorrentFile {
+            path: path.to_string_lossy().to_string(),
+            piece_length: torrent.piece_length,
+        },
+        hashes: torrent
+            .pieces
+            .into_iter()
+            .map(|bytes| CandidateHash {
+                bytes,
+                filename: Some(torrent.name.clone()),
+                size: None,
+            })
+            .collect(),
+    })
+}
+
+fn read_coreutils_digests_from_file<I, S>(lines: I, path: &Path) -> Option<(CandidateHashes, usize)>
+where
+    I: Iterator<Item = io::Result<S>>,
+    S: AsRef<str>,
+{
+    read_digests_from_file(lines, path, parse_coreutils_line)
+}
+
+fn read_bsd_digests_from_file<I, S>(lines: I, path: &Path) -> Option<(CandidateHashes, usize)>
+where
+    I: Iterator<Item = io::Result<S>>,
+    S: AsRef<str>,
+{
+    read_digests_from_file(lines, path, parse_bsd_line)
+}
+
+/// Parses one line of a digests file into its algorithm, hash and filename
+type LineParser = fn(&str) -> Option<(Algorithm, Vec<u8>, &str)>;
+
+/// Read a digests file where each line is parsed by `parse_line` into an algorithm, hash and
+/// filename. All of the lines must use the same algorithm. Lines which can't be parsed, such as
+/// comments, are skipped and counted, as long as at least one line can be.
+fn read_digests_from_file<I, S>(
+    lines: I,
+    path: &Path,
+    parse_line: LineParser,
+) -> Option<(CandidateHashes, usize)>
 where
     I: Iterator<Item = io::Result<S>>,
     S: AsRef<str>,
 {
     let mut hashes = vec![];
     let mut alg: Option<Algorithm> = None;
+    let mut improper = 0;
     for l in lines.flatten() {
         let l = l.as_ref().trim();
         // Allow (ignore) blank lines
         if l.is_empty() {
             continue;
         }
-        // Expected format
-        // <valid-hash><space><space-or-*><filename>
-        let (line_alg, bytes, filename) = match l
-            .find(' ')
-            .and_then(|space_pos| {
-                // Char before filename should be space for text or * for binary
-                match l.chars().nth(space_pos + 1) {
-                    Some(' ') | Some('*') => (l.get(..space_pos)).zip(l.get(space_pos + 2..)),
-                    _ => None,
-                }
-            })
-            .and_then(|(maybe_hash, filename)| {
-                // Filename should be in this position without extra whitespace
-                if filename.trim() == filename {
-                    try_parse_hash(maybe_hash).map(|(alg, bytes)| (alg, bytes, filename))
-                } else {
-                    None
-                }
-            }) {
+        // GNU coreutils marks lines whose filename contains escaped characters with a backslash
+        let parsed = match l.strip_prefix('\\') {
+            Some(escaped) => parse_line(escaped)
+                .and_then(|(alg, bytes, f)| unescape_filename(f).map(|f| (alg, bytes, f))),
+            None => parse_line(l).map(|(alg, bytes, f)| (alg, bytes, f.to_owned())),
+        };
+        let (line_alg, bytes, filename) = match parsed {
             Some(t) => t,
             None => {
-                // if we have a line with content we cannot parse, this is an error
-                return None;
+                improper += 1;
+                continue;
             }
         };
         if alg.is_some() && alg != Some(line_alg) {
@@ -189,7 +1131,8 @@ where
         // So far so good - create an entry for this line
         hashes.push(CandidateHash {
             bytes,
-            filename: Some(filename.to_owned()),
+            filename: Some(filename),
+            size: None,
         });
     }
 
@@ -203,37 +1146,287 @@ where
     };
 
     // Otherwise all is well and we can return our results
-    Some(CandidateHashes {
+    let candidates = CandidateHashes {
         alg,
         source: VerificationSource::DigestsFile(path.to_string_lossy().to_string()),
         hashes,
-    })
+    };
+    Some((candidates, improper))
+}
+
+/// If the check file lists a size for the input which differs from the size of the file on disk,
+/// describe the problem. Only regular files can be checked before they are read.
+pub fn check_listed_size(candidates: &CandidateHashes, input: &Input) -> Option<String> {
+    let candidate = candidates.hashes.iter().find(|c| {
+        c.filename
+            .as_deref()
+            .is_some_and(|f| filenames_match(f, &input.name))
+    })?;
+    let actual = wrong_size(candidate, &input.path)?;
+    Some(format!(
+        "'{}' is {} bytes but the check file lists it as {} bytes",
+        input.name, actual, candidate.size?
+    ))
+}
+
+/// The size of the file at `path` if the candidate lists a different size for it
+pub fn wrong_size(candidate: &CandidateHash, path: &Path) -> Option<u64> {
+    let expected = candidate.size?;
+    if path.to_str() == Some("-") {
+        return None;
+    }
+    let metadata = std::fs::metadata(path).ok().filter(|m| m.is_file())?;
+    (metadata.len() != expected).then_some(metadata.len())
+}
+
+/// Read an apt Release or InRelease file, or a Debian source control file. These have a section
+/// for each algorithm listing the hash, size and path of each file. The strongest algorithm is
+/// used.
+fn read_release_from_file(lines: &[String], path: &Path) -> Option<(CandidateHashes, usize)> {
+    // Section names in order of preference
+    const SECTIONS: &[(&str, Algorithm)] = &[
+        ("SHA512", Algorithm::Sha512),
+        ("Checksums-Sha512", Algorithm::Sha512),
+        ("SHA256", Algorithm::Sha256),
+        ("Checksums-Sha256", Algorithm::Sha256),
+        ("SHA1", Algorithm::Sha1),
+        ("Checksums-Sha1", Algorithm::Sha1),
+        ("MD5Sum", Algorithm::Md5),
+    ];
+    // The preference of each section found, its entries and how many lines couldn't be parsed
+    let mut sections: Vec<(usize, Vec<CandidateHash>, usize)> = vec![];
+    let mut in_section = false;
+    for line in lines {
+        // Entries are indented under their section's field name. Anything else ends the section.
+        if !line.starts_with(' ') {
+            let section = line
+                .trim_end()
+                .strip_suffix(':')
+                .and_then(|name| SECTIONS.iter().position(|(n, _)| *n == name));
+            in_section = section.is_some();
+            if let Some(preference) = section {
+                sections.push((preference, vec![], 0));
+            }
+            continue;
+        }
+        let (preference, hashes, improper) = match (in_section, sections.last_mut()) {
+            (true, Some(section)) => section,
+            _ => continue,
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let entry = match fields.as_slice() {
+            [hash, size, .., name] => hex::decode(hash)
+                .ok()
+                .filter(|b| Algorithm::from_len(b.len()) == Ok(SECTIONS[*preference].1))
+                .zip(size.parse().ok())
+                .map(|(bytes, size)| CandidateHash {
+                    bytes,
+                    filename: Some(name.to_string()),
+                    size: Some(size),
+                }),
+            _ => None,
+        };
+        match entry {
+            Some(entry) => hashes.push(entry),
+            None => *improper += 1,
+        }
+    }
+    let (preference, hashes, improper) = sections
+        .into_iter()
+        .filter(|(_, hashes, _)| !hashes.is_empty())
+        .min_by_key(|(preference, _, _)| *preference)?;
+    let candidates = CandidateHashes {
+        alg: SECTIONS[preference].1,
+        source: VerificationSource::DigestsFile(path.to_string_lossy().to_string()),
+        hashes,
+    };
+    Some((candidates, improper))
+}
+
+/// Read the output of hashdeep, which starts with a header naming the columns. Every line has the
+/// size of a file and one or more hashes, followed by its filename. The strongest algorithm is used.
+fn read_hashdeep_from_file(lines: &[String], path: &Path) -> Option<(CandidateHashes, usize)> {
+    if !lines.first()?.starts_with("%%%% HASHDEEP-") {
+        return None;
+    }
+    let columns: Vec<&str> = lines
+        .iter()
+        .find_map(|l| l.strip_prefix("%%%% size,"))?
+        .split(',')
+        .collect();
+    // The filename is always the last column
+    let (alg, hash_column) = [
+        ("sha256", Algorithm::Sha256),
+        ("sha1", Algorithm::Sha1),
+        ("md5", Algorithm::Md5),
+    ]
+    .iter()
+    .find_map(|(name, alg)| Some((*alg, columns.iter().position(|c| c == name)?)))?;
+    let mut hashes = vec![];
+    let mut improper = 0;
+    // Lines starting with %% or ## are part of the header
+    for line in lines
+        .iter()
+        .filter(|l| !l.starts_with("%%") && !l.starts_with("##"))
+    {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // The filename may itself contain commas
+        let fields: Vec<&str> = line.splitn(columns.len() + 1, ',').collect();
+        let entry = match fields.as_slice() {
+            [size, rest @ ..] if rest.len() == columns.len() => hex::decode(rest[hash_column])
+                .ok()
+                .filter(|b| Algorithm::from_len(b.len()) == Ok(alg))
+                .zip(size.parse().ok())
+                .map(|(bytes, size)| CandidateHash {
+                    bytes,
+                    filename: Some(rest[columns.len() - 1].to_owned()),
+                    size: Some(size),
+                }),
+            _ => None,
+        };
+        match entry {
+            Some(entry) => hashes.push(entry),
+            None => improper += 1,
+        }
+    }
+    if hashes.is_empty() {
+        return None;
+    }
+    let candidates = CandidateHashes {
+        alg,
+        source: VerificationSource::DigestsFile(path.to_string_lossy().to_string()),
+        hashes,
+    };
+    Some((candidates, improper))
+}
+
+/// Reverse the escaping of backslashes and line breaks which coreutils applies to filenames
+fn unescape_filename(escaped: &str) -> Option<String> {
+    let mut filename = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        filename.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(filename)
+}
+
+/// Parse a line of a coreutils-style digests file such as SHA256SUMS
+fn parse_coreutils_line(l: &str) -> Option<(Algorithm, Vec<u8>, &str)> {
+    // Expected format
+    // <valid-hash><space><space-or-*><filename>
+    l.find(' ')
+        .and_then(|space_pos| {
+            // Char before filename should be space for text or * for binary
+            match l.chars().nth(space_pos + 1) {
+                Some(' ') | Some('*') => (l.get(..space_pos)).zip(l.get(space_pos + 2..)),
+                _ => None,
+            }
+        })
+        .and_then(|(maybe_hash, filename)| {
+            // Filename should be in this position without extra whitespace
+            if filename.trim() == filename {
+                try_parse_hash(maybe_hash).map(|(alg, bytes)| (alg, bytes, filename))
+            } else {
+                None
+            }
+        })
+}
+
+/// Parse a line in the BSD tagged format produced by `shasum --tag` and `openssl dgst`, such as
+/// `SHA256 (file.iso) = <hash>`. The algorithm named by the tag must match the hash length.
+fn parse_bsd_line(l: &str) -> Option<(Algorithm, Vec<u8>, &str)> {
+    let open = l.find('(')?;
+    let close = l.rfind(')')?;
+    let filename = l.get(open + 1..close)?;
+    let hash = l[close + 1..].trim_start().strip_prefix('=')?;
+    let alg = match l[..open].trim_end().to_ascii_uppercase().as_str() {
+        "MD5" => Algorithm::Md5,
+        "SHA1" | "SHA-1" => Algorithm::Sha1,
+        "SHA256" | "SHA-256" | "SHA2-256" => Algorithm::Sha256,
+        "SHA384" | "SHA-384" | "SHA2-384" => Algorithm::Sha384,
+        "SHA512" | "SHA-512" | "SHA2-512" => Algorithm::Sha512,
+        _ => return None,
+    };
+    let bytes = hex::decode(hash.trim()).ok()?;
+    if Algorithm::from_len(bytes.len()).ok()? != alg {
+        return None;
+    }
+    Some((alg, bytes, filename))
+}
+
+/// What to make of a hash which matches but is listed under a different filename to the input
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NamePolicy {
+    /// Give a Maybe result and warn about the filename
+    #[default]
+    Maybe,
+    /// Give an Ok result, only noting the filename, as after the download was renamed
+    Ignore,
+    /// Give a Fail result, as surely as if the hash had not matched
+    Fail,
+}
+
+impl NamePolicy {
+    /// The result when the hash matches but the filename does not
+    fn mismatch_level(self) -> MatchLevel {
+        match self {
+            NamePolicy::Maybe => MatchLevel::Maybe,
+            NamePolicy::Ignore => MatchLevel::Ok,
+            NamePolicy::Fail => MatchLevel::Fail,
+        }
+    }
 }
 
 /// Determine if the calculated hash matches any of the candidates.
 ///
 /// Ok result: the hash matches, and if the candidate has a filename, that matches too
-/// Maybe result: the hash matches but the filename does not
+/// Maybe result: the hash matches but the filename does not. `names` can make this Ok or Fail
+/// instead.
 /// Fail result: neither of the above
-pub fn verify_hash<'a>(calculated: &Hash, candidates: &'a CandidateHashes) -> Verification<'a> {
+///
+/// If the calculated hash is an HMAC the comparison is performed in constant time.
+pub fn verify_hash<'a>(
+    calculated: &Hash,
+    candidates: &'a CandidateHashes,
+    names: NamePolicy,
+) -> Verification<'a> {
+    if candidates.alg == Algorithm::Ssdeep {
+        return verify_similarity(calculated, candidates);
+    }
     let mut ok: Option<&CandidateHash> = None;
     let mut maybe: Option<&CandidateHash> = None;
     let mut messages = Vec::new();
 
     for candidate in &candidates.hashes {
-        if candidate.bytes == calculated.bytes {
+        if digests_equal(calculated, candidate) {
             match candidate.filename {
                 None => ok = Some(candidate),
-                Some(ref candidate_filename) if candidate_filename == &calculated.filename => {
+                Some(ref candidate_filename)
+                    if filenames_match(candidate_filename, &calculated.filename) =>
+                {
+                    ok = Some(candidate)
+                }
+                Some(ref candidate_filename) if names == NamePolicy::Ignore => {
+                    messages.push((
+                        MessageLevel::Note,
+                        tr!("filename-mismatch", filename = candidate_filename),
+                    ));
                     ok = Some(candidate)
                 }
                 Some(ref candidate_filename) => {
                     messages.push((
                         MessageLevel::Warning,
-                        format!(
-                            "The matched hash has filename '{}', which does not match the input.",
-                            candidate_filename
-                        ),
+                        tr!("filename-mismatch", filename = candidate_filename),
                     ));
                     maybe = Some(candidate);
                 }
@@ -241,12 +1434,20 @@ pub fn verify_hash<'a>(calculated: &Hash, candidates: &'a CandidateHashes) -> Ve
         }
     }
 
-    // Warn that a "successful" MD5 result is not necessarily great
-    if candidates.alg == Algorithm::Md5 && (ok.is_some() || maybe.is_some()) {
-        messages.push((
-            MessageLevel::Note,
-            "MD5 can easily be forged. Use a stronger algorithm if possible.".to_owned(),
-        ))
+    // Warn that a "successful" result with a weak algorithm is not necessarily great, or fail it
+    // outright if that is the policy
+    if ok.or(maybe).is_some() {
+        if let Some((level, message)) = policy::weak_match_message(candidates.alg, calculated.keyed)
+        {
+            messages.push((level, message));
+            if matches!(level, MessageLevel::Error) {
+                return Verification {
+                    match_level: MatchLevel::Fail,
+                    comparison_hash: ok.or(maybe),
+                    messages,
+                };
+            }
+        }
     }
 
     // If we got a full match, great
@@ -261,7 +1462,7 @@ pub fn verify_hash<'a>(calculated: &Hash, candidates: &'a CandidateHashes) -> Ve
     // Second priority, a "maybe" result
     if maybe.is_some() {
         return Verification {
-            match_level: MatchLevel::Maybe,
+            match_level: names.mismatch_level(),
             comparison_hash: maybe,
             messages,
         };
@@ -280,6 +1481,148 @@ pub fn verify_hash<'a>(calculated: &Hash, candidates: &'a CandidateHashes) -> Ve
     }
 }
 
+/// Whether a calculated digest is the same as a candidate. HMACs are compared in constant time so
+/// that the time taken doesn't reveal how much of a forged value was correct.
+pub fn digests_equal(calculated: &Hash, candidate: &CandidateHash) -> bool {
+    if calculated.keyed {
+        bool::from(candidate.bytes.ct_eq(&calculated.bytes))
+    } else {
+        candidate.bytes == calculated.bytes
+    }
+}
+
+/// Check whether a filename listed with a candidate hash refers to the input. Digests files and
+/// lists of input files sometimes give relative paths with a leading `./`.
+fn filenames_match(candidate_filename: &str, input_filename: &str) -> bool {
+    let candidate_filename = candidate_filename
+        .strip_prefix("./")
+        .unwrap_or(candidate_filename);
+    candidate_filename == input_filename.strip_prefix("./").unwrap_or(input_filename)
+}
+
+/// Compare a calculated fuzzy hash against the candidates, choosing the most similar.
+///
+/// Ok result: the fuzzy hashes are identical
+/// Similar result: the input is partially similar to a candidate
+/// Fail result: the input is not similar to any candidate
+fn verify_similarity<'a>(calculated: &Hash, candidates: &'a CandidateHashes) -> Verification<'a> {
+    let calculated_str = String::from_utf8_lossy(&calculated.bytes);
+    let best = candidates
+        .hashes
+        .iter()
+        .filter_map(|c| {
+            fuzzy::compare(&calculated_str, &String::from_utf8_lossy(&c.bytes)).map(|s| (s, c))
+        })
+        .max_by_key(|(score, _)| *score);
+    let mut messages = vec![];
+    let (match_level, comparison_hash) = match best {
+        Some((100, c)) => {
+            messages.push((MessageLevel::Note, tr!("fuzzy-similarity")));
+            (MatchLevel::Ok, Some(c))
+        }
+        Some((0, _)) | None => (MatchLevel::Fail, None),
+        Some((score, c)) => (MatchLevel::Similar(score), Some(c)),
+    };
+    Verification {
+        match_level,
+        comparison_hash,
+        messages,
+    }
+}
+
+/// Determine if the calculated piece hashes match those listed in a torrent.
+///
+/// Ok result: all pieces match, and the torrent's filename matches the input
+/// Maybe result: all pieces match but the filename does not, depending on `names`
+/// Fail result: any piece is missing, extra or does not match
+pub fn verify_pieces<'a>(
+    calculated: &Hash,
+    candidates: &'a CandidateHashes,
+    names: NamePolicy,
+) -> Verification<'a> {
+    let mut messages = Vec::new();
+    let calculated_pieces: Vec<&[u8]> = calculated.bytes.chunks(20).collect();
+    let expected = candidates.hashes.len();
+
+    let failed: Vec<usize> = candidates
+        .hashes
+        .iter()
+        .zip(&calculated_pieces)
+        .enumerate()
+        .filter(|(_, (candidate, piece))| candidate.bytes != **piece)
+        .map(|(i, _)| i)
+        .collect();
+    if !failed.is_empty() {
+        messages.push((
+            MessageLevel::Error,
+            tr!(
+                "pieces-failed",
+                failed = failed.len(),
+                total = expected,
+                pieces = format_ranges(&failed)
+            ),
+        ));
+    }
+    if calculated_pieces.len() < expected {
+        messages.push((
+            MessageLevel::Error,
+            tr!(
+                "pieces-missing",
+                missing = expected - calculated_pieces.len(),
+                total = expected
+            ),
+        ));
+    } else if calculated_pieces.len() > expected {
+        messages.push((MessageLevel::Error, tr!("pieces-extra")));
+    }
+
+    let match_level = if !messages.is_empty() {
+        MatchLevel::Fail
+    } else {
+        match &candidates.hashes[0].filename {
+            Some(name) if name != &calculated.filename && names == NamePolicy::Ignore => {
+                messages.push((
+                    MessageLevel::Note,
+                    tr!("torrent-filename-mismatch", filename = name),
+                ));
+                MatchLevel::Ok
+            }
+            Some(name) if name != &calculated.filename => {
+                messages.push((
+                    MessageLevel::Warning,
+                    tr!("torrent-filename-mismatch", filename = name),
+                ));
+                names.mismatch_level()
+            }
+            _ => MatchLevel::Ok,
+        }
+    };
+    Verification {
+        match_level,
+        comparison_hash: None,
+        messages,
+    }
+}
+
+/// Summarise a sorted list of indices as ranges, such as "0-3, 7, 9-10"
+fn format_ranges(indices: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &i in indices {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == i => *end = i,
+            _ => ranges.push((i, i)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -328,7 +1671,7 @@ mod tests {
         ));
 
         for i in &[invalid1, invalid2, invalid3, invalid4, invalid5] {
-            assert!(read_raw_candidate_from_file(*i, example_path).is_none());
+            assert!(read_raw_candidate_from_file(i, example_path).is_none());
         }
     }
 
@@ -340,7 +1683,7 @@ mod tests {
         fe6c26d485a3573a1cb0ad0682f5105325a1905f  shasums";
         let lines = shasums.lines().map(std::io::Result::Ok);
         let path = Path::new("SHASUMS");
-        let candidates = read_coreutils_digests_from_file(lines, path);
+        let candidates = read_coreutils_digests_from_file(lines, path).map(|(c, _)| c);
 
         assert_eq!(
             candidates,
@@ -350,14 +1693,17 @@ mod tests {
                     CandidateHash {
                         bytes: hex::decode("4b91f7a387a6edd4a7c0afb2897f1ca968c9695b").unwrap(),
                         filename: Some("cp".to_owned()),
+                        size: None,
                     },
                     CandidateHash {
                         bytes: hex::decode("75eb7420a9f5a260b04a3e8ad51e50f2838a17fc").unwrap(),
                         filename: Some("lel.txt".to_owned()),
+                        size: None,
                     },
                     CandidateHash {
                         bytes: hex::decode("fe6c26d485a3573a1cb0ad0682f5105325a1905f").unwrap(),
                         filename: Some("shasums".to_owned()),
+                        size: None,
                     }
                 ],
                 source: VerificationSource::DigestsFile(path.to_string_lossy().to_string()),
@@ -365,6 +1711,349 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_skip_improper_lines() {
+        let shasums = "# Checksums for release 1.0
+        4b91f7a387a6edd4a7c0afb2897f1ca968c9695b  cp
+        not a digest";
+        let lines = shasums.lines().map(std::io::Result::Ok);
+        let (candidates, improper) =
+            read_coreutils_digests_from_file(lines, Path::new("SHASUMS")).unwrap();
+        assert_eq!(candidates.hashes.len(), 1);
+        assert_eq!(improper, 2);
+    }
+
+    #[test]
+    fn test_find_hex_hashes() {
+        let text =
+            "SHA-256 checksum: 87428FC522803D31065E7BCE3CF03FE475096631E5E07BBD7A0FDE60C4CF25C7 \
+            (MD5: 9eb84090956c484e32cb6c08455a667b, signed by 1234abcd)";
+        let (alg, hashes) = find_hex_hashes(text).unwrap();
+        assert_eq!(alg, Algorithm::Sha256);
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(hashes[0][..2], [0x87, 0x42]);
+        assert_eq!(find_hex_hashes("Download (1234abcd, 28 MB)"), None);
+    }
+
+    #[test]
+    fn test_read_release() {
+        let release = "Origin: Debian
+Date: Sat, 10 Aug 2024 09:32:01 UTC
+MD5Sum:
+ 0ed6d4c8891eb86358b94bb35d9e4da4  1484322 contrib/Contents-all
+SHA256:
+ 87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf25c7    31243 main/binary-amd64/Packages
+ 0123 5 main/binary-amd64/Packages.xz
+Acquire-By-Hash: yes";
+        let lines: Vec<String> = release.lines().map(str::to_owned).collect();
+        let (candidates, improper) = read_release_from_file(&lines, Path::new("Release")).unwrap();
+        assert_eq!(candidates.alg, Algorithm::Sha256);
+        assert_eq!(candidates.hashes.len(), 1);
+        assert_eq!(
+            candidates.hashes[0].filename.as_deref(),
+            Some("main/binary-amd64/Packages")
+        );
+        assert_eq!(candidates.hashes[0].size, Some(31243));
+        assert_eq!(improper, 1);
+    }
+
+    #[test]
+    fn test_read_hashdeep() {
+        let audit = "%%%% HASHDEEP-1.0
+%%%% size,md5,sha256,filename
+## Invoked from: /home/user
+## $ hashdeep -r evidence
+##
+2,60b725f10c9c85c70d97880dfe8191b3,87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf25c7,evidence/a,b.txt
+2,60b725f10c9c85c70d97880dfe8191b3,truncated,evidence/c.txt";
+        let lines: Vec<String> = audit.lines().map(str::to_owned).collect();
+        let (candidates, improper) = read_hashdeep_from_file(&lines, Path::new("audit")).unwrap();
+        assert_eq!(candidates.alg, Algorithm::Sha256);
+        assert_eq!(candidates.hashes.len(), 1);
+        assert_eq!(
+            candidates.hashes[0].filename.as_deref(),
+            Some("evidence/a,b.txt")
+        );
+        assert_eq!(candidates.hashes[0].size, Some(2));
+        assert_eq!(improper, 1);
+    }
+
+    #[test]
+    fn test_read_mhl() {
+        let mhl = br#"<hashlist version="1.1">
+  <hash><file>a.mov</file><size>2</size><xxhash64be>44bc2cf5ad770999</xxhash64be>
+    <md5>60b725f10c9c85c70d97880dfe8191b3</md5></hash>
+  <hash><file>b.mov</file><xxhash64be>44bc2cf5ad770999</xxhash64be></hash>
+</hashlist>"#;
+        let (candidates, improper) = read_mhl_candidates(mhl, Path::new("a.mhl")).unwrap();
+        assert_eq!(candidates.alg, Algorithm::Md5);
+        assert_eq!(candidates.hashes.len(), 1);
+        assert_eq!(candidates.hashes[0].filename.as_deref(), Some("a.mov"));
+        assert_eq!(candidates.hashes[0].size, Some(2));
+        assert_eq!(improper, 1);
+    }
+
+    #[test]
+    fn test_read_manifest() {
+        let json = br#"[
+            {"name": "a.txt", "algorithm": "SHA-1", "digest": "4b91f7a387a6edd4a7c0afb2897f1ca968c9695b"},
+            {"name": "b.txt", "digest": "sha1:75eb7420a9f5a260b04a3e8ad51e50f2838a17fc"},
+            {"name": "c.txt", "algorithm": "md5", "digest": "75eb7420a9f5a260b04a3e8ad51e50f2838a17fc"}
+        ]"#;
+        let (candidates, improper) =
+            read_manifest_candidates(json, Path::new("manifest.json")).unwrap();
+        assert_eq!(candidates.alg, Algorithm::Sha1);
+        assert_eq!(candidates.hashes.len(), 2);
+        assert_eq!(candidates.hashes[1].filename.as_deref(), Some("b.txt"));
+        assert_eq!(improper, 1);
+    }
+
+    #[test]
+    fn test_describe_truncated() {
+        assert_eq!(
+            describe_truncated("87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf"),
+            Some("Provided hash looks like a SHA-256 hash truncated by 4 hex chars".to_owned())
+        );
+        assert_eq!(describe_truncated("60b725f10c9c85c70d97880dfe8191b3"), None);
+        assert_eq!(describe_truncated("not a hash"), None);
+    }
+
+    #[test]
+    fn test_get_from_name() {
+        let name = "0123456789abcdef/snapshot_sha1_4b91f7a387a6edd4a7c0afb2897f1ca968c9695b.img";
+        let candidates = get_from_name(name).unwrap();
+        assert_eq!(candidates.alg, Algorithm::Sha1);
+        assert_eq!(candidates.hashes.len(), 1);
+        assert!(get_from_name("0123456789abcdef0123456789abcdef/package-1.2.tar.gz").is_err());
+    }
+
+    #[test]
+    fn test_parse_loose_text() {
+        let copied = "Checksums for this release:
+        4b91f7a387a6edd4a7c0afb2897f1ca968c9695b  a.txt
+        75eb7420a9f5a260b04a3e8ad51e50f2838a17fc  b.txt";
+        let candidates = parse_loose_text(copied, VerificationSource::Clipboard).unwrap();
+        assert_eq!(candidates.alg, Algorithm::Sha1);
+        assert_eq!(candidates.source, VerificationSource::Clipboard);
+        assert_eq!(candidates.hashes[1].filename.as_deref(), Some("b.txt"));
+
+        let candidates = parse_loose_text(
+            "SHA-1: 4b91f7a387a6edd4a7c0afb2897f1ca968c9695b",
+            VerificationSource::Clipboard,
+        )
+        .unwrap();
+        assert_eq!(candidates.hashes[0].filename, None);
+        let fingerprint = "4B:91:F7:A3:87:A6:ED:D4:A7:C0:AF:B2:89:7F:1C:A9:68:C9:69:5B\n";
+        assert_eq!(
+            parse_loose_text(fingerprint, VerificationSource::Clipboard)
+                .unwrap()
+                .alg,
+            Algorithm::Sha1
+        );
+    }
+
+    #[test]
+    fn test_normalise_text() {
+        let windows =
+            b"\xef\xbb\xbf4b91f7a387a6edd4a7c0afb2897f1ca968c9695b  a.txt\r\nb\rc\r\n".to_vec();
+        assert_eq!(
+            normalise_text(windows),
+            b"4b91f7a387a6edd4a7c0afb2897f1ca968c9695b  a.txt\nb\rc\n"
+        );
+    }
+
+    #[test]
+    fn test_escaped_filenames() {
+        let shasums = "\\4b91f7a387a6edd4a7c0afb2897f1ca968c9695b  two\\nlines\\\\.txt
+        \\SHA1 (back\\\\slash) = 75eb7420a9f5a260b04a3e8ad51e50f2838a17fc
+        \\fe6c26d485a3573a1cb0ad0682f5105325a1905f  bad\\escape";
+        let lines = shasums.lines().map(std::io::Result::Ok);
+        let (candidates, improper) =
+            read_coreutils_digests_from_file(lines, Path::new("SHASUMS")).unwrap();
+        assert_eq!(
+            candidates.hashes[0].filename.as_deref(),
+            Some("two\nlines\\.txt")
+        );
+        assert_eq!(improper, 2);
+
+        let lines = shasums.lines().map(std::io::Result::Ok);
+        let (candidates, _) = read_bsd_digests_from_file(lines, Path::new("SHASUMS")).unwrap();
+        assert_eq!(
+            candidates.hashes[0].filename.as_deref(),
+            Some("back\\slash")
+        );
+    }
+
+    #[test]
+    fn test_read_bsd_digests() {
+        let digests =
+            "SHA256 (ubuntu.iso) = 1eb85fc97224598dad1852b5d6483bbcf0aa8608790dcc657a5a2a761ae9c8c6
+        SHA2-256(file (1).txt)= 0263829989b6fd954f72baaf2fc64bc2e2f01d692d4de72986ea808f6e99813f";
+        let lines = digests.lines().map(std::io::Result::Ok);
+        let (candidates, _) = read_bsd_digests_from_file(lines, Path::new("SUMS")).unwrap();
+        assert_eq!(candidates.alg, Algorithm::Sha256);
+        let filenames: Vec<_> = candidates
+            .hashes
+            .iter()
+            .map(|h| h.filename.as_deref())
+            .collect();
+        assert_eq!(filenames, [Some("ubuntu.iso"), Some("file (1).txt")]);
+
+        // The tag must agree with the length of the hash
+        assert!(parse_bsd_line("MD5 (a) = 4b91f7a387a6edd4a7c0afb2897f1ca968c9695b").is_none());
+        assert!(parse_bsd_line("SHA512 (a) = d229da563da18fe5d58cd95a6467d584").is_none());
+        assert!(parse_bsd_line("SHA1024 (a) = d229da563da18fe5d58cd95a6467d584").is_none());
+
+        // A line which isn't text fails the whole file rather than being left out
+        let digests = b"SHA1 (a) = 4b91f7a387a6edd4a7c0afb2897f1ca968c9695b\nSHA1 (\xff) = 75eb7420a9f5a260b04a3e8ad51e50f2838a17fc\n";
+        let result = parse_check_data(
+            digests.to_vec(),
+            Path::new("SUMS"),
+            CheckFileOptions::default(),
+            &mut vec![],
+        );
+        assert!(matches!(result, Err(HashgoodError::Io { .. })));
+    }
+
+    #[test]
+    fn test_parse_separated_hex() {
+        let sha1 = "4b91f7a387a6edd4a7c0afb2897f1ca968c9695b";
+        for separated in [
+            "4B:91:F7:A3:87:A6:ED:D4:A7:C0:AF:B2:89:7F:1C:A9:68:C9:69:5B",
+            "4b 91 f7 a3 87 a6 ed d4 a7 c0 af b2 89 7f 1c a9 68 c9 69 5b",
+            "4b91 f7a3 87a6 edd4 a7c0 afb2 897f 1ca9 68c9 695b",
+        ] {
+            let (alg, bytes) = try_parse_hash(separated).unwrap();
+            assert_eq!(alg, Algorithm::Sha1);
+            assert_eq!(hex::encode(bytes), sha1);
+        }
+        assert_eq!(decode_separated_hex("4b91  f7a3"), None);
+        assert_eq!(
+            decode_separated_hex("60b725f10c9c85c70d97880dfe8191b3  deadbeef"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_nix() {
+        let empty_sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        for nix in [
+            "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73",
+            "sha256:0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73",
+            "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+        ] {
+            let (alg, bytes) = try_parse_hash(nix).unwrap();
+            assert_eq!(alg, Algorithm::Sha256);
+            assert_eq!(hex::encode(bytes), empty_sha256);
+        }
+        assert_eq!(try_parse_prefixed(&format!("sha1:{}", empty_sha256)), None);
+
+        // An MD5 with a typo is the length of a Nix SHA-1, so that needs its prefix
+        let mistyped = "d229da563da18fa5d58cd95a6467d5g4";
+        assert_eq!(try_parse_hash(mistyped), None);
+        assert!(parse_hash(mistyped).is_err());
+        let (alg, _) = try_parse_hash(&format!("sha1:{}", mistyped)).unwrap();
+        assert_eq!(alg, Algorithm::Sha1);
+        assert_eq!(
+            decode_nix_base32("zmdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_base64() {
+        let md5 = hex::decode("d229da563da18fe5d58cd95a6467d584").unwrap();
+        assert_eq!(
+            try_parse_hash("0inaVj2hj+XVjNlaZGfVhA=="),
+            Some((Algorithm::Md5, md5.clone()))
+        );
+        assert_eq!(
+            try_parse_hash("0inaVj2hj-XVjNlaZGfVhA"),
+            Some((Algorithm::Md5, md5))
+        );
+        assert_eq!(try_parse_hash("0inaVj2hj+XVjNlaZGfV"), None);
+
+        // SHA-384 as base64 is as long as SHA-256 as hex
+        let sha384 = "oqVuAfXRKap7fdgcCY5uykM6-R9GqQ8K_uxy9rx7HNQlGYl1kPzQho1wx4JwY8wC";
+        assert_eq!(try_parse_hash(sha384).unwrap().0, Algorithm::Sha384);
+        // A SHA-256 with a typo must not be mistaken for it
+        let mistyped = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852g855";
+        assert_eq!(try_parse_hash(mistyped), None);
+        assert!(parse_hash(mistyped).is_err());
+        assert!(parse_hash(&mistyped.to_uppercase()).is_err());
+    }
+
+    #[test]
+    fn test_parse_sri() {
+        let sri = "sha384-oqVuAfXRKap7fdgcCY5uykM6+R9GqQ8K/uxy9rx7HNQlGYl1kPzQho1wx4JwY8wC";
+        let (alg, bytes) = try_parse_sri(sri).unwrap();
+        assert_eq!(alg, Algorithm::Sha384);
+        assert_eq!(
+            hex::encode(bytes),
+            "a2a56e01f5d129aa7b7dd81c098e6eca433af91f46a90f0afeec72f6bc7b1cd42519897590fcd0868d7\
+            0c7827063cc02"
+        );
+        let both = format!(
+            "sha256-n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg= {}",
+            sri
+        );
+        assert_eq!(try_parse_sri(&both).unwrap().0, Algorithm::Sha384);
+        assert!(try_parse_sri("sha256-oqVuAfXRKap7fdgcCY5uykM6+R9GqQ8K").is_none());
+        assert!(try_parse_sri("md5-1B2M2Y8AsgTpgAmY7PhCfg==").is_none());
+    }
+
+    #[test]
+    fn test_filenames_match() {
+        assert!(filenames_match("file.iso", "file.iso"));
+        assert!(filenames_match("./sub/file.iso", "sub/file.iso"));
+        assert!(filenames_match("sub/file.iso", "./sub/file.iso"));
+        assert!(!filenames_match("other.iso", "file.iso"));
+    }
+
+    #[test]
+    fn test_name_policy() {
+        let candidate = |bytes: Vec<u8>| CandidateHash {
+            bytes,
+            filename: Some("file.iso".to_owned()),
+            size: None,
+        };
+        let candidates = CandidateHashes {
+            alg: Algorithm::Sha256,
+            hashes: vec![candidate(vec![0xab; 32])],
+            source: VerificationSource::CommandArgument,
+        };
+        let torrent = CandidateHashes {
+            alg: Algorithm::Sha1,
+            hashes: vec![candidate(vec![0xab; 20])],
+            source: VerificationSource::TorrentFile {
+                path: "file.torrent".to_owned(),
+                piece_length: 16384,
+            },
+        };
+        let hash = |filename, byte, len| Hash::new(Algorithm::Sha256, vec![byte; len], filename);
+        for (names, renamed) in [
+            (NamePolicy::Maybe, MatchLevel::Maybe),
+            (NamePolicy::Ignore, MatchLevel::Ok),
+            (NamePolicy::Fail, MatchLevel::Fail),
+        ] {
+            let level = |hash| verify_hash(&hash, &candidates, names).match_level;
+            assert_eq!(level(hash("file.iso", 0xab, 32)), MatchLevel::Ok);
+            assert_eq!(level(hash("renamed.iso", 0xab, 32)), renamed);
+            assert_eq!(level(hash("file.iso", 0xcd, 32)), MatchLevel::Fail);
+
+            let level = |hash| verify_pieces(&hash, &torrent, names).match_level;
+            assert_eq!(level(hash("file.iso", 0xab, 20)), MatchLevel::Ok);
+            assert_eq!(level(hash("renamed.iso", 0xab, 20)), renamed);
+            assert_eq!(level(hash("file.iso", 0xcd, 20)), MatchLevel::Fail);
+        }
+    }
+
+    #[test]
+    fn test_format_ranges() {
+        assert_eq!(format_ranges(&[0, 1, 2, 3, 7, 9, 10]), "0-3, 7, 9-10");
+        assert_eq!(format_ranges(&[5]), "5");
+    }
+
     #[test]
     fn test_invalid_shasums() {
         let no_format = "4b91f7a387a6edd4a7c0afb2897f1ca968c9695b cp";
@@ -380,4 +2069,40 @@ mod tests {
             );
         }
     }
+
+    struct VaultSource;
+
+    impl CandidateSource for VaultSource {
+        fn name(&self) -> &str {
+            "a test vault"
+        }
+
+        fn candidates(
+            &self,
+            context: &SourceContext,
+            _messages: &mut Vec<(MessageLevel, String)>,
+        ) -> Option<Result<CandidateHashes, HashgoodError>> {
+            use md5::Digest;
+            let secret = context.hash_file?.to_str()?.strip_prefix("vault:")?;
+            Some(parse_hash(&hex::encode(md5::Md5::digest(secret))))
+        }
+    }
+
+    #[test]
+    fn registered_source() {
+        use structopt::StructOpt;
+        register_candidate_source(Box::new(VaultSource));
+        let mut messages = vec![];
+
+        let opt = Opt::from_iter(["hashgood", "file", "-c", "vault:secret"]);
+        let candidates = get_candidate_hashes(&opt, &mut messages).unwrap().unwrap();
+        assert_eq!(candidates.alg, Algorithm::Md5);
+
+        let opt = Opt::from_iter(["hashgood", "file", "d229da563da18fe5d58cd95a6467d584"]);
+        let candidates = get_candidate_hashes(&opt, &mut messages).unwrap().unwrap();
+        assert_eq!(
+            candidates.hashes[0].bytes,
+            hex::decode("d229da563da18fe5d58cd95a6467d584").unwrap()
+        );
+    }
 }
@@ -0,0 +1,75 @@
+use super::error::HashgoodError;
+use super::input::Input;
+
+/// The type of a function which hashes an input again after it has changed
+pub type ChangeFn<'f> = dyn FnMut(&Input) -> Result<(), HashgoodError> + 'f;
+
+/// Watch the inputs for changes until the program is interrupted, calling the function for each
+/// input which changed. Writes usually come in bursts, such as while a download is still
+/// completing, so an input is only hashed again once it has been left alone for a moment.
+#[cfg(feature = "watch")]
+pub fn watch_inputs(inputs: &[Input], on_change: &mut ChangeFn) -> Result<(), HashgoodError> {
+    use super::input::as_url;
+    use notify::event::{AccessKind, AccessMode, ModifyKind};
+    use notify::{EventKind, RecursiveMode, Watcher};
+    use std::path::PathBuf;
+    use std::sync::mpsc::{channel, RecvTimeoutError};
+    use std::time::Duration;
+
+    const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+    // Watch the containing directories rather than the files themselves so that inputs which are
+    // replaced or created from scratch are noticed too
+    let mut paths = vec![];
+    for input in inputs {
+        if input.path.to_str() == Some("-") || as_url(&input.path).is_some() {
+            return Err("Only files on disk can be watched for changes".into());
+        }
+        paths.push(std::path::absolute(&input.path)?);
+    }
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for path in &paths {
+        let dir = path.parent().unwrap_or(path);
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    eprintln!("Watching for changes. Press Ctrl-C to stop.");
+
+    let mut changed: Vec<PathBuf> = vec![];
+    loop {
+        let event = match rx.recv_timeout(SETTLE_TIME) {
+            Ok(event) => event?,
+            Err(RecvTimeoutError::Timeout) => {
+                for (input, path) in inputs.iter().zip(&paths) {
+                    if changed.contains(path) {
+                        // A problem such as the file being deleted shouldn't stop us watching
+                        if let Err(e) = on_change(input) {
+                            eprintln!("Error: {}", e);
+                        }
+                    }
+                }
+                changed.clear();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        // Reading the input to hash it generates events too, which must not trigger another hash
+        let content_changed = match event.kind {
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+            EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_)) => false,
+            _ => true,
+        };
+        if content_changed {
+            for path in event.paths {
+                if paths.contains(&path) && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+pub fn watch_inputs(_inputs: &[Input], _on_change: &mut ChangeFn) -> Result<(), HashgoodError> {
+    Err("Watching is not supported by this build of hashgood (requires the watch feature)".into())
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+
+/// Read an extended attribute of a file. Returns None if the file doesn't have that attribute.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub fn get_xattr(path: &Path, name: &str) -> Result<Option<Vec<u8>>, String> {
+    use std::ffi::CString;
+    use std::io::Error;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let c_name = CString::new(name).map_err(|e| e.to_string())?;
+    let read = |buf: &mut [u8]| {
+        // SAFETY: both strings are NUL-terminated and the buffer is valid for its length
+        let len = unsafe {
+            #[cfg(target_os = "macos")]
+            {
+                libc::getxattr(
+                    c_path.as_ptr(),
+                    c_name.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                    0,
+                )
+            }
+            #[cfg(not(target_os = "macos"))]
+            {
+                libc::getxattr(
+                    c_path.as_ptr(),
+                    c_name.as_ptr(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            }
+        };
+        match len {
+            len if len >= 0 => Ok(Some(len as usize)),
+            _ => {
+                let err = Error::last_os_error();
+                #[cfg(target_os = "macos")]
+                let missing = libc::ENOATTR;
+                #[cfg(not(target_os = "macos"))]
+                let missing = libc::ENODATA;
+                match err.raw_os_error() {
+                    Some(code) if code == missing => Ok(None),
+                    _ => Err(format!(
+                        "Unable to read attribute '{}' of '{}': {}",
+                        name,
+                        path.to_string_lossy(),
+                        err
+                    )),
+                }
+            }
+        }
+    };
+    // Ask for the size first. A checksum is small so it is unlikely to change in between.
+    let len = match read(&mut [])? {
+        Some(len) => len,
+        None => return Ok(None),
+    };
+    let mut value = vec![0; len];
+    Ok(read(&mut value)?.map(|len| {
+        value.truncate(len);
+        value
+    }))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub fn get_xattr(_path: &Path, _name: &str) -> Result<Option<Vec<u8>>, String> {
+    Err("Extended attributes are not supported on this platform".to_owned())
+}